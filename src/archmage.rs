@@ -1,29 +1,79 @@
-use crate::command::{ArchmageCommand, CommandDispatcher};
+use crate::command::error::reply_to_component;
+use crate::command::invocation::{Invocation, Reply};
+use crate::command::monitor::Monitors;
+use crate::command::prefix::{self, DEFAULT_PREFIX};
+use crate::command::{sync::definitions_match, ArchmageCommand, CommandDispatcher};
+use crate::database::{ErrorRecord, MageDB};
+use crate::shutdown::{self, InFlight};
+use crate::config::UnlistedGuilds;
 use crate::Config;
 use eyre::{bail, eyre, Result};
+use songbird::{SerenityInit, Songbird};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use serde::Serialize;
-use serenity::all::{CreateEmbed, CreateInteractionResponseMessage};
+use serenity::all::CreateEmbed;
+use serenity::gateway::{GatewayError, ShardManager};
+use serenity::http::{CacheHttp, Http, HttpError};
 use serenity::async_trait;
 use serenity::model::prelude::Ready;
 use serenity::{model::prelude::*, prelude::*};
 use tracing::{event, span, Level};
 
+/// Intents Archmage always needs, to know which guilds it's in.
+const BASE_INTENTS: GatewayIntents = GatewayIntents::GUILDS;
+/// Intents needed for chat commands, which need to read messages.
+const CHAT_COMMAND_INTENTS: GatewayIntents = GatewayIntents::GUILD_MESSAGES
+    .union(GatewayIntents::MESSAGE_CONTENT);
+
+/// How long shutdown waits for commands that are already running.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
+/// How long shutdown spends redrawing monitors whose redraws were put off.
+const MONITOR_GRACE: Duration = Duration::from_secs(10);
+
 #[non_exhaustive]
 pub struct Archmage {
-    config: Config,
+    /// Swapped out wholesale when the configuration is reloaded.
+    config: RwLock<Arc<Config>>,
+    /// Where the configuration is reloaded from. None means the default path.
+    config_path: Option<PathBuf>,
     commands: CommandDispatcher,
+    db: MageDB,
+    /// The gateway intents Discord granted, as bits.
+    intents: AtomicU64,
+    /// Events being handled, so that shutdown can wait for them.
+    in_flight: InFlight,
+    /// Every voice connection.
+    voice: Arc<Songbird>,
+    /// Monitors waiting to be redrawn.
+    monitors: Monitors,
 }
 
 impl Archmage {
-    /// Create a new *Archmage* instance with a given configuration.
+    /// Create a new *Archmage* instance with a given configuration and database.
     /// Typically you want to start the bot by using [Archmage::start]
-    pub fn new(config: Config) -> Self {
+    pub fn new(config: Config, db: MageDB) -> Self {
         Self {
-            config,
+            config: RwLock::new(Arc::new(config)),
+            config_path: None,
             commands: CommandDispatcher::new(),
+            db,
+            intents: AtomicU64::new(0),
+            in_flight: InFlight::default(),
+            voice: Songbird::serenity(),
+            monitors: Monitors::default(),
         }
     }
 
+    /// Where the configuration was loaded from, so that it can be reloaded
+    /// from the same place. See [Archmage::reload_config].
+    pub fn with_config_path(mut self, path: Option<PathBuf>) -> Self {
+        self.config_path = path;
+        self
+    }
+
     /// Add new commands to the bot. This method accepts any 
     /// [crate::command::ArchmageCommand] as its type argument, which may contain
     /// one or more Archmage commands. For more information on defining new
@@ -45,20 +95,170 @@ impl Archmage {
         self.commands.register::<T>()
     }
 
+    /// Every command this instance knows how to run.
+    pub fn commands(&self) -> &CommandDispatcher {
+        &self.commands
+    }
+
+    /// The database backing this instance.
+    pub fn db(&self) -> &MageDB {
+        &self.db
+    }
+
+    /// The messages kept up to date in campaigns' monitor channels.
+    pub fn monitors(&self) -> &Monitors {
+        &self.monitors
+    }
+
+    /// The gateway intents Archmage is connected with. Features needing any
+    /// others are unavailable.
+    pub fn intents(&self) -> GatewayIntents {
+        GatewayIntents::from_bits_truncate(self.intents.load(Ordering::Relaxed))
+    }
+
+    /// The current configuration. This is a snapshot: if the configuration
+    /// is reloaded, the snapshot doesn't change.
+    pub fn config(&self) -> Arc<Config> {
+        self.config
+            .read()
+            .expect("Configuration lock poisoned")
+            .clone()
+    }
+
+    /// Whether a user may use owner-only commands: they're listed in the
+    /// configuration, own the application, or are on the team that does.
+    pub async fn is_owner(&self, ctx: &Context, user: UserId) -> Result<bool> {
+        if self.config().owners.contains(&user.get()) {
+            return Ok(true);
+        }
+
+        let application = ctx
+            .http
+            .get_current_application_info()
+            .await
+            .map_err(|e| eyre!(e))?;
+        let owner = application.owner.is_some_and(|owner| owner.id == user);
+        let on_team = application
+            .team
+            .is_some_and(|team| team.members.iter().any(|member| member.user.id == user));
+        Ok(owner || on_team)
+    }
+
     /// Launch the bot, connect to Discord and listen for events.
+    ///
+    /// Only the gateway intents the registered commands need are requested,
+    /// unless the configuration says otherwise. If Discord refuses any
+    /// privileged intents, Archmage reconnects without them, and the features
+    /// that needed them are disabled.
+    ///
+    /// Runs until Archmage is stopped with Ctrl-C or `SIGTERM`, at which point
+    /// it shuts down cleanly; see [Archmage::shutdown].
     pub async fn start(self) -> Result<()> {
-        let config = self.config.clone();
-        let mut client = Client::builder(config.token, GatewayIntents::all())
-            .application_id(config.appid.into())
-            .event_handler(self)
-            // TODO: Set Voice Handler
-            .await
-            .expect("Could not build client!");
+        let config = self.config();
+        let mut intents = config
+            .intents()
+            .unwrap_or(BASE_INTENTS | CHAT_COMMAND_INTENTS | self.commands.intents());
+        let archmage = Arc::new(self);
+
+        loop {
+            archmage.intents.store(intents.bits(), Ordering::Relaxed);
+            let mut client = Client::builder(&config.token, intents)
+                .application_id(config.appid.into())
+                .event_handler_arc(archmage.clone())
+                .register_songbird_with(archmage.voice.clone())
+                .await
+                .expect("Could not build client!");
 
-        if let Err(why) = client.start().await {
-            bail!("An error occurred while running the client: {:?}", why);
+            #[cfg(unix)]
+            let reloader = archmage.reload_on_hangup(client.cache.clone(), client.http.clone())?;
+            let stopper = {
+                let archmage = archmage.clone();
+                let shard_manager = client.shard_manager.clone();
+                let http = client.http.clone();
+                tokio::spawn(async move {
+                    shutdown::signalled().await;
+                    archmage.shutdown(&shard_manager, &http).await;
+                })
+            };
+            let result = client.start().await;
+            #[cfg(unix)]
+            reloader.abort();
+            stopper.abort();
+
+            match result {
+                Ok(()) => return Ok(()),
+                Err(SerenityError::Gateway(GatewayError::DisallowedGatewayIntents))
+                    if intents.intersects(GatewayIntents::privileged()) =>
+                {
+                    let refused = intents & GatewayIntents::privileged();
+                    archmage.log_disabled_features(refused);
+                    intents -= refused;
+                }
+                Err(why) => bail!("An error occurred while running the client: {:?}", why),
+            }
+        }
+    }
+
+    /// Stop Archmage cleanly: turn away new events, give those already being
+    /// handled a chance to finish, redraw monitors they changed, leave every
+    /// voice channel, make sure the database is on disk, and finally
+    /// disconnect from Discord, which lets [Archmage::start] return.
+    pub async fn shutdown(&self, shard_manager: &ShardManager, http: &Http) {
+        event!(Level::INFO, "Shutting down");
+
+        let unfinished = self.in_flight.drain(SHUTDOWN_GRACE).await;
+        if unfinished > 0 {
+            event!(
+                Level::WARN,
+                "Gave up waiting for {} unfinished events after {:?}",
+                unfinished,
+                SHUTDOWN_GRACE
+            );
+        }
+
+        // Redraws are put off for a moment after each change, so some may
+        // still be waiting.
+        let flush = self.monitors.flush(&self.db, http);
+        if tokio::time::timeout(MONITOR_GRACE, flush).await.is_err() {
+            event!(
+                Level::WARN,
+                "Gave up redrawing monitors after {:?}",
+                MONITOR_GRACE
+            );
+        }
+
+        let calls: Vec<_> = self.voice.iter().map(|(guild, _)| guild).collect();
+        for guild in calls {
+            if let Err(e) = self.voice.remove(guild).await {
+                event!(Level::WARN, "Error leaving voice in guild '{}': {}", guild.0, e);
+            }
+        }
+
+        if let Err(e) = self.db.flush().await {
+            event!(Level::ERROR, "Error flushing the database: {}", e);
+        }
+
+        shard_manager.shutdown_all().await;
+        event!(Level::INFO, "Farewell!");
+    }
+
+    /// Explain what won't work without the intents Discord refused.
+    fn log_disabled_features(&self, refused: GatewayIntents) {
+        let names: Vec<_> = refused.iter_names().map(|(name, _)| name).collect();
+        event!(
+            Level::WARN,
+            "Discord refused the privileged intents {}. Enable them for the application in the Discord developer portal to use every feature",
+            names.join(", ")
+        );
+
+        if refused.intersects(CHAT_COMMAND_INTENTS) {
+            event!(Level::WARN, "Chat commands are disabled; only slash commands will work");
+        }
+        for registration in self.commands.registrations() {
+            if registration.required_intents().intersects(refused) {
+                event!(Level::WARN, "/{} is disabled", registration.name());
+            }
         }
-        Ok(())
     }
 }
 
@@ -71,14 +271,32 @@ impl Archmage {
 impl EventHandler for Archmage {
     /// Runs whenever a bot interaction is called for.
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        let Some(_guard) = self.in_flight.enter() else {
+            if let Interaction::Command(command) = interaction {
+                Self::turn_away(command, &ctx).await;
+            }
+            return;
+        };
+
         match interaction.clone() {
             Interaction::Command(command) => {
-                if let Err(e) = self.handle_command(&command, &ctx).await {
+                let invocation = Invocation::from_interaction(command);
+                if let Err(e) = self.handle_command(&invocation, &ctx).await {
+                    self.send_terminal_error_message(
+                        &invocation,
+                        e,
+                        &ctx,
+                        ErrorOrigin::Command(&invocation),
+                    ).await;
+                }
+            }
+            Interaction::Component(component) => {
+                if let Err(e) = self.handle_component(&component, &ctx).await {
                     self.send_terminal_error_message(
-                        command,
+                        &component,
                         e,
                         &ctx,
-                        interaction.message_component().map(|m| m.message),
+                        ErrorOrigin::Component(&component),
                     ).await;
                 }
             }
@@ -86,11 +304,81 @@ impl EventHandler for Archmage {
         };
     }
 
+    /// Runs whenever a message is sent in a channel the bot can see. Messages
+    /// starting with the guild's prefix are run as chat commands.
+    async fn message(&self, ctx: Context, message: Message) {
+        if message.author.bot {
+            return;
+        }
+        let Some(_guard) = self.in_flight.enter() else {
+            return;
+        };
+        let Some(guild_id) = message.guild_id else {
+            return;
+        };
+
+        let prefix = self.prefix_for(&guild_id).await;
+        let Some((name, args)) = prefix::split_command(&message.content, &prefix) else {
+            return;
+        };
+        // Other bots may share our prefix, so unknown commands are ignored.
+        let Some(registration) = self.commands.get(&name) else {
+            return;
+        };
+        // As are commands in guilds awaiting approval, to avoid spamming them.
+        if !self.guild_permitted(Some(guild_id)).await {
+            return;
+        }
+
+        let syntax = registration.chat_syntax();
+        match prefix::parse_options(registration.definition(), syntax, args) {
+            Ok(options) => {
+                let invocation = Invocation::from_message(message, name, options);
+                if let Err(e) = self.handle_command(&invocation, &ctx).await {
+                    self.send_terminal_error_message(
+                        &invocation,
+                        e,
+                        &ctx,
+                        ErrorOrigin::Command(&invocation),
+                    ).await;
+                }
+            }
+            Err(problem) => {
+                let response = message
+                    .channel_id
+                    .send_message(
+                        &ctx.http,
+                        serenity::all::CreateMessage::new()
+                            .reference_message(&message)
+                            .add_embed(
+                                CreateEmbed::new()
+                                    .color(Color::from_rgb(0xFF, 0xA5, 0x00))
+                                    .description(format!(
+                                        "{problem}\nUse `{prefix}help {name}` to see how to use this command."
+                                    ))
+                                    .title("I didn't understand that")
+                                    .timestamp(Timestamp::now()),
+                            ),
+                    )
+                    .await;
+                if let Err(e) = response {
+                    event!(
+                        Level::ERROR,
+                        error = &format!("{}", e).as_str(),
+                        "Error sending usage message to user channel"
+                    )
+                }
+            }
+        }
+    }
+
     /// Runs when the bot starts up and is connected to discord.
     async fn ready(&self, ctx: Context, event: Ready) {
         let span = span!(Level::INFO, "event ready");
         let _guard = span.enter();
 
+        self.sync_global_commands(&ctx).await;
+
         // Ensure only allowed guilds have the bot
         for guild in event.guilds {
             self.join_guild(&guild.id, &ctx).await
@@ -119,18 +407,37 @@ impl EventHandler for Archmage {
 impl Archmage {
     /// Called by any event that results in a connection to a guild.
     /// If the guild is allowed, registers guild commands.
-    /// If the guild is not allowed, leaves the guild.
-    async fn join_guild(&self, guild_id: &GuildId, ctx: &Context) {
-        if let Err(e) = self.leave_if_not_allowed(guild_id, ctx).await {
-            event!(
-                Level::WARN,
-                "Error leaving illegal Guild '{}': {}",
-                guild_id,
-                e
-            );
-        } else {
-            // Runs if the guild is allowed.
-            // Inform the guild of supported commands.
+    /// If the guild is not allowed, leaves the guild, or waits for an owner to
+    /// approve it, depending on the configuration.
+    pub async fn join_guild(&self, guild_id: &GuildId, ctx: &impl CacheHttp) {
+        let allowed = match self.is_guild_allowed(guild_id).await {
+            Ok(allowed) => allowed,
+            Err(e) => {
+                event!(
+                    Level::ERROR,
+                    "Error checking whether guild '{}' is allowed, ignoring it for now: {}",
+                    guild_id,
+                    e
+                );
+                return;
+            }
+        };
+
+        if !allowed {
+            if let Err(e) = self.handle_unlisted_guild(guild_id, ctx).await {
+                event!(
+                    Level::WARN,
+                    "Error handling unlisted Guild '{}': {}",
+                    guild_id,
+                    e
+                );
+            }
+            return;
+        }
+
+        // Inform the guild of supported commands, unless they're global.
+        let config = self.config();
+        if !config.global_commands {
             if let Err(e) = self.register_commands(guild_id, ctx).await {
                 event!(
                     Level::ERROR,
@@ -139,25 +446,138 @@ impl Archmage {
                     e
                 );
             }
-            event!(Level::INFO, "Joined Guild {}", guild_id);
+        } else if config.purge_stale_commands {
+            if let Err(e) = self.purge_guild_commands(guild_id, ctx).await {
+                event!(
+                    Level::ERROR,
+                    "Error purging commands for guild '{}': {}",
+                    guild_id,
+                    e
+                );
+            }
         }
+        event!(Level::INFO, "Joined Guild {}", guild_id);
     }
 
-    /// Leaves the provided guild if it's not in the allowed list.
-    /// Returns `Ok(())` whether or not the guild was in the allowed list, and
-    /// only returns `Err(Report)` when there was some upstream error.
-    async fn leave_if_not_allowed(&self, guild: &GuildId, ctx: &Context) -> Result<()> {
-        if !self.config.allowed_guilds.contains(&guild.get()) {
-            event!(Level::WARN, "Disconnecting from illegal guild: {}", guild);
-            if let Err(error) = guild.leave(&ctx).await {
-                bail!("Error leaving guild '{}': {}", guild.get(), error)
+    /// Whether Archmage may work in a guild, either because the configuration
+    /// file lists it or because an owner allowed it.
+    pub async fn is_guild_allowed(&self, guild: &GuildId) -> Result<bool> {
+        if self.config().allowed_guilds.contains(&guild.get()) {
+            return Ok(true);
+        }
+        self.db.is_guild_allowed(&guild.get()).await
+    }
+
+    /// Leaves a guild that isn't allowed or, in pending mode, records it for
+    /// an owner to approve and takes away its commands in the meantime.
+    async fn handle_unlisted_guild(&self, guild: &GuildId, ctx: &impl CacheHttp) -> Result<()> {
+        match self.config().unlisted_guilds {
+            UnlistedGuilds::Leave => {
+                event!(Level::WARN, "Disconnecting from illegal guild: {}", guild);
+                if let Err(error) = guild.leave(ctx.http()).await {
+                    bail!("Error leaving guild '{}': {}", guild.get(), error)
+                }
+            }
+            UnlistedGuilds::Pending => {
+                let name = ctx
+                    .cache()
+                    .and_then(|cache| guild.name(cache))
+                    .unwrap_or_else(|| "Unknown".to_owned());
+                self.db.mark_guild_pending(&guild.get(), &name).await?;
+                self.purge_guild_commands(guild, ctx).await?;
+                event!(
+                    Level::WARN,
+                    "Guild {} ({}) is awaiting approval. Use `/admin guilds allow {}` to allow it",
+                    name,
+                    guild,
+                    guild
+                );
             }
         }
         Ok(())
     }
 
-    /// Registers bot commands for the given build. Should be called once on guild join.
-    pub async fn register_commands(&self, guild: &GuildId, ctx: &Context) -> Result<()> {
+    /// Register or purge global commands, depending on the configuration.
+    async fn sync_global_commands(&self, ctx: &impl CacheHttp) {
+        let config = self.config();
+        if config.global_commands {
+            if let Err(e) = self.register_global_commands(ctx).await {
+                event!(Level::ERROR, "Error registering global commands: {}", e);
+            }
+        } else if config.purge_stale_commands {
+            if let Err(e) = self.purge_global_commands(ctx).await {
+                event!(Level::ERROR, "Error purging global commands: {}", e);
+            }
+        }
+    }
+
+    /// Reload the configuration from where it was first loaded, then bring
+    /// every guild in line with it. If the new configuration is invalid, the
+    /// old one is kept.
+    pub async fn reload_config(&self, ctx: &impl CacheHttp) {
+        let config = match Config::load(self.config_path.as_deref()) {
+            Ok(config) => config,
+            Err(e) => {
+                event!(Level::ERROR, "Not reloading configuration: {:?}", e);
+                return;
+            }
+        };
+
+        let old = self.config();
+        if config.token != old.token
+            || config.appid != old.appid
+            || config.database != old.database
+        {
+            event!(
+                Level::WARN,
+                "The token, application ID and database can't be changed without a restart"
+            );
+        }
+        *self.config.write().expect("Configuration lock poisoned") = Arc::new(config);
+        event!(Level::INFO, "Reloaded configuration");
+
+        self.sync_global_commands(ctx).await;
+        if let Some(cache) = ctx.cache() {
+            for guild in cache.guilds() {
+                self.join_guild(&guild, ctx).await;
+            }
+        }
+    }
+
+    /// Reload the configuration whenever Archmage receives `SIGHUP`, until
+    /// the returned task is aborted.
+    #[cfg(unix)]
+    fn reload_on_hangup(
+        self: &Arc<Self>,
+        cache: Arc<serenity::cache::Cache>,
+        http: Arc<Http>,
+    ) -> Result<tokio::task::JoinHandle<()>> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = signal(SignalKind::hangup())
+            .map_err(|e| eyre!(e).wrap_err("Unable to listen for SIGHUP"))?;
+        let archmage = self.clone();
+        Ok(tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                event!(Level::INFO, "Received SIGHUP, reloading configuration");
+                archmage.reload_config(&(&cache, http.as_ref())).await;
+            }
+        }))
+    }
+
+    /// Registers bot commands for the given guild. Should be called on guild join.
+    /// Discord is only updated if its definitions differ from ours, so this
+    /// is cheap to call on every reconnect.
+    pub async fn register_commands(&self, guild: &GuildId, ctx: &impl CacheHttp) -> Result<()> {
+        let existing = guild
+            .get_commands(ctx.http())
+            .await
+            .map_err(|e| eyre!(e).wrap_err(format!("Unable to get guild commands for guild '{guild}'!")))?;
+        if definitions_match(&existing, self.commands.get_all_defs()) {
+            event!(Level::DEBUG, "Commands for guild {} are up to date", guild);
+            return Ok(());
+        }
+
         if let Err(e) = guild
             .set_commands(ctx.http(), self.commands.get_all_defs().cloned().collect())
             .await
@@ -166,24 +586,172 @@ impl Archmage {
                 eyre!(e).wrap_err(format!("Unable to set guild commands for guild '{guild}!'"))
             );
         }
+        event!(Level::INFO, "Updated commands for guild {}", guild);
         Ok(())
     }
 
-    /// Specialization sub-function of [Archmage::interaction_create].
-    /// Handles slash commands, specifically.
+    /// Registers bot commands globally, for every guild at once. Like
+    /// [Archmage::register_commands], Discord is only updated on change.
+    pub async fn register_global_commands(&self, ctx: &impl CacheHttp) -> Result<()> {
+        let existing = Command::get_global_commands(ctx.http())
+            .await
+            .map_err(|e| eyre!(e).wrap_err("Unable to get global commands!"))?;
+        if definitions_match(&existing, self.commands.get_all_defs()) {
+            event!(Level::DEBUG, "Global commands are up to date");
+            return Ok(());
+        }
+
+        if let Err(e) = Command::set_global_commands(
+            ctx.http(),
+            self.commands.get_all_defs().cloned().collect(),
+        )
+        .await
+        {
+            return Err(eyre!(e).wrap_err("Unable to set global commands!"));
+        }
+        event!(Level::INFO, "Updated global commands");
+        Ok(())
+    }
+
+    /// Removes every command registered directly to the given guild. Used to
+    /// clean up after switching to global commands.
+    pub async fn purge_guild_commands(&self, guild: &GuildId, ctx: &impl CacheHttp) -> Result<()> {
+        let existing = guild
+            .get_commands(ctx.http())
+            .await
+            .map_err(|e| eyre!(e).wrap_err(format!("Unable to get guild commands for guild '{guild}'!")))?;
+        if existing.is_empty() {
+            return Ok(());
+        }
+
+        if let Err(e) = guild.set_commands(ctx.http(), Vec::new()).await {
+            return Err(
+                eyre!(e).wrap_err(format!("Unable to purge guild commands for guild '{guild}!'"))
+            );
+        }
+        event!(Level::INFO, "Purged {} stale commands from guild {}", existing.len(), guild);
+        Ok(())
+    }
+
+    /// Removes every global command. Used to clean up after switching back to
+    /// per-guild commands.
+    pub async fn purge_global_commands(&self, ctx: &impl CacheHttp) -> Result<()> {
+        let existing = Command::get_global_commands(ctx.http())
+            .await
+            .map_err(|e| eyre!(e).wrap_err("Unable to get global commands!"))?;
+        if existing.is_empty() {
+            return Ok(());
+        }
+
+        if let Err(e) = Command::set_global_commands(ctx.http(), Vec::new()).await {
+            return Err(eyre!(e).wrap_err("Unable to purge global commands!"));
+        }
+        event!(Level::INFO, "Purged {} stale global commands", existing.len());
+        Ok(())
+    }
+
+    /// Let someone know their command won't be run, since Archmage is shutting
+    /// down. Chat commands are ignored instead, so as not to spam channels.
+    async fn turn_away(command: CommandInteraction, ctx: &Context) {
+        let response = command
+            .create_response(
+                &ctx.http,
+                serenity::all::CreateInteractionResponse::Message(
+                    serenity::all::CreateInteractionResponseMessage::new()
+                        .ephemeral(true)
+                        .embed(
+                            CreateEmbed::new()
+                                .color(Color::from_rgb(0xFF, 0xA5, 0x00))
+                                .description("Archmage is shutting down. Please try again in a moment.")
+                                .title("Closing time")
+                                .timestamp(Timestamp::now()),
+                        ),
+                ),
+            )
+            .await;
+        if let Err(e) = response {
+            event!(Level::WARN, "Error turning away command during shutdown: {}", e);
+        }
+    }
+
+    /// Whether commands may be run in the given guild. Direct messages are
+    /// always permitted. Guilds are treated as not permitted if it can't be
+    /// checked.
+    pub(crate) async fn guild_permitted(&self, guild_id: Option<GuildId>) -> bool {
+        let Some(guild_id) = guild_id else {
+            return true;
+        };
+        match self.is_guild_allowed(&guild_id).await {
+            Ok(allowed) => allowed,
+            Err(e) => {
+                event!(
+                    Level::ERROR,
+                    "Error checking whether guild '{}' is allowed: {}",
+                    guild_id,
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// The chat command prefix for the given guild.
+    async fn prefix_for(&self, guild_id: &GuildId) -> String {
+        match self.db.get_prefix(&guild_id.get()).await {
+            Ok(prefix) => prefix.unwrap_or_else(|| DEFAULT_PREFIX.to_owned()),
+            Err(e) => {
+                event!(
+                    Level::ERROR,
+                    "Error getting prefix for guild '{}', using the default: {}",
+                    guild_id,
+                    e
+                );
+                DEFAULT_PREFIX.to_owned()
+            }
+        }
+    }
+
+    /// Specialization sub-function of [Archmage::interaction_create] and
+    /// [Archmage::message]. Handles commands, whether slash or chat.
     ///
     /// This function largely delegates the actual command dispatch, i.e. deciding
     /// which command to run, to [CommandDispatcher::run]. If you're looking for
     /// how to add new commands, check out the crate root module `main.rs`.
-    pub async fn handle_command(&self, command: &CommandInteraction, ctx: &Context) -> Result<()> {
-        match self
-            .commands
-            .run(&command.data.name, self, command, ctx)
-            .await
-        {
+    pub async fn handle_command(&self, invocation: &Invocation, ctx: &Context) -> Result<()> {
+        if !self.guild_permitted(invocation.guild_id()).await {
+            return invocation
+                .respond(ctx, Reply::new().ephemeral(true).embed(awaiting_approval()))
+                .await;
+        }
+
+        match self.commands.run(self, invocation, ctx).await {
             Some(Ok(())) => Ok(()),
             Some(Err(e)) => Err(e),
-            None => self.handle_unimplemented(command, ctx).await,
+            None => self.handle_unimplemented(invocation, ctx).await,
+        }
+    }
+
+    /// Specialization sub-function of [Archmage::interaction_create].
+    /// Handles message components such as buttons and select menus, which are
+    /// routed back to the command that created them by
+    /// [CommandDispatcher::run_component]. Components nobody claims are
+    /// logged and otherwise ignored, since Discord will tell the user the
+    /// interaction failed.
+    pub async fn handle_component(
+        &self,
+        component: &ComponentInteraction,
+        ctx: &Context,
+    ) -> Result<()> {
+        match self.commands.run_component(self, component, ctx).await {
+            Some(result) => result,
+            None => {
+                event!(
+                    Level::WARN,
+                    "No handler for component '{}'",
+                    component.data.custom_id
+                );
+                Ok(())
+            }
         }
     }
 
@@ -197,22 +765,20 @@ impl Archmage {
     /// console.
     pub async fn handle_unimplemented(
         &self,
-        command: &CommandInteraction,
+        invocation: &Invocation,
         ctx: &Context,
     ) -> Result<()> {
-        let response = command
-            .create_response(
-                &ctx.http,
-                serenity::all::CreateInteractionResponse::Message(
-                    CreateInteractionResponseMessage::new().embed(
-                        CreateEmbed::new()
-                            .color(Color::from_rgb(0x00, 0xFF, 0x00))
-                            .description(
-                                "Archmage is still working on this spell! Please try again later.",
-                            )
-                            .title("Not yet implemented!")
-                            .timestamp(Timestamp::now()),
-                    ),
+        let response = invocation
+            .respond(
+                ctx,
+                Reply::new().embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .description(
+                            "Archmage is still working on this spell! Please try again later.",
+                        )
+                        .title("Not yet implemented!")
+                        .timestamp(Timestamp::now()),
                 ),
             )
             .await;
@@ -231,12 +797,16 @@ impl Archmage {
     /// Send a message to both the console and the appropriate channel. This
     /// method embeds a unique ID for each error, which allows channel errors to
     /// be matched to their more technical, verbose console errors.
+    ///
+    /// Failed interactions are answered privately, whether or not the handler
+    /// had already responded or deferred. Only if the interaction's token has
+    /// expired is the error posted to the channel instead.
     pub async fn send_terminal_error_message<CTX: Serialize>(
         &self,
         error_context: CTX,
         error: eyre::Report,
         discord_context: &Context,
-        triggering_message: Option<impl AsRef<Message>>,
+        origin: ErrorOrigin<'_>,
     ) {
         let err_id = uuid::Uuid::new_v4().as_simple().to_string();
         let env = serde_json::to_string(&error_context).expect("JSON Serialization Failure");
@@ -246,29 +816,100 @@ impl Archmage {
             error = &format!("{}", error).as_str(),
         );
 
-        if let Some(message) = triggering_message {
-            let response = message.as_ref()
-            .channel_id.send_message(
-                &discord_context.http,
-                serenity::all::CreateMessage::new().add_embed(
-                    CreateEmbed::new()
-                        .color(Color::from_rgb(0xFF, 0x00, 0x00))
-                        .description(format!("Artifices failed, magic gone awry. Something is wrong in the Archmage's tower! (Your error code is {})", &err_id))
-                        .title("An Error Occurred")
-                        .timestamp(Timestamp::now())
-                    )
-                ).await;
+        let (guild_id, user_id) = match origin {
+            ErrorOrigin::Command(invocation) => (invocation.guild_id(), invocation.user().id),
+            ErrorOrigin::Component(component) => (component.guild_id, component.user.id),
+        };
+        let record = ErrorRecord {
+            id: err_id.clone(),
+            context: env,
+            report: format!("{:?}", error),
+            guild_id: guild_id.map(|id| id.get()),
+            user_id: Some(user_id.get()),
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        if let Err(e) = self.db.log_error(&record).await {
+            event!(
+                Level::ERROR,
+                error = &format!("{}", e).as_str(),
+                "Unable to record error {} in the database",
+                &err_id
+            )
+        }
 
-            if let Err(e) = response {
-                event!(
-                    Level::ERROR,
-                    error = &format!("{}", e).as_str(),
-                    "DOUBLE FAULT! Error sending error message to user channel"
+        let embed = CreateEmbed::new()
+            .color(Color::from_rgb(0xFF, 0x00, 0x00))
+            .description(format!("Artifices failed, magic gone awry. Something is wrong in the Archmage's tower! (Your error code is {})", &err_id))
+            .title("An Error Occurred")
+            .timestamp(Timestamp::now());
+
+        let (response, channel) = match origin {
+            ErrorOrigin::Command(invocation) => (
+                invocation
+                    .respond(discord_context, Reply::new().ephemeral(true).embed(embed.clone()))
+                    .await,
+                invocation.channel_id(),
+            ),
+            ErrorOrigin::Component(component) => (
+                reply_to_component(component, discord_context, embed.clone()).await,
+                component.channel_id,
+            ),
+        };
+
+        let response = match response {
+            Err(e) if token_expired(&e) => channel
+                .send_message(
+                    &discord_context.http,
+                    serenity::all::CreateMessage::new().add_embed(embed),
                 )
-            }
+                .await
+                .map(|_| ())
+                .map_err(|e| eyre!(e)),
+            other => other,
+        };
+
+        if let Err(e) = response {
+            event!(
+                Level::ERROR,
+                error = &format!("{}", e).as_str(),
+                "DOUBLE FAULT! Error sending error message to user channel"
+            )
         }
     }
 }
 
+/// Where a failure came from, and so where the user should hear about it.
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorOrigin<'a> {
+    /// A slash or chat command.
+    Command(&'a Invocation),
+    /// A button or select menu on one of Archmage's messages.
+    Component(&'a ComponentInteraction),
+}
+
+/// Whether Discord rejected a response because the interaction is too old to
+/// respond to. Tokens last fifteen minutes, or three seconds if the
+/// interaction was never acknowledged.
+fn token_expired(error: &eyre::Report) -> bool {
+    /// Unknown Interaction, Unknown Webhook and Invalid Webhook Token.
+    const EXPIRED: [isize; 3] = [10062, 10015, 50027];
+    match error.downcast_ref::<SerenityError>() {
+        Some(SerenityError::Http(HttpError::UnsuccessfulRequest(response))) => {
+            EXPIRED.contains(&response.error.code)
+        }
+        _ => false,
+    }
+}
+
+/// What users in guilds that haven't been allowed are told when they try to
+/// use Archmage.
+pub(crate) fn awaiting_approval() -> CreateEmbed {
+    CreateEmbed::new()
+        .color(Color::from_rgb(0xFF, 0xA5, 0x00))
+        .description("Archmage hasn't been approved for this server yet. Please check back later!")
+        .title("Awaiting approval")
+        .timestamp(Timestamp::now())
+}
+
 unsafe impl Send for Archmage {}
 unsafe impl Sync for Archmage {}
\ No newline at end of file
@@ -0,0 +1,73 @@
+//! Who may change what in a campaign. Server administrators may do anything a
+//! DM can, and DMs may do anything a player can. Anyone may look.
+use serenity::all::{Permissions, UserId};
+
+use crate::archmage::Archmage;
+use crate::model::campaign::Campaign;
+use crate::model::character::Character;
+
+use super::error::{CommandError, CommandResult};
+
+/// Whether a user may run a campaign.
+async fn is_dm(
+    archmage: &Archmage,
+    campaign: &Campaign,
+    user: UserId,
+    permissions: Option<Permissions>,
+) -> CommandResult<bool> {
+    Ok(permissions.is_some_and(|p| p.administrator())
+        || archmage.db().is_dm(&campaign.id, &user.get()).await?)
+}
+
+/// Only a campaign's DMs may run it.
+pub async fn require_dm(
+    archmage: &Archmage,
+    campaign: &Campaign,
+    user: UserId,
+    permissions: Option<Permissions>,
+) -> CommandResult {
+    match is_dm(archmage, campaign, user, permissions).await? {
+        true => Ok(()),
+        false => Err(CommandError::permission(format!(
+            "Only DMs of **{}** may do that.",
+            campaign.name
+        ))),
+    }
+}
+
+/// Only a character's players, and their campaign's DMs, may change them.
+pub async fn require_player(
+    archmage: &Archmage,
+    campaign: &Campaign,
+    character: &Character,
+    user: UserId,
+    permissions: Option<Permissions>,
+) -> CommandResult {
+    if archmage.db().is_player(&character.id, &user.get()).await?
+        || is_dm(archmage, campaign, user, permissions).await?
+    {
+        return Ok(());
+    }
+    Err(CommandError::permission(format!(
+        "Only whoever plays `{}`, and DMs of **{}**, may do that.",
+        character.name, campaign.name
+    )))
+}
+
+/// Anyone playing in a campaign, and its DMs, may change its party pot.
+/// `character` is the one the user plays there, if any.
+pub async fn require_party(
+    archmage: &Archmage,
+    campaign: &Campaign,
+    character: Option<&Character>,
+    user: UserId,
+    permissions: Option<Permissions>,
+) -> CommandResult {
+    if character.is_some() || is_dm(archmage, campaign, user, permissions).await? {
+        return Ok(());
+    }
+    Err(CommandError::permission(format!(
+        "Only players and DMs of **{}** may do that.",
+        campaign.name
+    )))
+}
@@ -0,0 +1,332 @@
+//! Commands for whoever runs Archmage, rather than for server administrators.
+//! Every subcommand here is restricted to the bot's owners.
+use eyre::eyre;
+use serenity::all::{
+    Color, CommandDataOption, CommandDataOptionValue, CommandOptionType, CreateCommand,
+    CreateCommandOption, CreateEmbed, GuildId, Permissions, Timestamp,
+};
+use serenity::prelude::*;
+
+use crate::archmage::Archmage;
+
+use super::error::{CommandError, CommandResult};
+use super::invocation::{Invocation, Reply};
+use super::options::required_string;
+use super::text::{truncate, DESCRIPTION_LIMIT, FIELD_LIMIT};
+use super::{handle_fn, ArchmageCommand, CommandRegistration};
+
+pub struct AdminCommand;
+
+impl ArchmageCommand for AdminCommand {
+    fn register() -> Vec<CommandRegistration> {
+        vec![CommandRegistration::new(
+            "admin",
+            CreateCommand::new("admin")
+                .description("Tools for Archmage's owners")
+                // Hidden from everyone but administrators; owners are checked
+                // when the command is run.
+                .default_member_permissions(Permissions::ADMINISTRATOR)
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "error",
+                        "Look up an error by its code",
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::String,
+                            "id",
+                            "The error code a user was given",
+                        )
+                        .required(true),
+                    ),
+                )
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommandGroup,
+                        "guilds",
+                        "Manage which servers Archmage works in",
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "allow",
+                            "Let Archmage work in a server",
+                        )
+                        .add_sub_option(guild_option()),
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "remove",
+                            "Stop Archmage working in a server",
+                        )
+                        .add_sub_option(guild_option()),
+                    )
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "list",
+                        "List allowed servers, and those awaiting approval",
+                    )),
+                ),
+            handle_fn!(Self::run),
+        )
+        .help(
+            "Only Archmage's owners may use this command. Servers listed in the configuration \
+             file can't be removed here; edit the file and send Archmage `SIGHUP` instead.",
+        )
+        .example("/admin error 3f2b9c0e6d8a4f1b9e7c5a2d4b6f8e0a")
+        .example("/admin guilds allow 123456789012345678")
+        .example("/admin guilds list")]
+    }
+}
+
+impl AdminCommand {
+    async fn run(archmage: &Archmage, invocation: &Invocation, ctx: &Context) -> CommandResult {
+        if !archmage.is_owner(ctx, invocation.user().id).await? {
+            return Err(CommandError::permission(
+                "Only Archmage's owners may use admin commands.",
+            ));
+        }
+
+        let Some(subcommand) = invocation.options().first() else {
+            return Err(eyre!("/admin used without a subcommand").into());
+        };
+        match (subcommand.name.as_str(), &subcommand.value) {
+            ("error", CommandDataOptionValue::SubCommand(options)) => {
+                let id = required_string(options, "id")?;
+                Self::error(archmage, invocation, ctx, id).await
+            }
+            ("guilds", CommandDataOptionValue::SubCommandGroup(subcommands)) => {
+                let Some(subcommand) = subcommands.first() else {
+                    return Err(eyre!("/admin guilds used without a subcommand").into());
+                };
+                let CommandDataOptionValue::SubCommand(options) = &subcommand.value else {
+                    return Err(
+                        eyre!("/admin guilds {} is not a subcommand", subcommand.name).into(),
+                    );
+                };
+                match subcommand.name.as_str() {
+                    "allow" => Self::allow_guild(archmage, invocation, ctx, options).await,
+                    "remove" => Self::remove_guild(archmage, invocation, ctx, options).await,
+                    "list" => Self::list_guilds(archmage, invocation, ctx).await,
+                    other => Err(eyre!("Unknown /admin guilds subcommand {other}").into()),
+                }
+            }
+            (other, _) => Err(eyre!("Unknown /admin subcommand {other}").into()),
+        }
+    }
+
+    async fn error(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        id: &str,
+    ) -> CommandResult {
+        let id = id.trim().to_lowercase();
+        let Some(record) = archmage.db().get_error(&id).await? else {
+            return Err(CommandError::not_found(format!(
+                "There's no record of an error with the code `{id}`."
+            )));
+        };
+
+        let mention = |id: Option<u64>, fmt: fn(u64) -> String| {
+            id.map(fmt).unwrap_or_else(|| "None".to_owned())
+        };
+        invocation
+            .respond(
+                ctx,
+                Reply::new().ephemeral(true).embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0xFF, 0x00, 0x00))
+                        .title(format!("Error {}", record.id))
+                        .description(code_block("", &record.report, DESCRIPTION_LIMIT))
+                        .field("When", format!("<t:{}:f>", record.created_at), true)
+                        .field(
+                            "Guild",
+                            mention(record.guild_id, |id| format!("`{id}`")),
+                            true,
+                        )
+                        .field(
+                            "User",
+                            mention(record.user_id, |id| format!("<@{id}>")),
+                            true,
+                        )
+                        .field(
+                            "Context",
+                            code_block("json", &record.context, FIELD_LIMIT),
+                            false,
+                        )
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn allow_guild(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let guild = guild_id(options)?;
+        let configured = archmage.config().allowed_guilds.contains(&guild.get());
+        if configured
+            || !archmage
+                .db()
+                .allow_guild(&guild.get(), &invocation.user().id.get())
+                .await?
+        {
+            return Err(CommandError::user(format!(
+                "{} is already allowed.",
+                describe(ctx, guild)
+            )));
+        }
+
+        // Set the guild up straight away if Archmage is already there.
+        if ctx.cache.guild(guild).is_some() {
+            archmage.join_guild(&guild, ctx).await;
+        }
+        Self::confirm(
+            invocation,
+            ctx,
+            "Server allowed",
+            format!("Archmage may now work in {}.", describe(ctx, guild)),
+        )
+        .await
+    }
+
+    async fn remove_guild(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let guild = guild_id(options)?;
+        if archmage.config().allowed_guilds.contains(&guild.get()) {
+            return Err(CommandError::user(format!(
+                "{} is allowed by the configuration file, so it has to be removed there.",
+                describe(ctx, guild)
+            )));
+        }
+        if !archmage.db().disallow_guild(&guild.get()).await? {
+            return Err(CommandError::not_found(format!(
+                "{} isn't allowed.",
+                describe(ctx, guild)
+            )));
+        }
+
+        // Leave it, or put it back to awaiting approval, as configured.
+        let description = describe(ctx, guild);
+        if ctx.cache.guild(guild).is_some() {
+            archmage.join_guild(&guild, ctx).await;
+        }
+        Self::confirm(
+            invocation,
+            ctx,
+            "Server removed",
+            format!("Archmage no longer works in {description}."),
+        )
+        .await
+    }
+
+    async fn list_guilds(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+    ) -> CommandResult {
+        let list = |guilds: Vec<String>| match guilds.is_empty() {
+            true => "None".to_owned(),
+            false => truncate(&guilds.join("\n"), FIELD_LIMIT),
+        };
+        let configured = archmage
+            .config()
+            .allowed_guilds
+            .iter()
+            .map(|id| describe(ctx, GuildId::new(*id)))
+            .collect();
+        let allowed = archmage
+            .db()
+            .allowed_guilds()
+            .await?
+            .into_iter()
+            .map(|id| describe(ctx, GuildId::new(id)))
+            .collect();
+        let pending = archmage
+            .db()
+            .pending_guilds()
+            .await?
+            .into_iter()
+            .map(|(id, name)| format!("{name} (`{id}`)"))
+            .collect();
+
+        invocation
+            .respond(
+                ctx,
+                Reply::new().ephemeral(true).embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .title("Servers")
+                        .field("From the configuration file", list(configured), false)
+                        .field("Allowed by owners", list(allowed), false)
+                        .field("Awaiting approval", list(pending), false)
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn confirm(
+        invocation: &Invocation,
+        ctx: &Context,
+        title: &str,
+        description: String,
+    ) -> CommandResult {
+        invocation
+            .respond(
+                ctx,
+                Reply::new().ephemeral(true).embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .title(title)
+                        .description(description)
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// Guild IDs are too large for Discord's integer options, so they're taken
+/// as text.
+fn guild_option() -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::String, "guild", "The server's ID").required(true)
+}
+
+fn guild_id(options: &[CommandDataOption]) -> CommandResult<GuildId> {
+    let id = required_string(options, "guild")?.trim();
+    id.parse::<u64>()
+        .ok()
+        .filter(|id| *id != 0)
+        .map(GuildId::new)
+        .ok_or(CommandError::user(format!("`{id}` isn't a server ID.")))
+}
+
+/// A guild's name and ID, or just its ID if Archmage isn't in it.
+fn describe(ctx: &Context, guild: GuildId) -> String {
+    match guild.name(&ctx.cache) {
+        Some(name) => format!("{name} (`{guild}`)"),
+        None => format!("`{guild}`"),
+    }
+}
+
+/// Wrap text in a code block, cutting the text short if the whole block would
+/// be longer than `limit` characters.
+fn code_block(language: &str, body: &str, limit: usize) -> String {
+    let fence = format!("```{language}\n");
+    let room = limit - fence.chars().count() - "\n```".len();
+    format!("{fence}{}\n```", truncate(body, room))
+}
@@ -0,0 +1,125 @@
+//! Splitting free-form command arguments into words, where a "double quoted
+//! phrase" counts as a single word, and batches into their entries.
+
+/// A single word of input.
+#[derive(Debug, Clone)]
+pub struct Token {
+    /// The word itself, without any surrounding quotes.
+    pub text: String,
+    /// Whether the word was quoted. Quoted words are never mistaken for
+    /// keywords like subcommand names.
+    pub quoted: bool,
+    /// Byte offset of the start of the word (or its opening quote) in the
+    /// original input.
+    pub start: usize,
+}
+
+/// Split input on whitespace, keeping quoted phrases together. An unclosed
+/// quote runs to the end of the input.
+pub fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(start, ch)) = chars.peek() {
+        if ch.is_whitespace() {
+            let _ = chars.next();
+            continue;
+        }
+
+        let mut text = String::new();
+        let quoted = ch == '"';
+        if quoted {
+            let _ = chars.next();
+            for (_, ch) in chars.by_ref() {
+                if ch == '"' {
+                    break;
+                }
+                text.push(ch);
+            }
+        } else {
+            while let Some(&(_, ch)) = chars.peek() {
+                if ch.is_whitespace() {
+                    break;
+                }
+                text.push(ch);
+                let _ = chars.next();
+            }
+        }
+
+        tokens.push(Token {
+            text,
+            quoted,
+            start,
+        });
+    }
+
+    tokens
+}
+
+/// Split a batch such as `3 Gold; Torch` into its entries, on semicolons
+/// outside of double quotes. The quotes are kept, for [tokenize] to handle.
+pub fn split_batch(input: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, ch) in input.char_indices() {
+        match ch {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                parts.push(&input[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(input: &str) -> Vec<(String, bool, usize)> {
+        tokenize(input)
+            .into_iter()
+            .map(|t| (t.text, t.quoted, t.start))
+            .collect()
+    }
+
+    #[test]
+    fn quoted_phrases_are_one_word() {
+        assert_eq!(
+            words("add 1  \"Potion of Strength\" x"),
+            [
+                ("add".to_owned(), false, 0),
+                ("1".to_owned(), false, 4),
+                ("Potion of Strength".to_owned(), true, 7),
+                ("x".to_owned(), false, 28),
+            ]
+        );
+        assert_eq!(words("\"\""), [(String::new(), true, 0)]);
+        assert!(words("  ").is_empty());
+    }
+
+    #[test]
+    fn unclosed_quotes_run_to_the_end() {
+        assert_eq!(
+            words("say \"hello there"),
+            [
+                ("say".to_owned(), false, 0),
+                ("hello there".to_owned(), true, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn batches_split_outside_quotes() {
+        assert_eq!(
+            split_batch("3 Gold; \"Salt; Pepper\";Torch"),
+            ["3 Gold", " \"Salt; Pepper\"", "Torch"]
+        );
+        assert_eq!(split_batch("Torch;"), ["Torch", ""]);
+        assert_eq!(split_batch(""), [""]);
+    }
+}
@@ -0,0 +1,847 @@
+//! Running a campaign, for its DMs. Server administrators may do anything a
+//! DM can.
+use eyre::eyre;
+use serenity::all::{
+    ButtonStyle, ChannelId, ChannelType, Color, CommandDataOption, CommandOptionType,
+    ComponentInteraction, CreateActionRow, CreateButton, CreateCommand, CreateCommandOption,
+    CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage,
+    GuildId, Timestamp,
+};
+use serenity::prelude::*;
+
+use crate::archmage::Archmage;
+use crate::model::campaign::Campaign;
+use crate::model::character::Character;
+use crate::model::monitor::Kind;
+use crate::model::xp::Threshold;
+
+use super::access::require_dm;
+use super::error::{CommandError, CommandResult};
+use super::invocation::{Invocation, Reply};
+use super::monitor;
+use super::options::{required_integer, required_string, required_user};
+use super::text::{truncate, DESCRIPTION_LIMIT};
+use super::{handle_fn, options, resolve, ArchmageCommand, CommandRegistration};
+
+pub struct CampaignCommand;
+
+impl ArchmageCommand for CampaignCommand {
+    fn register() -> Vec<CommandRegistration> {
+        vec![CommandRegistration::new(
+            "campaign",
+            CreateCommand::new("campaign")
+                .description("Run a campaign you're a DM of")
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "set",
+                        "Use a campaign for commands in a channel, unless they name another",
+                    )
+                    .add_sub_option(channel_option())
+                    .add_sub_option(campaign_option()),
+                )
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "add",
+                        "Let someone play a character, adding the character if they're new",
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::User,
+                            "player",
+                            "Who will play the character",
+                        )
+                        .required(true),
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::String,
+                            "character",
+                            "The character's short ID, e.g. Cal",
+                        )
+                        .required(true),
+                    )
+                    .add_sub_option(campaign_option()),
+                )
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "remove",
+                        "Remove a player from a character, delete a character, or free a channel",
+                    )
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::User,
+                        "player",
+                        "The player to remove. Leave it out to delete the whole character",
+                    ))
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "character",
+                        "The character's short ID",
+                    ))
+                    .add_sub_option(channel_option())
+                    .add_sub_option(campaign_option()),
+                )
+                .add_option(feature_subcommand(
+                    "enable",
+                    "Turn a feature back on in a campaign",
+                ))
+                .add_option(feature_subcommand(
+                    "disable",
+                    "Turn a feature off in a campaign, such as inventories or quests",
+                ))
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommandGroup,
+                        "level",
+                        "Change how much XP each level takes",
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "set",
+                            "Set how much XP it takes to reach a level",
+                        )
+                        .add_sub_option(level_option())
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::Integer,
+                                "xp",
+                                "How much XP it takes",
+                            )
+                            .min_int_value(0)
+                            .required(true),
+                        )
+                        .add_sub_option(campaign_option()),
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "remove",
+                            "Remove a level from the table",
+                        )
+                        .add_sub_option(level_option())
+                        .add_sub_option(campaign_option()),
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "show",
+                            "Show how much XP each level takes",
+                        )
+                        .add_sub_option(campaign_option()),
+                    ),
+                )
+                .add_option(monitor_group(
+                    Kind::Inventory,
+                    "Keep the party pot and every inventory up to date in a channel",
+                ))
+                .add_option(monitor_group(
+                    Kind::Quests,
+                    "Keep the active quests up to date in a channel",
+                ))
+                .add_option(monitor_group(
+                    Kind::Characters,
+                    "Keep every character's level, XP and players up to date in a channel",
+                )),
+            handle_fn!(Self::run),
+        )
+        .leading("campaign")
+        .default_subcommand("level set")
+        .help(
+            "Only a campaign's DMs (and server administrators) may use this command. When the \
+             campaign isn't named, the channel's campaign is used.\n\n\
+             A channel can only belong to one campaign at a time, and threads use their \
+             channel's campaign. `remove` without a character frees the channel instead.\n\n\
+             Character IDs are short, unique within the campaign, and may be shared by \
+             several players. Removing a character's last player keeps the character; leaving \
+             the player out deletes the character and their inventory, after asking first.\n\n\
+             Campaigns start with the D&D 3.5e SRD's XP table. Each level must take more XP \
+             than the one before it.\n\n\
+             Every feature starts out on. Turning one off stops everyone, DMs included, from \
+             using its commands in the campaign, but keeps everything Archmage knows about it.\n\n\
+             Monitors are messages Archmage keeps up to date as things change, so that \
+             inventories, quests or characters can be seen at a glance. Each can be in one \
+             channel per campaign; monitoring in another channel moves it there. Deleted \
+             monitor messages are sent again on the next change.",
+        )
+        .example("/campaign set #dnd Lost Mine")
+        .example("/campaign add @Alice Cal")
+        .example("/campaign remove @Alice Cal")
+        .example("/campaign remove Cal")
+        .example("/campaign level set 2 1000")
+        .example("/campaign disable quests")
+        .example("/campaign quests monitor #quest-log")
+        .example("/campaign quests disable")
+        .on_component(handle_fn!(Self::confirm_delete))]
+    }
+}
+
+impl CampaignCommand {
+    async fn run(archmage: &Archmage, invocation: &Invocation, ctx: &Context) -> CommandResult {
+        let Some(guild_id) = invocation.guild_id() else {
+            return Err(CommandError::user("Campaigns only exist in servers."));
+        };
+        let Some((subcommand, options)) = options::subcommand(invocation.options()) else {
+            return Err(eyre!("/campaign used without a subcommand").into());
+        };
+        let (group, subcommand, options) = match subcommand {
+            "level" => {
+                let (subcommand, options) = options::subcommand(options)
+                    .ok_or(eyre!("/campaign level used without a subcommand"))?;
+                (Some("level"), subcommand, options)
+            }
+            group => match Kind::parse(group) {
+                Some(kind) => {
+                    let (subcommand, options) = options::subcommand(options)
+                        .ok_or(eyre!("/campaign {group} used without a subcommand"))?;
+                    (Some(kind.as_str()), subcommand, options)
+                }
+                None => (None, subcommand, options),
+            },
+        };
+
+        let campaign = resolve::campaign(archmage, invocation, ctx, options).await?;
+        let permissions = invocation.member_permissions(ctx).await?;
+        require_dm(archmage, &campaign, invocation.user().id, permissions).await?;
+        match (group, subcommand) {
+            (None, "set") => {
+                Self::set(archmage, invocation, ctx, guild_id, &campaign, options).await
+            }
+            (None, "add") => Self::add(archmage, invocation, ctx, &campaign, options).await,
+            (None, "remove") => Self::remove(archmage, invocation, ctx, &campaign, options).await,
+            (None, "enable" | "disable") => {
+                Self::set_feature(archmage, invocation, ctx, &campaign, subcommand, options).await
+            }
+            (Some("level"), "set") => {
+                Self::set_level(archmage, invocation, ctx, &campaign, options).await
+            }
+            (Some("level"), "remove") => {
+                Self::remove_level(archmage, invocation, ctx, &campaign, options).await
+            }
+            (Some("level"), "show") => {
+                Self::show_levels(archmage, invocation, ctx, &campaign).await
+            }
+            (Some(group), "monitor" | "disable") => {
+                let kind = Kind::parse(group)
+                    .ok_or(eyre!("Unknown /campaign subcommand group {group}"))?;
+                match subcommand {
+                    "monitor" => {
+                        Self::monitor(
+                            archmage, invocation, ctx, guild_id, &campaign, kind, options,
+                        )
+                        .await
+                    }
+                    _ => Self::unmonitor(archmage, invocation, ctx, &campaign, kind).await,
+                }
+            }
+            (_, other) => Err(eyre!("Unknown /campaign subcommand {other}").into()),
+        }
+    }
+
+    async fn set(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        guild_id: GuildId,
+        campaign: &Campaign,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let channel = channel(invocation, options);
+        let in_guild = channel
+            .to_channel(ctx)
+            .await
+            .ok()
+            .and_then(|c| c.guild())
+            .is_some_and(|c| c.guild_id == guild_id);
+        if !in_guild {
+            return Err(CommandError::user(format!(
+                "<#{channel}> isn't a channel in this server."
+            )));
+        }
+
+        let bound = archmage
+            .db()
+            .bind_channel(&channel.get(), &campaign.id, &invocation.user().id.get())
+            .await?;
+        if !bound {
+            let current = archmage.db().channel_campaign(&channel.get()).await?;
+            return Err(CommandError::user(match current {
+                Some(current) if current.id == campaign.id => {
+                    format!("<#{channel}> already belongs to **{}**.", campaign.name)
+                }
+                Some(current) => format!(
+                    "<#{channel}> belongs to **{}**. Remove it from there first.",
+                    current.name
+                ),
+                None => format!("<#{channel}> changed while I was busy. Please try again."),
+            }));
+        }
+
+        Self::done(
+            invocation,
+            ctx,
+            "Channel set",
+            format!(
+                "Commands in <#{channel}> are now about **{}**, unless they name another campaign.",
+                campaign.name
+            ),
+        )
+        .await
+    }
+
+    async fn add(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        campaign: &Campaign,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let player = required_user(options, "player")?;
+        let name = Character::check_name(required_string(options, "character")?)
+            .map_err(CommandError::user)?;
+
+        let (character, created) = archmage.db().ensure_character(&campaign.id, name).await?;
+        if !archmage
+            .db()
+            .add_player(&character.id, &player.get())
+            .await?
+        {
+            return Err(CommandError::user(format!(
+                "<@{player}> already plays `{}`.",
+                character.name
+            )));
+        }
+
+        monitor::changed(archmage, ctx, campaign.id, Kind::Characters);
+        if created {
+            monitor::changed(archmage, ctx, campaign.id, Kind::Inventory);
+        }
+
+        let added = match created {
+            true => format!("Added `{}` to **{}**. ", character.name, campaign.name),
+            false => String::new(),
+        };
+        let players = archmage
+            .db()
+            .players(&character.id)
+            .await?
+            .iter()
+            .map(|id| format!("<@{id}>"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Self::done(
+            invocation,
+            ctx,
+            "Player added",
+            format!("{added}`{}` is now played by {players}.", character.name),
+        )
+        .await
+    }
+
+    /// Removes a player from a character, or asks to delete the character if
+    /// no player is given. Without a character, frees a channel instead.
+    async fn remove(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        campaign: &Campaign,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let player = options::user(options, "player");
+        let Some(name) = options::string(options, "character") else {
+            if let Some(player) = player {
+                return Err(CommandError::user(format!(
+                    "Which character should <@{player}> be removed from?"
+                )));
+            }
+            return Self::remove_channel(archmage, invocation, ctx, campaign, options).await;
+        };
+        if options.iter().any(|o| o.name == "channel") {
+            return Err(CommandError::user(
+                "Remove a character or a channel, but not both at once.",
+            ));
+        }
+
+        let character = resolve::character(archmage, campaign, name).await?;
+        let Some(player) = player else {
+            return Self::ask_delete(invocation, ctx, &character).await;
+        };
+        if !archmage
+            .db()
+            .remove_player(&character.id, &player.get())
+            .await?
+        {
+            return Err(CommandError::not_found(format!(
+                "<@{player}> doesn't play `{}`.",
+                character.name
+            )));
+        }
+        monitor::changed(archmage, ctx, campaign.id, Kind::Characters);
+        Self::done(
+            invocation,
+            ctx,
+            "Player removed",
+            format!("<@{player}> no longer plays `{}`.", character.name),
+        )
+        .await
+    }
+
+    async fn remove_channel(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        campaign: &Campaign,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let channel = channel(invocation, options);
+        if !archmage
+            .db()
+            .unbind_channel(&channel.get(), &campaign.id)
+            .await?
+        {
+            return Err(CommandError::not_found(format!(
+                "<#{channel}> doesn't belong to **{}**.",
+                campaign.name
+            )));
+        }
+        Self::done(
+            invocation,
+            ctx,
+            "Channel removed",
+            format!("<#{channel}> no longer belongs to **{}**.", campaign.name),
+        )
+        .await
+    }
+
+    async fn set_level(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        campaign: &Campaign,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let threshold = Threshold {
+            level: required_integer(options, "level")?,
+            xp: required_integer(options, "xp")?,
+        };
+        archmage
+            .db()
+            .set_level(&campaign.id, threshold, &invocation.user().id.get())
+            .await?
+            .map_err(CommandError::user)?;
+        monitor::changed(archmage, ctx, campaign.id, Kind::Characters);
+        Self::done(
+            invocation,
+            ctx,
+            "Level set",
+            format!(
+                "Level {} of **{}** now takes {} XP.",
+                threshold.level, campaign.name, threshold.xp
+            ),
+        )
+        .await
+    }
+
+    async fn remove_level(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        campaign: &Campaign,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let level = required_integer(options, "level")?;
+        let user_id = invocation.user().id.get();
+        if !archmage
+            .db()
+            .remove_level(&campaign.id, &level, &user_id)
+            .await?
+        {
+            return Err(CommandError::not_found(format!(
+                "**{}** has no level {level}.",
+                campaign.name
+            )));
+        }
+        monitor::changed(archmage, ctx, campaign.id, Kind::Characters);
+        Self::done(
+            invocation,
+            ctx,
+            "Level removed",
+            format!("**{}** no longer has a level {level}.", campaign.name),
+        )
+        .await
+    }
+
+    async fn set_feature(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        campaign: &Campaign,
+        subcommand: &str,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let name = required_string(options, "feature")?.trim();
+        let features = archmage.commands().features();
+        let Some(feature) = features.iter().find(|f| f.eq_ignore_ascii_case(name)) else {
+            let known: Vec<_> = features.iter().map(|f| format!("`{f}`")).collect();
+            return Err(CommandError::not_found(format!(
+                "There's no feature called `{name}`. Features are {}.",
+                known.join(", ")
+            )));
+        };
+
+        let enabled = subcommand == "enable";
+        let changed = archmage
+            .db()
+            .set_feature_enabled(&campaign.id, feature, enabled, &invocation.user().id.get())
+            .await?;
+        // Monitors weren't redrawn while it was off.
+        if changed && enabled {
+            for kind in Kind::ALL.into_iter().filter(|k| k.feature() == *feature) {
+                monitor::changed(archmage, ctx, campaign.id, kind);
+            }
+        }
+        let (state, title) = match enabled {
+            true => ("on", "Feature enabled"),
+            false => ("off", "Feature disabled"),
+        };
+        let mut description = match changed {
+            true => format!("Turned {feature} {state} in **{}**.", campaign.name),
+            false => format!("{feature} was already {state} in **{}**.", campaign.name),
+        };
+        let disabled = archmage.db().disabled_features(&campaign.id).await?;
+        if !disabled.is_empty() {
+            let disabled: Vec<_> = disabled.iter().map(|f| format!("`{f}`")).collect();
+            description.push_str(&format!("\n\nTurned off: {}", disabled.join(", ")));
+        }
+        Self::done(invocation, ctx, title, description).await
+    }
+
+    async fn show_levels(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        campaign: &Campaign,
+    ) -> CommandResult {
+        let levels = archmage.db().levels(&campaign.id).await?;
+        let description = match levels.is_empty() {
+            true => "No levels. Add some with `/campaign level set`.".to_owned(),
+            false => levels
+                .iter()
+                .map(|t| format!("Level {}: {} XP", t.level, t.xp))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        invocation
+            .respond(
+                ctx,
+                Reply::new().embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .title("Levels")
+                        .description(truncate(&description, DESCRIPTION_LIMIT))
+                        .footer(CreateEmbedFooter::new(&campaign.name))
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Monitor a kind of thing in a channel, drawing it there straight away so
+    /// that any trouble posting is reported now rather than on the next
+    /// change.
+    async fn monitor(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        guild_id: GuildId,
+        campaign: &Campaign,
+        kind: Kind,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let channel = channel(invocation, options);
+        let in_guild = channel
+            .to_channel(ctx)
+            .await
+            .ok()
+            .and_then(|c| c.guild())
+            .is_some_and(|c| c.guild_id == guild_id);
+        if !in_guild {
+            return Err(CommandError::user(format!(
+                "<#{channel}> isn't a channel in this server."
+            )));
+        }
+
+        let previous = archmage
+            .db()
+            .set_monitor(
+                &campaign.id,
+                kind,
+                &channel.get(),
+                &invocation.user().id.get(),
+            )
+            .await?;
+        if let Some(previous) = previous {
+            monitor::clear(&ctx.http, previous.channel_id, &previous.message_ids).await;
+        }
+        let drawn = archmage
+            .monitors()
+            .redraw(archmage.db(), &ctx.http, campaign.id, kind)
+            .await;
+        if drawn.is_err() {
+            if let Some(removed) = archmage.db().remove_monitor(&campaign.id, kind).await? {
+                monitor::clear(&ctx.http, removed.channel_id, &removed.message_ids).await;
+            }
+            return Err(CommandError::user(format!(
+                "I couldn't post in <#{channel}>. Check that I may send messages and embed \
+                 links there."
+            )));
+        }
+
+        Self::done(
+            invocation,
+            ctx,
+            "Monitor set",
+            format!(
+                "<#{channel}> now shows **{}**'s {}, kept up to date.",
+                campaign.name,
+                kind.as_str()
+            ),
+        )
+        .await
+    }
+
+    async fn unmonitor(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        campaign: &Campaign,
+        kind: Kind,
+    ) -> CommandResult {
+        let Some(removed) = archmage.db().remove_monitor(&campaign.id, kind).await? else {
+            return Err(CommandError::not_found(format!(
+                "**{}** doesn't monitor its {}.",
+                campaign.name,
+                kind.as_str()
+            )));
+        };
+        monitor::clear(&ctx.http, removed.channel_id, &removed.message_ids).await;
+        Self::done(
+            invocation,
+            ctx,
+            "Monitor removed",
+            format!(
+                "**{}**'s {} are no longer shown in <#{}>.",
+                campaign.name,
+                kind.as_str(),
+                removed.channel_id
+            ),
+        )
+        .await
+    }
+
+    /// Deleting a character can't be undone, so ask first. The buttons are
+    /// handled by [Self::confirm_delete].
+    async fn ask_delete(
+        invocation: &Invocation,
+        ctx: &Context,
+        character: &Character,
+    ) -> CommandResult {
+        let buttons = CreateActionRow::Buttons(vec![
+            CreateButton::new(format!("campaign:delete:{}", character.id))
+                .label("Delete")
+                .style(ButtonStyle::Danger),
+            CreateButton::new("campaign:cancel")
+                .label("Cancel")
+                .style(ButtonStyle::Secondary),
+        ]);
+        invocation
+            .respond(
+                ctx,
+                Reply::new()
+                    .ephemeral(true)
+                    .embed(
+                        CreateEmbed::new()
+                            .color(Color::from_rgb(0xFF, 0xA5, 0x00))
+                            .title(format!("Delete {}?", character.name))
+                            .description(
+                                "Their inventory and everything else Archmage keeps about \
+                                 them will be deleted, with no way to get it back. Items they \
+                                 put in the party pot and quests they added are kept. To remove \
+                                 a single player instead, name them in `/campaign remove`.",
+                            )
+                            .timestamp(Timestamp::now()),
+                    )
+                    .components(vec![buttons]),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Handles the buttons from [Self::ask_delete]. Their custom IDs look like
+    /// `campaign:delete:<character row ID>` or `campaign:cancel`. Chat command
+    /// replies can be seen by everyone, so whoever presses a button is checked
+    /// again.
+    async fn confirm_delete(
+        archmage: &Archmage,
+        component: &ComponentInteraction,
+        ctx: &Context,
+    ) -> CommandResult {
+        let embed = match component.data.custom_id.strip_prefix("campaign:delete:") {
+            None if component.data.custom_id == "campaign:cancel" => CreateEmbed::new()
+                .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                .title("Cancelled")
+                .description("Nothing was deleted."),
+            None => {
+                return Err(eyre!(
+                    "Malformed campaign button ID '{}'",
+                    component.data.custom_id
+                )
+                .into())
+            }
+            Some(id) => {
+                let id = id.parse::<i64>().map_err(|_| {
+                    eyre!("Malformed character ID in '{}'", component.data.custom_id)
+                })?;
+                let gone = || CommandError::not_found("That character has already been deleted.");
+                let character = archmage.db().character_by_id(&id).await?.ok_or_else(gone)?;
+                let campaign = archmage
+                    .db()
+                    .campaign_by_id(&character.campaign_id)
+                    .await?
+                    .filter(|c| Some(GuildId::new(c.guild_id)) == component.guild_id)
+                    .ok_or_else(gone)?;
+                let permissions = component.member.as_ref().and_then(|m| m.permissions);
+                require_dm(archmage, &campaign, component.user.id, permissions).await?;
+
+                let _ = archmage.db().delete_character(&character.id).await?;
+                monitor::changed(archmage, ctx, campaign.id, Kind::Characters);
+                monitor::changed(archmage, ctx, campaign.id, Kind::Inventory);
+                CreateEmbed::new()
+                    .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                    .title("Character deleted")
+                    .description(format!(
+                        "`{}` is no longer part of **{}**.",
+                        character.name, campaign.name
+                    ))
+            }
+        };
+
+        component
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new()
+                        .embed(embed.timestamp(Timestamp::now()))
+                        .components(vec![]),
+                ),
+            )
+            .await
+            .map_err(|e| eyre!(e))?;
+        Ok(())
+    }
+
+    async fn done(
+        invocation: &Invocation,
+        ctx: &Context,
+        title: &str,
+        description: String,
+    ) -> CommandResult {
+        invocation
+            .respond(
+                ctx,
+                Reply::new().embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .description(description)
+                        .title(title)
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// The campaign is optional everywhere, so that channels can imply it. See
+/// [resolve::campaign]. Chat commands give it before the subcommand, as in
+/// `!campaign "Lost Mine" set`.
+fn campaign_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        "campaign",
+        "The campaign. Leave it out to use this channel's",
+    )
+}
+
+fn feature_subcommand(name: &str, description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::SubCommand, name, description)
+        .add_sub_option(
+            CreateCommandOption::new(
+                CommandOptionType::String,
+                "feature",
+                "The feature: inventory, quests or characters",
+            )
+            .required(true),
+        )
+        .add_sub_option(campaign_option())
+}
+
+fn monitor_group(kind: Kind, description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::SubCommandGroup,
+        kind.as_str(),
+        description,
+    )
+    .add_sub_option(
+        CreateCommandOption::new(
+            CommandOptionType::SubCommand,
+            "monitor",
+            "Keep them up to date in a channel",
+        )
+        .add_sub_option(channel_option())
+        .add_sub_option(campaign_option()),
+    )
+    .add_sub_option(
+        CreateCommandOption::new(
+            CommandOptionType::SubCommand,
+            "disable",
+            "Stop keeping them up to date, deleting the messages",
+        )
+        .add_sub_option(campaign_option()),
+    )
+}
+
+fn level_option() -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::Integer, "level", "The level")
+        .min_int_value(1)
+        .required(true)
+}
+
+fn channel_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::Channel,
+        "channel",
+        "The channel. Leave it out to use this one",
+    )
+    .channel_types(vec![
+        ChannelType::Text,
+        ChannelType::News,
+        ChannelType::Forum,
+        ChannelType::PublicThread,
+        ChannelType::PrivateThread,
+        ChannelType::NewsThread,
+    ])
+}
+
+/// The channel named in the `channel` option, or the one the command was
+/// used in.
+fn channel(invocation: &Invocation, options: &[CommandDataOption]) -> ChannelId {
+    options
+        .iter()
+        .find(|o| o.name == "channel")
+        .and_then(|o| o.value.as_channel_id())
+        .unwrap_or(invocation.channel_id())
+}
@@ -0,0 +1,97 @@
+//! Reading batches of tags and values such as
+//! `Name "Calmasis M. Bluthersworth"; Alignment LN`. Entries are separated by
+//! semicolons, except inside double quotes.
+use crate::command::args::{split_batch, tokenize};
+use crate::model::info::{check_value, clean_tag, Info};
+
+/// Read a batch of tags, each followed by its value. Errors are meant to be
+/// shown to the user as-is.
+pub fn info(input: &str) -> Result<Vec<Info>, String> {
+    let info = split_batch(input)
+        .iter()
+        .filter(|part| !part.trim().is_empty())
+        .map(|part| entry(part))
+        .collect::<Result<Vec<_>, _>>()?;
+    match info.is_empty() {
+        true => Err("Which tags?".to_owned()),
+        false => Ok(info),
+    }
+}
+
+/// Read a batch of tags alone, as for removing them.
+pub fn tags(input: &str) -> Result<Vec<String>, String> {
+    let tags = split_batch(input)
+        .iter()
+        .filter(|part| !part.trim().is_empty())
+        .map(|part| {
+            let words = tokenize(part)
+                .into_iter()
+                .map(|w| w.text)
+                .collect::<Vec<_>>();
+            clean_tag(&words.join(" "))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    match tags.is_empty() {
+        true => Err("Which tags?".to_owned()),
+        false => Ok(tags),
+    }
+}
+
+/// The first word of an entry is its tag, and the rest its value. A value
+/// that is a single quoted phrase loses its quotes, but otherwise values are
+/// kept as written.
+fn entry(part: &str) -> Result<Info, String> {
+    let tokens = tokenize(part);
+    let Some(first) = tokens.first() else {
+        return Err("Which tag?".to_owned());
+    };
+    let tag = clean_tag(&first.text)?;
+    let value = match &tokens[1..] {
+        [] => "",
+        [only] if only.quoted => only.text.as_str(),
+        [next, ..] => &part[next.start..],
+    };
+    Ok(Info {
+        value: check_value(&tag, value)?.to_owned(),
+        tag,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(tag: &str, value: &str) -> Info {
+        Info {
+            tag: tag.to_owned(),
+            value: value.to_owned(),
+        }
+    }
+
+    #[test]
+    fn reads_tags_and_values() {
+        assert_eq!(
+            info("Name \"Calmasis M. Bluthersworth\"; Alignment LN;\"Known  As\" Cal \"the\" Bold; Notes"),
+            Ok(vec![
+                entry("Name", "Calmasis M. Bluthersworth"),
+                entry("Alignment", "LN"),
+                entry("Known As", "Cal \"the\" Bold"),
+                entry("Notes", ""),
+            ])
+        );
+        assert_eq!(info(" ; "), Err("Which tags?".to_owned()));
+    }
+
+    #[test]
+    fn reads_tags_alone() {
+        assert_eq!(
+            tags("Alignment; \"Known  As\"; Eye Colour"),
+            Ok(vec![
+                "Alignment".to_owned(),
+                "Known As".to_owned(),
+                "Eye Colour".to_owned(),
+            ])
+        );
+        assert_eq!(tags(""), Err("Which tags?".to_owned()));
+    }
+}
@@ -0,0 +1,477 @@
+//! Character info posts: whatever players want to note about their
+//! characters, under tags of their choosing. Anyone may read them, but only a
+//! character's players and their campaign's DMs may change them.
+use eyre::eyre;
+use serenity::all::{
+    ButtonStyle, Color, CommandDataOption, CommandOptionType, ComponentInteraction,
+    CreateActionRow, CreateButton, CreateCommand, CreateCommandOption, CreateEmbed,
+    CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage, Timestamp,
+};
+use serenity::prelude::*;
+
+use crate::archmage::Archmage;
+use crate::model::character::Character;
+use crate::model::feature;
+use crate::model::info::Info;
+
+use super::access::require_player;
+use super::error::{CommandError, CommandResult};
+use super::invocation::{Invocation, Reply};
+use super::options::{self, required_string};
+use super::text::{EMBEDS_PER_MESSAGE, FIELDS_PER_EMBED, MESSAGE_EMBED_LIMIT};
+use super::{handle_fn, resolve, ArchmageCommand, CommandRegistration};
+
+mod entries;
+
+/// Values this short sit side by side, rather than taking a whole line.
+const INLINE_LENGTH: usize = 32;
+/// Room left on each page for the title, footer and image embeds' titles.
+const PAGE_HEADROOM: usize = 500;
+
+pub struct CharCommand;
+
+impl ArchmageCommand for CharCommand {
+    fn register() -> Vec<CommandRegistration> {
+        vec![CommandRegistration::new(
+            "char",
+            CreateCommand::new("char")
+                .description("Keep notes on characters, under tags of your choosing")
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "show",
+                        "Show a character's info post, or one tag of it",
+                    )
+                    .add_sub_option(character_option())
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "tag",
+                        "The tag to show. Leave it out to show the whole post",
+                    ))
+                    .add_sub_option(campaign_option()),
+                )
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommandGroup,
+                        "info",
+                        "Change a character's info post",
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "add",
+                            "Add tags to a character's info post, or change them",
+                        )
+                        .add_sub_option(character_option())
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::String,
+                                "entries",
+                                "Tags and their values, e.g. Alignment LN; Race \"Ugol Kenku\"",
+                            )
+                            .required(true),
+                        )
+                        .add_sub_option(campaign_option()),
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "remove",
+                            "Remove tags from a character's info post",
+                        )
+                        .add_sub_option(character_option())
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::String,
+                                "tags",
+                                "Tags to remove, e.g. Alignment; \"Ability Scores\"",
+                            )
+                            .required(true),
+                        )
+                        .add_sub_option(campaign_option()),
+                    ),
+                ),
+            handle_fn!(Self::run),
+        )
+        .leading("campaign")
+        .leading("character")
+        .leading("tag")
+        .default_subcommand("show")
+        .feature(feature::CHARACTERS)
+        .on_component(handle_fn!(Self::turn_page))
+        .help(
+            "Anyone may read a character's info post, but only the character's players and \
+             their campaign's DMs may change it.\n\n\
+             Each entry is a tag followed by its value, and several can be given at once by \
+             separating them with semicolons. Put tags with spaces, and values containing \
+             semicolons, in double quotes. Adding a tag that's already there changes its \
+             value. A tag can be left empty, and stays until it's removed. A value that's \
+             nothing but a link to an image is shown as the image.",
+        )
+        .example("/char show Cal")
+        .example("/char show Cal Alignment")
+        .example("/char info add Cal Name \"Calmasis M. Bluthersworth\"; Alignment LN")
+        .example("/char info remove Cal Alignment")]
+    }
+}
+
+impl CharCommand {
+    async fn run(archmage: &Archmage, invocation: &Invocation, ctx: &Context) -> CommandResult {
+        let Some((subcommand, options)) = options::subcommand(invocation.options()) else {
+            return Err(eyre!("/char used without a subcommand").into());
+        };
+        let (subcommand, options) = match subcommand {
+            "info" => {
+                options::subcommand(options).ok_or(eyre!("/char info used without a subcommand"))?
+            }
+            _ => (subcommand, options),
+        };
+
+        let campaign = resolve::campaign(archmage, invocation, ctx, options).await?;
+        let name = required_string(options, "character")?;
+        let character = resolve::character(archmage, &campaign, name).await?;
+        match subcommand {
+            "show" => Self::show(archmage, invocation, ctx, &character, options).await,
+            "add" | "remove" => {
+                let permissions = invocation.member_permissions(ctx).await?;
+                require_player(
+                    archmage,
+                    &campaign,
+                    &character,
+                    invocation.user().id,
+                    permissions,
+                )
+                .await?;
+                Self::change(archmage, invocation, ctx, subcommand, &character, options).await
+            }
+            other => Err(eyre!("Unknown /char subcommand {other}").into()),
+        }
+    }
+
+    async fn show(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        character: &Character,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let info = archmage.db().character_info(&character.id).await?;
+        let (embeds, components) = match options::string(options, "tag") {
+            Some(tag) => {
+                let tag = tag.split_whitespace().collect::<Vec<_>>().join(" ");
+                let Some(entry) = info.iter().find(|i| i.tag.eq_ignore_ascii_case(&tag)) else {
+                    return Err(CommandError::not_found(format!(
+                        "`{}` has no **{tag}** tag.",
+                        character.name
+                    )));
+                };
+                (vec![single(character, entry)], Vec::new())
+            }
+            None => render(character, &info, 0),
+        };
+
+        let mut reply = Reply::new().components(components);
+        for embed in embeds {
+            reply = reply.embed(embed);
+        }
+        invocation.respond(ctx, reply).await?;
+        Ok(())
+    }
+
+    async fn change(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        subcommand: &str,
+        character: &Character,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let description = match subcommand {
+            "add" => {
+                let info = entries::info(required_string(options, "entries")?)
+                    .map_err(CommandError::user)?;
+                let tags = list(info.iter().map(|i| i.tag.as_str()));
+                archmage
+                    .db()
+                    .set_info(&character.id, info, &invocation.user().id.get())
+                    .await?;
+                format!("Set {tags}.")
+            }
+            _ => {
+                let tags =
+                    entries::tags(required_string(options, "tags")?).map_err(CommandError::user)?;
+                let removed = archmage
+                    .db()
+                    .remove_info(&character.id, tags, &invocation.user().id.get())
+                    .await?;
+                if removed.is_empty() {
+                    return Err(CommandError::not_found(format!(
+                        "`{}` has no such tags.",
+                        character.name
+                    )));
+                }
+                format!("Removed {}.", list(removed.iter().map(String::as_str)))
+            }
+        };
+
+        invocation
+            .respond(
+                ctx,
+                Reply::new().embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .title(format!("Character information for {}", character.name))
+                        .description(description)
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Handles the previous/next page buttons of long posts. Their custom IDs
+    /// look like `char:<page>:<character row ID>`.
+    async fn turn_page(
+        archmage: &Archmage,
+        component: &ComponentInteraction,
+        ctx: &Context,
+    ) -> CommandResult {
+        let mut parts = component.data.custom_id.split(':').skip(1);
+        let (Some(page), Some(id)) = (
+            parts.next().and_then(|p| p.parse::<usize>().ok()),
+            parts.next().and_then(|id| id.parse::<i64>().ok()),
+        ) else {
+            return Err(eyre!("Malformed character page ID '{}'", component.data.custom_id).into());
+        };
+        let Some(character) = archmage.db().character_by_id(&id).await? else {
+            return Err(CommandError::not_found(
+                "That character has since been removed.",
+            ));
+        };
+        // Posts can be paged wherever they were sent, not only in channels
+        // bound to the character's campaign.
+        if let Some(campaign) = archmage.db().campaign_by_id(&character.campaign_id).await? {
+            archmage
+                .commands()
+                .require_feature(archmage, "char", &campaign)
+                .await?;
+        }
+
+        let info = archmage.db().character_info(&character.id).await?;
+        let (embeds, components) = render(&character, &info, page);
+        component
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new()
+                        .embeds(embeds)
+                        .components(components),
+                ),
+            )
+            .await
+            .map_err(|e| eyre!(e))?;
+        Ok(())
+    }
+}
+
+/// Render one page of a character's post: an embed with a field for each tag,
+/// followed by one for each image. Long posts are split into pages, so that
+/// each fits in a single message.
+fn render(
+    character: &Character,
+    info: &[Info],
+    page: usize,
+) -> (Vec<CreateEmbed>, Vec<CreateActionRow>) {
+    let title = format!("Character information for {}", character.name);
+    if info.is_empty() {
+        let embed = CreateEmbed::new()
+            .color(Color::from_rgb(0x00, 0xFF, 0x00))
+            .title(title)
+            .description("Nothing yet. Add some with `/char info add`.")
+            .timestamp(Timestamp::now());
+        return (vec![embed], Vec::new());
+    }
+
+    let pages = paginate(info);
+    let page = page.min(pages.len() - 1);
+    let (images, fields): (Vec<&Info>, Vec<&Info>) =
+        pages[page].iter().partition(|i| i.image().is_some());
+
+    let mut embed = CreateEmbed::new()
+        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+        .title(title)
+        .timestamp(Timestamp::now());
+    for entry in fields {
+        let inline = entry.value.chars().count() <= INLINE_LENGTH && !entry.value.contains('\n');
+        embed = embed.field(&entry.tag, shown(&entry.value), inline);
+    }
+    if pages.len() > 1 {
+        embed = embed.footer(CreateEmbedFooter::new(format!(
+            "Page {} of {}",
+            page + 1,
+            pages.len()
+        )));
+    }
+    let mut embeds = vec![embed];
+    embeds.extend(
+        images
+            .into_iter()
+            .filter_map(|entry| Some(image(&entry.tag, entry.image()?))),
+    );
+
+    if pages.len() == 1 {
+        return (embeds, Vec::new());
+    }
+    let id = |target: usize| format!("char:{target}:{}", character.id);
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(id(page.saturating_sub(1)))
+            .style(ButtonStyle::Secondary)
+            .label("Previous")
+            .disabled(page == 0),
+        CreateButton::new(id(page + 1))
+            .style(ButtonStyle::Secondary)
+            .label("Next")
+            .disabled(page + 1 == pages.len()),
+    ]);
+    (embeds, vec![buttons])
+}
+
+/// Split a post into pages that each fit in one message: no more fields than
+/// an embed can hold, no more images than the message has embeds to spare,
+/// and not too much text in all.
+fn paginate(info: &[Info]) -> Vec<Vec<&Info>> {
+    let mut pages = Vec::new();
+    let mut page = Vec::new();
+    let (mut fields, mut images, mut length) = (0, 0, 0);
+    for entry in info {
+        let is_image = entry.image().is_some();
+        let entry_length = entry.tag.chars().count() + shown(&entry.value).chars().count();
+        let full = match is_image {
+            true => images + 1 >= EMBEDS_PER_MESSAGE,
+            false => fields == FIELDS_PER_EMBED,
+        };
+        // An entry too long for any page still gets one of its own.
+        let overflows = length + entry_length > MESSAGE_EMBED_LIMIT - PAGE_HEADROOM;
+        if !page.is_empty() && (full || overflows) {
+            pages.push(std::mem::take(&mut page));
+            (fields, images, length) = (0, 0, 0);
+        }
+        match is_image {
+            true => images += 1,
+            false => fields += 1,
+        }
+        length += entry_length;
+        page.push(entry);
+    }
+    pages.push(page);
+    pages
+}
+
+/// A single tag of a character's post.
+fn single(character: &Character, entry: &Info) -> CreateEmbed {
+    let embed = CreateEmbed::new()
+        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+        .title(&character.name)
+        .timestamp(Timestamp::now());
+    match entry.image() {
+        Some(url) => embed.description(format!("**{}**", entry.tag)).image(url),
+        None => embed.description(format!("**{}**: {}", entry.tag, shown(&entry.value))),
+    }
+}
+
+/// A tag whose value is an image, as an embed of its own.
+fn image(tag: &str, url: &str) -> CreateEmbed {
+    CreateEmbed::new()
+        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+        .title(tag)
+        .image(url)
+}
+
+/// Discord won't show an empty field, so empty tags need something there.
+fn shown(value: &str) -> &str {
+    match value.is_empty() {
+        true => "*Empty*",
+        false => value,
+    }
+}
+
+/// Tags in bold, separated by commas.
+fn list<'a>(tags: impl Iterator<Item = &'a str>) -> String {
+    tags.map(|tag| format!("**{tag}**"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Chat commands give the character before the subcommand, as in
+/// `!char Cal info add Alignment LN`.
+fn character_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        "character",
+        "The character's short ID",
+    )
+    .required(true)
+}
+
+fn campaign_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        "campaign",
+        "The campaign. Leave it out to use this channel's",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(count: usize, value: &str) -> Vec<Info> {
+        (0..count)
+            .map(|i| Info {
+                tag: format!("Tag {i}"),
+                value: value.to_owned(),
+            })
+            .collect()
+    }
+
+    fn sizes(pages: &[Vec<&Info>]) -> Vec<usize> {
+        pages.iter().map(Vec::len).collect()
+    }
+
+    #[test]
+    fn empty_post() {
+        assert_eq!(sizes(&paginate(&[])), [0]);
+    }
+
+    #[test]
+    fn fields_per_page() {
+        let full = post(FIELDS_PER_EMBED, "LN");
+        assert_eq!(sizes(&paginate(&full)), [FIELDS_PER_EMBED]);
+        let over = post(FIELDS_PER_EMBED + 1, "LN");
+        assert_eq!(sizes(&paginate(&over)), [FIELDS_PER_EMBED, 1]);
+    }
+
+    #[test]
+    fn images_per_page() {
+        // One embed is kept for the fields.
+        let images = post(EMBEDS_PER_MESSAGE, "https://example.com/cal.png");
+        assert_eq!(sizes(&paginate(&images)), [EMBEDS_PER_MESSAGE - 1, 1]);
+    }
+
+    #[test]
+    fn length_per_page() {
+        let long = "x".repeat(1000);
+        let fit = (MESSAGE_EMBED_LIMIT - PAGE_HEADROOM) / 1010;
+        let post = post(fit + 1, &long);
+        assert_eq!(sizes(&paginate(&post)), [fit, 1]);
+    }
+
+    #[test]
+    fn entry_longer_than_a_page() {
+        let huge = post(1, &"x".repeat(MESSAGE_EMBED_LIMIT));
+        assert_eq!(sizes(&paginate(&huge)), [1]);
+        let mut post = post(2, "LN");
+        post.insert(1, huge[0].clone());
+        assert_eq!(sizes(&paginate(&post)), [1, 1, 1]);
+    }
+}
@@ -0,0 +1,555 @@
+//! Server-wide settings and campaign administration, which only server
+//! administrators may change.
+use eyre::eyre;
+use serenity::all::{
+    ButtonStyle, Color, CommandDataOption, CommandOptionType, ComponentInteraction,
+    CreateActionRow, CreateButton, CreateCommand, CreateCommandOption, CreateEmbed,
+    CreateInteractionResponse, CreateInteractionResponseMessage, GuildId, Permissions, Timestamp,
+};
+use serenity::prelude::*;
+
+use crate::archmage::Archmage;
+use crate::model::campaign::Campaign;
+
+use super::error::{CommandError, CommandResult};
+use super::invocation::{Invocation, Reply};
+use super::options::{self, required_string, required_user};
+use super::prefix::DEFAULT_PREFIX;
+use super::text::{embed_length, truncate, FIELDS_PER_EMBED, FIELD_LIMIT, MESSAGE_EMBED_LIMIT};
+use super::{handle_fn, resolve, ArchmageCommand, CommandRegistration};
+
+pub struct ConfigCommand;
+
+/// Prefixes longer than this are more likely typos than intentional.
+const MAX_PREFIX_LENGTH: usize = 5;
+/// Room left in the list of campaigns for saying how many it leaves out.
+const NOTE_HEADROOM: usize = 64;
+
+impl ArchmageCommand for ConfigCommand {
+    fn register() -> Vec<CommandRegistration> {
+        vec![CommandRegistration::new(
+            "config",
+            CreateCommand::new("config")
+                .description("Configure Archmage for this server")
+                .default_member_permissions(Permissions::ADMINISTRATOR)
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "prefix",
+                        "Set the prefix for chat commands",
+                    )
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "prefix",
+                        "The new prefix. Leave it out to go back to the default",
+                    )),
+                )
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "dm",
+                        "Make someone a DM of a campaign, starting it if it doesn't exist",
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::User, "user", "The new DM")
+                            .required(true),
+                    )
+                    .add_sub_option(campaign_option()),
+                )
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "remove",
+                        "Remove a DM from a campaign, or without a DM, delete the campaign",
+                    )
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::User,
+                        "user",
+                        "The DM to remove. Leave it out to delete the whole campaign",
+                    ))
+                    .add_sub_option(campaign_option()),
+                )
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommandGroup,
+                        "campaign",
+                        "Manage this server's campaigns",
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "create",
+                            "Start a new campaign",
+                        )
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::String,
+                                "name",
+                                "The campaign's name",
+                            )
+                            .required(true),
+                        ),
+                    )
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "list",
+                        "List this server's campaigns, their DMs and their channels",
+                    ))
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "rename",
+                            "Rename a campaign",
+                        )
+                        .add_sub_option(campaign_option())
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::String,
+                                "name",
+                                "The campaign's new name",
+                            )
+                            .required(true),
+                        ),
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "delete",
+                            "Delete a campaign and everything in it",
+                        )
+                        .add_sub_option(campaign_option()),
+                    ),
+                ),
+            handle_fn!(Self::run),
+        )
+        .help(
+            "Only server administrators may use this command. Deleting a campaign can't be \
+             undone, so you'll be asked to confirm it first. Removing a campaign's last DM \
+             doesn't delete it.",
+        )
+        .example("/config prefix ?")
+        .example("/config prefix")
+        .example("/config dm @Alice Lost Mine")
+        .example("/config remove @Alice Lost Mine")
+        .example("/config campaign rename Phandelver Lost Mine")
+        .on_component(handle_fn!(Self::confirm_delete))]
+    }
+}
+
+impl ConfigCommand {
+    async fn run(archmage: &Archmage, invocation: &Invocation, ctx: &Context) -> CommandResult {
+        let Some(guild_id) = invocation.guild_id() else {
+            return Err(CommandError::user("Archmage can only be configured in a server."));
+        };
+
+        let is_admin = invocation
+            .member_permissions(ctx)
+            .await?
+            .is_some_and(|p| p.administrator());
+        if !is_admin {
+            return Err(CommandError::permission(
+                "Only server administrators may configure Archmage.",
+            ));
+        }
+
+        let Some((subcommand, options)) = options::subcommand(invocation.options()) else {
+            return Err(eyre!("/config used without a subcommand").into());
+        };
+        match subcommand {
+            "prefix" => Self::prefix(archmage, invocation, ctx, guild_id.get(), options).await,
+            "dm" => Self::add_dm(archmage, invocation, ctx, guild_id, options).await,
+            "remove" => Self::remove(archmage, invocation, ctx, guild_id, options).await,
+            "campaign" => {
+                let Some((subcommand, options)) = options::subcommand(options) else {
+                    return Err(eyre!("/config campaign used without a subcommand").into());
+                };
+                match subcommand {
+                    "create" => Self::create(archmage, invocation, ctx, guild_id, options).await,
+                    "list" => Self::list(archmage, invocation, ctx, guild_id).await,
+                    "rename" => Self::rename(archmage, invocation, ctx, guild_id, options).await,
+                    "delete" => {
+                        let campaign = find_campaign(archmage, guild_id, options).await?;
+                        Self::ask_delete(invocation, ctx, &campaign).await
+                    }
+                    other => Err(eyre!("Unknown /config campaign subcommand {other}").into()),
+                }
+            }
+            other => Err(eyre!("Unknown /config subcommand {other}").into()),
+        }
+    }
+
+    async fn prefix(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        guild_id: u64,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let prefix = options::string(options, "prefix")
+            .map(str::trim)
+            .filter(|p| !p.is_empty());
+
+        if let Some(prefix) = prefix {
+            if prefix.chars().count() > MAX_PREFIX_LENGTH || prefix.contains(char::is_whitespace) {
+                return Err(CommandError::user(format!(
+                    "Prefixes may be at most {MAX_PREFIX_LENGTH} characters long, with no spaces."
+                )));
+            }
+        }
+
+        archmage.db().set_prefix(&guild_id, prefix).await?;
+        Self::done(
+            invocation,
+            ctx,
+            "Prefix updated",
+            format!(
+                "Chat commands in this server now start with `{}`.",
+                prefix.unwrap_or(DEFAULT_PREFIX)
+            ),
+        )
+        .await
+    }
+
+    async fn add_dm(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        guild_id: GuildId,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let user = required_user(options, "user")?;
+        let name = Campaign::clean_name(required_string(options, "campaign")?)
+            .map_err(CommandError::user)?;
+
+        let (campaign, created) = archmage.db().ensure_campaign(&guild_id.get(), &name).await?;
+        if !archmage.db().add_dm(&campaign.id, &user.get()).await? {
+            return Err(CommandError::user(format!(
+                "<@{user}> is already a DM of **{}**.",
+                campaign.name
+            )));
+        }
+
+        let started = match created {
+            true => format!("Started **{}**. ", campaign.name),
+            false => String::new(),
+        };
+        Self::done(
+            invocation,
+            ctx,
+            "DM added",
+            format!("{started}<@{user}> is now a DM of **{}**.", campaign.name),
+        )
+        .await
+    }
+
+    /// Removes a DM if one is given, and otherwise asks to delete the whole
+    /// campaign, as CMDAPI.md's `!config remove` does.
+    async fn remove(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        guild_id: GuildId,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let campaign = find_campaign(archmage, guild_id, options).await?;
+        let Some(user) = options::user(options, "user") else {
+            return Self::ask_delete(invocation, ctx, &campaign).await;
+        };
+
+        if !archmage.db().remove_dm(&campaign.id, &user.get()).await? {
+            return Err(CommandError::not_found(format!(
+                "<@{user}> isn't a DM of **{}**.",
+                campaign.name
+            )));
+        }
+        Self::done(
+            invocation,
+            ctx,
+            "DM removed",
+            format!("<@{user}> is no longer a DM of **{}**.", campaign.name),
+        )
+        .await
+    }
+
+    async fn create(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        guild_id: GuildId,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let name =
+            Campaign::clean_name(required_string(options, "name")?).map_err(CommandError::user)?;
+        let Some(campaign) = archmage.db().create_campaign(&guild_id.get(), &name).await? else {
+            return Err(CommandError::user(format!(
+                "This server already has a campaign called **{name}**."
+            )));
+        };
+        Self::done(
+            invocation,
+            ctx,
+            "Campaign started",
+            format!(
+                "Started **{}**. Use `/config dm` to give it a DM.",
+                campaign.name
+            ),
+        )
+        .await
+    }
+
+    async fn list(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        guild_id: GuildId,
+    ) -> CommandResult {
+        let campaigns = archmage.db().campaigns(&guild_id.get()).await?;
+        let mut entries = Vec::new();
+        for campaign in campaigns.iter().take(FIELDS_PER_EMBED) {
+            let dms = archmage.db().dms(&campaign.id).await?;
+            let channels = archmage.db().campaign_channels(&campaign.id).await?;
+            let dms = mentions(&dms, "<@", "No DMs");
+            let channels = mentions(&channels, "<#", "No channels");
+            entries.push((campaign.name.clone(), format!("{dms}\n{channels}")));
+        }
+
+        let embed = listing(entries, campaigns.len());
+        invocation.respond(ctx, Reply::new().embed(embed)).await?;
+        Ok(())
+    }
+
+    async fn rename(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        guild_id: GuildId,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let campaign = find_campaign(archmage, guild_id, options).await?;
+        let name =
+            Campaign::clean_name(required_string(options, "name")?).map_err(CommandError::user)?;
+        if !archmage.db().rename_campaign(&campaign.id, &name).await? {
+            return Err(CommandError::user(format!(
+                "This server already has a campaign called **{name}**."
+            )));
+        }
+        Self::done(
+            invocation,
+            ctx,
+            "Campaign renamed",
+            format!("**{}** is now called **{name}**.", campaign.name),
+        )
+        .await
+    }
+
+    /// Deleting a campaign can't be undone, so ask first. The buttons are
+    /// handled by [Self::confirm_delete].
+    async fn ask_delete(invocation: &Invocation, ctx: &Context, campaign: &Campaign) -> CommandResult {
+        let buttons = CreateActionRow::Buttons(vec![
+            CreateButton::new(format!("config:delete:{}", campaign.id))
+                .label("Delete")
+                .style(ButtonStyle::Danger),
+            CreateButton::new("config:cancel")
+                .label("Cancel")
+                .style(ButtonStyle::Secondary),
+        ]);
+        invocation
+            .respond(
+                ctx,
+                Reply::new()
+                    .ephemeral(true)
+                    .embed(
+                        CreateEmbed::new()
+                            .color(Color::from_rgb(0xFF, 0xA5, 0x00))
+                            .title(format!("Delete {}?", campaign.name))
+                            .description(
+                                "Everything Archmage keeps about this campaign will be deleted, \
+                                 with no way to get it back. To remove a single DM instead, \
+                                 name them in `/config remove`.",
+                            )
+                            .timestamp(Timestamp::now()),
+                    )
+                    .components(vec![buttons]),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Handles the buttons from [Self::ask_delete]. Their custom IDs look like
+    /// `config:delete:<campaign ID>` or `config:cancel`. Chat command replies
+    /// can be seen by everyone, so whoever presses a button is checked again.
+    async fn confirm_delete(
+        archmage: &Archmage,
+        component: &ComponentInteraction,
+        ctx: &Context,
+    ) -> CommandResult {
+        let is_admin = component
+            .member
+            .as_ref()
+            .and_then(|m| m.permissions)
+            .is_some_and(|p| p.administrator());
+        if !is_admin {
+            return Err(CommandError::permission(
+                "Only server administrators may configure Archmage.",
+            ));
+        }
+
+        let embed = match component.data.custom_id.strip_prefix("config:delete:") {
+            None if component.data.custom_id == "config:cancel" => CreateEmbed::new()
+                .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                .title("Cancelled")
+                .description("Nothing was deleted."),
+            None => {
+                return Err(eyre!("Malformed config button ID '{}'", component.data.custom_id).into())
+            }
+            Some(id) => {
+                let id = id
+                    .parse::<i64>()
+                    .map_err(|_| eyre!("Malformed campaign ID in '{}'", component.data.custom_id))?;
+                let campaign = archmage
+                    .db()
+                    .campaign_by_id(&id)
+                    .await?
+                    .filter(|c| Some(GuildId::new(c.guild_id)) == component.guild_id)
+                    .ok_or(CommandError::not_found("That campaign has already been deleted."))?;
+                let _ = archmage.db().delete_campaign(&campaign.id).await?;
+                CreateEmbed::new()
+                    .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                    .title("Campaign deleted")
+                    .description(format!("**{}** is gone for good.", campaign.name))
+            }
+        };
+
+        component
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new()
+                        .embed(embed.timestamp(Timestamp::now()))
+                        .components(vec![]),
+                ),
+            )
+            .await
+            .map_err(|e| eyre!(e))?;
+        Ok(())
+    }
+
+    async fn done(
+        invocation: &Invocation,
+        ctx: &Context,
+        title: &str,
+        description: String,
+    ) -> CommandResult {
+        invocation
+            .respond(
+                ctx,
+                Reply::new().embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .description(description)
+                        .title(title)
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// The list of campaigns, given each one's name and what to say about it,
+/// out of how many there are. As many are listed as fit in one embed.
+fn listing(entries: Vec<(String, String)>, total: usize) -> CreateEmbed {
+    let mut embed = CreateEmbed::new()
+        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+        .title("Campaigns")
+        .timestamp(Timestamp::now());
+    if total == 0 {
+        return embed.description("This server has no campaigns yet.");
+    }
+
+    let mut shown = 0;
+    for (name, body) in entries.into_iter().take(FIELDS_PER_EMBED) {
+        let candidate = embed
+            .clone()
+            .field(name, truncate(&body, FIELD_LIMIT), false);
+        if embed_length(&candidate) + NOTE_HEADROOM > MESSAGE_EMBED_LIMIT {
+            break;
+        }
+        embed = candidate;
+        shown += 1;
+    }
+    match shown < total {
+        true => embed.description(format!(
+            "Only the first {shown} of {total} campaigns are shown."
+        )),
+        false => embed,
+    }
+}
+
+/// Mention each of a list of users or channels, or say there are none.
+fn mentions(ids: &[u64], prefix: &str, none: &str) -> String {
+    match ids.is_empty() {
+        true => none.to_owned(),
+        false => ids
+            .iter()
+            .map(|id| format!("{prefix}{id}>"))
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// Campaigns are chosen by name. Where it comes last, chat commands can take
+/// names with spaces without quoting them.
+fn campaign_option() -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::String, "campaign", "The campaign's name")
+        .required(true)
+}
+
+/// The campaign named in the `campaign` option.
+async fn find_campaign(
+    archmage: &Archmage,
+    guild_id: GuildId,
+    options: &[CommandDataOption],
+) -> CommandResult<Campaign> {
+    resolve::named(archmage, guild_id, required_string(options, "campaign")?).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(count: usize, length: usize) -> Vec<(String, String)> {
+        (1..=count)
+            .map(|i| (format!("Campaign {i}"), "x".repeat(length)))
+            .collect()
+    }
+
+    #[test]
+    fn lists_every_campaign_that_fits() {
+        let json = serde_json::to_value(listing(entries(3, 10), 3)).unwrap();
+        assert!(json["description"].is_null());
+        assert_eq!(json["fields"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn stops_before_the_message_is_too_long() {
+        let embed = listing(entries(FIELDS_PER_EMBED, 2 * FIELD_LIMIT), 40);
+        let json = serde_json::to_value(&embed).unwrap();
+        let shown = json["fields"].as_array().unwrap().len();
+        assert!(shown < FIELDS_PER_EMBED);
+        assert_eq!(
+            json["description"],
+            format!("Only the first {shown} of 40 campaigns are shown.")
+        );
+        assert!(embed_length(&embed) <= MESSAGE_EMBED_LIMIT);
+    }
+
+    #[test]
+    fn says_when_there_are_none() {
+        let embed = listing(Vec::new(), 0);
+        assert_eq!(
+            serde_json::to_value(&embed).unwrap()["description"],
+            "This server has no campaigns yet."
+        );
+    }
+}
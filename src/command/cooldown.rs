@@ -0,0 +1,259 @@
+//! Rate limiting for commands, so that a single player (or a whole channel or
+//! guild) can't flood a channel with responses and run Archmage into Discord's
+//! own rate limits.
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use hashbrown::HashMap;
+use serde::Deserialize;
+
+use super::invocation::Invocation;
+
+/// The group of invocations a [Cooldown] counts against.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum CooldownScope {
+    /// Each user has their own bucket.
+    User,
+    /// Everyone in a channel shares a bucket.
+    Channel,
+    /// Everyone in a guild shares a bucket.
+    Guild,
+}
+
+/// Allows a command to be run at most `uses` times every `seconds` seconds
+/// per bucket of the given scope.
+///
+/// Cooldowns can be given a default when a command is registered, and can be
+/// overridden per-command in `secret/config.toml`:
+///
+/// ```toml
+/// [[cooldowns.roll]]
+/// scope = "user"
+/// uses = 3
+/// seconds = 10
+/// ```
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cooldown {
+    pub scope: CooldownScope,
+    pub uses: u32,
+    pub seconds: u64,
+}
+
+impl Cooldown {
+    /// Limit each user to `uses` invocations every `seconds` seconds.
+    pub const fn per_user(uses: u32, seconds: u64) -> Self {
+        Self {
+            scope: CooldownScope::User,
+            uses,
+            seconds,
+        }
+    }
+
+    /// Limit each channel to `uses` invocations every `seconds` seconds.
+    pub const fn per_channel(uses: u32, seconds: u64) -> Self {
+        Self {
+            scope: CooldownScope::Channel,
+            uses,
+            seconds,
+        }
+    }
+
+    fn window(&self) -> Duration {
+        Duration::from_secs(self.seconds)
+    }
+
+    /// The ID of the bucket this invocation falls into. Guild-scoped
+    /// cooldowns fall back to the channel when run outside of a guild.
+    fn bucket(&self, invocation: &Invocation) -> u64 {
+        match self.scope {
+            CooldownScope::User => invocation.user().id.get(),
+            CooldownScope::Channel => invocation.channel_id().get(),
+            CooldownScope::Guild => invocation
+                .guild_id()
+                .map(|g| g.get())
+                .unwrap_or(invocation.channel_id().get()),
+        }
+    }
+}
+
+/// How many buckets may accumulate before expired ones are swept out.
+const SWEEP_THRESHOLD: usize = 1024;
+
+#[derive(Debug)]
+struct Bucket {
+    window: Duration,
+    hits: VecDeque<Instant>,
+}
+
+impl Bucket {
+    /// Drop every hit that has fallen out of the window.
+    fn expire(&mut self, now: Instant) {
+        while let Some(hit) = self.hits.front() {
+            if now.duration_since(*hit) < self.window {
+                break;
+            }
+            let _ = self.hits.pop_front();
+        }
+    }
+}
+
+/// Keeps track of recent invocations for every cooldown bucket.
+///
+/// Buckets are kept per cooldown, not just per scope, so that two cooldowns
+/// of the same scope don't share one, and a cooldown changed by a config
+/// reload starts afresh rather than keeping the old window.
+#[derive(Debug, Default)]
+pub struct CooldownTracker {
+    buckets: Mutex<HashMap<(String, Cooldown, u64), Bucket>>,
+}
+
+impl CooldownTracker {
+    /// Record an invocation against all of `cooldowns`. Commands that share
+    /// cooldowns, like `roll` and `r`, pass the same `group`.
+    ///
+    /// If any bucket is already full, nothing is recorded and the time until
+    /// the command may be run again is returned instead.
+    pub fn check(
+        &self,
+        group: &str,
+        cooldowns: &[Cooldown],
+        invocation: &Invocation,
+    ) -> Option<Duration> {
+        let buckets: Vec<_> = cooldowns
+            .iter()
+            .map(|cooldown| (*cooldown, cooldown.bucket(invocation)))
+            .collect();
+        self.hit(group, &buckets, Instant::now())
+    }
+
+    /// [CooldownTracker::check], for cooldowns paired with the bucket the
+    /// invocation falls into for each.
+    fn hit(&self, group: &str, cooldowns: &[(Cooldown, u64)], now: Instant) -> Option<Duration> {
+        if cooldowns.is_empty() {
+            return None;
+        }
+
+        let mut buckets = self.buckets.lock().expect("Cooldown tracker poisoned");
+
+        if buckets.len() > SWEEP_THRESHOLD {
+            buckets.retain(|_, bucket| {
+                bucket.expire(now);
+                !bucket.hits.is_empty()
+            });
+        }
+
+        let mut wait: Option<Duration> = None;
+        for &(cooldown, id) in cooldowns {
+            let bucket = buckets
+                .entry((group.to_owned(), cooldown, id))
+                .or_insert_with(|| Bucket {
+                    window: cooldown.window(),
+                    hits: VecDeque::new(),
+                });
+            bucket.expire(now);
+
+            if bucket.hits.len() >= cooldown.uses as usize {
+                let remaining = bucket
+                    .hits
+                    .front()
+                    .map(|first| (*first + bucket.window).saturating_duration_since(now))
+                    .unwrap_or_default();
+                wait = Some(wait.map_or(remaining, |w| w.max(remaining)));
+            }
+        }
+
+        if wait.is_some() {
+            return wait;
+        }
+
+        for &(cooldown, id) in cooldowns {
+            if let Some(bucket) = buckets.get_mut(&(group.to_owned(), cooldown, id)) {
+                bucket.hits.push_back(now);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: u64 = 1;
+    const BOB: u64 = 2;
+
+    fn user(uses: u32, seconds: u64, id: u64) -> (Cooldown, u64) {
+        (Cooldown::per_user(uses, seconds), id)
+    }
+
+    #[test]
+    fn fills_the_window() {
+        let tracker = CooldownTracker::default();
+        let start = Instant::now();
+        let later = start + Duration::from_secs(4);
+        assert_eq!(tracker.hit("roll", &[user(2, 10, ALICE)], start), None);
+        assert_eq!(tracker.hit("roll", &[user(2, 10, ALICE)], later), None);
+        assert_eq!(
+            tracker.hit("roll", &[user(2, 10, ALICE)], later),
+            Some(Duration::from_secs(6))
+        );
+    }
+
+    #[test]
+    fn expires() {
+        let tracker = CooldownTracker::default();
+        let start = Instant::now();
+        assert_eq!(tracker.hit("roll", &[user(1, 10, ALICE)], start), None);
+        let almost = start + Duration::from_secs(9);
+        assert_eq!(
+            tracker.hit("roll", &[user(1, 10, ALICE)], almost),
+            Some(Duration::from_secs(1))
+        );
+        let after = start + Duration::from_secs(10);
+        assert_eq!(tracker.hit("roll", &[user(1, 10, ALICE)], after), None);
+    }
+
+    #[test]
+    fn scopes_are_separate() {
+        let tracker = CooldownTracker::default();
+        let now = Instant::now();
+        let channel = (Cooldown::per_channel(1, 10), ALICE);
+        assert_eq!(tracker.hit("roll", &[user(1, 10, ALICE)], now), None);
+        assert_eq!(tracker.hit("roll", &[user(1, 10, BOB)], now), None);
+        assert_eq!(tracker.hit("roll", &[channel], now), None);
+        assert!(tracker.hit("roll", &[user(1, 10, ALICE)], now).is_some());
+        assert_eq!(tracker.hit("help", &[user(1, 10, ALICE)], now), None);
+    }
+
+    #[test]
+    fn cooldowns_are_separate() {
+        let tracker = CooldownTracker::default();
+        let start = Instant::now();
+        let (short, long) = (user(1, 1, ALICE), user(2, 60, ALICE));
+        assert_eq!(tracker.hit("roll", &[short, long], start), None);
+        let later = start + Duration::from_secs(1);
+        assert_eq!(tracker.hit("roll", &[short, long], later), None);
+        let later = start + Duration::from_secs(2);
+        assert_eq!(
+            tracker.hit("roll", &[short, long], later),
+            Some(Duration::from_secs(58))
+        );
+
+        // As after a config reload that changes the window.
+        assert_eq!(tracker.hit("roll", &[user(2, 5, ALICE)], later), None);
+    }
+
+    #[test]
+    fn nothing_is_recorded_when_full() {
+        let tracker = CooldownTracker::default();
+        let now = Instant::now();
+        let (user, channel) = (user(1, 10, ALICE), (Cooldown::per_channel(2, 10), ALICE));
+        assert_eq!(tracker.hit("roll", &[user, channel], now), None);
+        assert!(tracker.hit("roll", &[user, channel], now).is_some());
+        assert_eq!(tracker.hit("roll", &[channel], now), None);
+    }
+}
@@ -0,0 +1,119 @@
+//! The ways a command can fail. Most failures are the user's to fix, and are
+//! explained to them without any fuss. Anything else is Archmage's fault, and
+//! is logged with an error code the user can pass along.
+use std::fmt;
+
+use eyre::eyre;
+use serenity::all::{
+    Color, ComponentInteraction, Context, CreateEmbed, CreateInteractionResponse,
+    CreateInteractionResponseFollowup, CreateInteractionResponseMessage, Timestamp,
+};
+
+/// What a command handler returns.
+pub type CommandResult<T = ()> = Result<T, CommandError>;
+
+/// Why a command failed.
+#[derive(Debug)]
+pub enum CommandError {
+    /// The user asked for something that doesn't make sense, like `/roll 1d`.
+    User(String),
+    /// The user isn't allowed to do that.
+    Permission(String),
+    /// The user asked about something that doesn't exist.
+    NotFound(String),
+    /// The command is part of a feature turned off in the campaign.
+    Disabled(String),
+    /// Something went wrong that the user can't do anything about.
+    Internal(eyre::Report),
+}
+
+impl CommandError {
+    pub fn user(message: impl Into<String>) -> Self {
+        Self::User(message.into())
+    }
+
+    pub fn permission(message: impl Into<String>) -> Self {
+        Self::Permission(message.into())
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::NotFound(message.into())
+    }
+
+    pub fn disabled(message: impl Into<String>) -> Self {
+        Self::Disabled(message.into())
+    }
+
+    /// How the error is shown to the user, or None for internal errors, which
+    /// are reported by [crate::archmage::Archmage::send_terminal_error_message]
+    /// instead.
+    pub fn embed(&self) -> Option<CreateEmbed> {
+        let (title, message, color) = match self {
+            Self::User(message) => ("That didn't work", message, Color::from_rgb(0xFF, 0xA5, 0x00)),
+            Self::Permission(message) => ("Permission denied", message, Color::from_rgb(0xFF, 0x00, 0x00)),
+            Self::NotFound(message) => ("Not found", message, Color::from_rgb(0xFF, 0xA5, 0x00)),
+            Self::Disabled(message) => ("Disabled", message, Color::from_rgb(0xFF, 0xA5, 0x00)),
+            Self::Internal(_) => return None,
+        };
+        Some(
+            CreateEmbed::new()
+                .color(color)
+                .description(message)
+                .title(title)
+                .timestamp(Timestamp::now()),
+        )
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::User(message)
+            | Self::Permission(message)
+            | Self::NotFound(message)
+            | Self::Disabled(message) => f.write_str(message),
+            Self::Internal(report) => write!(f, "{report}"),
+        }
+    }
+}
+
+/// Anything that goes wrong inside a handler without being explained to the
+/// user is Archmage's fault, so `?` works on any eyre result.
+impl From<eyre::Report> for CommandError {
+    fn from(report: eyre::Report) -> Self {
+        Self::Internal(report)
+    }
+}
+
+/// Privately reply to a component interaction, following up instead if its
+/// handler had already responded.
+pub async fn reply_to_component(
+    component: &ComponentInteraction,
+    ctx: &Context,
+    embed: CreateEmbed,
+) -> eyre::Result<()> {
+    let response = component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .ephemeral(true)
+                    .embed(embed.clone()),
+            ),
+        )
+        .await;
+    if response.is_ok() {
+        return Ok(());
+    }
+
+    component
+        .create_followup(
+            &ctx.http,
+            CreateInteractionResponseFollowup::new()
+                .ephemeral(true)
+                .embed(embed),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| eyre!(e))
+}
@@ -0,0 +1,339 @@
+//! Help for every registered command. Everything shown here is generated from
+//! the definitions sent to Discord, plus any help text and examples given at
+//! registration, so it can't fall out of date with the commands themselves.
+use eyre::eyre;
+use serde_json::Value;
+use serenity::all::{
+    ButtonStyle, Color, CommandOptionType, ComponentInteraction,
+    CreateActionRow, CreateButton, CreateCommand, CreateCommandOption, CreateEmbed,
+    CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage, Timestamp,
+};
+use serenity::prelude::*;
+
+use crate::archmage::Archmage;
+
+use super::schema::{
+    choices, definition_json, is_required, is_subcommand, kind, kind_name, options, text,
+};
+use super::error::{CommandError, CommandResult};
+use super::invocation::{Invocation, Reply};
+use super::text::{embed_length, truncate, DESCRIPTION_LIMIT, FIELD_LIMIT, MESSAGE_EMBED_LIMIT};
+use super::{handle_fn, ArchmageCommand, CommandDispatcher, CommandRegistration};
+
+pub struct HelpCommand;
+
+/// How many commands (or subcommands) are listed on a single page, at most.
+/// Pages with long help text hold fewer, to fit in a message.
+const PAGE_SIZE: usize = 8;
+/// Room left on every page for its footer.
+const FOOTER_HEADROOM: usize = 32;
+
+impl ArchmageCommand for HelpCommand {
+    fn register() -> Vec<CommandRegistration> {
+        vec![CommandRegistration::new(
+            "help",
+            CreateCommand::new("help")
+                .description("Learn what Archmage can do")
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "command",
+                    "The command to get detailed help with",
+                )),
+            handle_fn!(Self::run),
+        )
+        .help("Without a command, lists everything Archmage can do. With one, explains how to use it.")
+        .example("/help")
+        .example("/help roll")
+        .on_component(handle_fn!(Self::turn_page))]
+    }
+}
+
+impl HelpCommand {
+    async fn run(archmage: &Archmage, invocation: &Invocation, ctx: &Context) -> CommandResult {
+        let topic = invocation
+            .options()
+            .iter()
+            .find(|o| o.name == "command")
+            .and_then(|o| o.value.as_str())
+            .map(|t| t.trim().trim_start_matches('/').to_lowercase());
+
+        let (embed, components) = render(archmage.commands(), topic.as_deref(), 0)?;
+        invocation
+            .respond(
+                ctx,
+                Reply::new()
+                    .ephemeral(true)
+                    .embed(embed)
+                    .components(components),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Handles the previous/next page buttons. Their custom IDs look like
+    /// `help:<page>` for the command list, or `help:<page>:<command>`.
+    async fn turn_page(
+        archmage: &Archmage,
+        component: &ComponentInteraction,
+        ctx: &Context,
+    ) -> CommandResult {
+        let mut parts = component.data.custom_id.splitn(3, ':').skip(1);
+        let page = parts
+            .next()
+            .and_then(|p| p.parse::<usize>().ok())
+            .ok_or(eyre!("Malformed help page ID '{}'", component.data.custom_id))?;
+        let topic = parts.next();
+
+        let (embed, components) = render(archmage.commands(), topic, page)?;
+        component
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new()
+                        .embed(embed)
+                        .components(components),
+                ),
+            )
+            .await
+            .map_err(|e| eyre!(e))?;
+        Ok(())
+    }
+}
+
+/// A single embed field's worth of help.
+struct Section {
+    title: String,
+    body: String,
+}
+
+/// Render one page of help: either the list of all commands, or the details
+/// of a single one.
+fn render(
+    commands: &CommandDispatcher,
+    topic: Option<&str>,
+    page: usize,
+) -> CommandResult<(CreateEmbed, Vec<CreateActionRow>)> {
+    let (title, description, sections) = match topic {
+        None => overview(commands),
+        Some(name) => match commands.get(name) {
+            Some(registration) => details(registration),
+            None => {
+                return Err(CommandError::not_found(format!(
+                    "Archmage doesn't know a spell called `/{name}`. Use `/help` to see them all."
+                )))
+            }
+        },
+    };
+
+    let mut embed = CreateEmbed::new()
+        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+        .title(title)
+        .description(truncate(&description, DESCRIPTION_LIMIT))
+        .timestamp(Timestamp::now());
+    let fields = paginate(&embed, &sections);
+    let pages = fields.len().max(1);
+    let page = page.min(pages - 1);
+    if let Some(fields) = fields.into_iter().nth(page) {
+        embed = embed.fields(fields);
+    }
+
+    if pages == 1 {
+        return Ok((embed, Vec::new()));
+    }
+
+    embed = embed.footer(CreateEmbedFooter::new(format!("Page {} of {}", page + 1, pages)));
+    let id = |target: usize| match topic {
+        None => format!("help:{target}"),
+        Some(name) => format!("help:{target}:{name}"),
+    };
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(id(page.saturating_sub(1)))
+            .style(ButtonStyle::Secondary)
+            .label("Previous")
+            .disabled(page == 0),
+        CreateButton::new(id(page + 1))
+            .style(ButtonStyle::Secondary)
+            .label("Next")
+            .disabled(page + 1 == pages),
+    ]);
+    Ok((embed, vec![buttons]))
+}
+
+/// Split sections into pages of embed fields, each page holding at most
+/// [PAGE_SIZE] of them, and no more than fit in a message along with
+/// `embed`'s title and description.
+fn paginate(embed: &CreateEmbed, sections: &[Section]) -> Vec<Vec<(String, String, bool)>> {
+    let mut pages = Vec::new();
+    let mut page: Vec<(String, String, bool)> = Vec::new();
+    for section in sections {
+        let field = (
+            section.title.clone(),
+            truncate(&section.body, FIELD_LIMIT),
+            false,
+        );
+        let mut candidate = page.clone();
+        candidate.push(field.clone());
+        let fits = candidate.len() <= PAGE_SIZE
+            && embed_length(&embed.clone().fields(candidate)) + FOOTER_HEADROOM
+                <= MESSAGE_EMBED_LIMIT;
+        if !fits && !page.is_empty() {
+            pages.push(std::mem::take(&mut page));
+        }
+        page.push(field);
+    }
+    if !page.is_empty() {
+        pages.push(page);
+    }
+    pages
+}
+
+/// One section per command, alphabetically.
+fn overview(commands: &CommandDispatcher) -> (String, String, Vec<Section>) {
+    let mut registrations: Vec<_> = commands.registrations().collect();
+    registrations.sort_by(|a, b| a.name().cmp(b.name()));
+
+    let sections = registrations
+        .into_iter()
+        .map(|registration| {
+            let definition = definition_json(registration.definition());
+            Section {
+                title: format!("/{}", registration.name()),
+                body: text(&definition, "description").to_owned(),
+            }
+        })
+        .collect();
+
+    (
+        "Archmage's Spellbook".to_owned(),
+        "Use `/help <command>` to learn more about any of these. Every command can also be \
+         typed as a chat message starting with this server's prefix (`!` by default)."
+            .to_owned(),
+        sections,
+    )
+}
+
+/// Usage, options and examples for a single command. Commands with
+/// subcommands get a section per subcommand instead.
+fn details(registration: &CommandRegistration) -> (String, String, Vec<Section>) {
+    let definition = definition_json(registration.definition());
+    let name = registration.name();
+
+    let mut description = text(&definition, "description").to_owned();
+    if let Some(help) = registration.help_text() {
+        description.push_str("\n\n");
+        description.push_str(help);
+    }
+
+    let options = options(&definition);
+    let mut sections = Vec::new();
+    if options.iter().any(is_subcommand) {
+        for option in options {
+            subcommand_sections(&format!("/{name}"), option, &mut sections);
+        }
+    } else {
+        sections.push(Section {
+            title: "Usage".to_owned(),
+            body: usage(&format!("/{name}"), options),
+        });
+    }
+
+    if !registration.examples().is_empty() {
+        sections.push(Section {
+            title: "Examples".to_owned(),
+            body: registration
+                .examples()
+                .iter()
+                .map(|e| format!("`{e}`"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        });
+    }
+
+    (format!("/{name}"), description, sections)
+}
+
+/// Flatten a subcommand (or every subcommand in a group) into sections.
+fn subcommand_sections(prefix: &str, option: &Value, sections: &mut Vec<Section>) {
+    let path = format!("{prefix} {}", text(option, "name"));
+    if kind(option) == Some(CommandOptionType::SubCommandGroup) {
+        for child in options(option) {
+            subcommand_sections(&path, child, sections);
+        }
+        return;
+    }
+
+    sections.push(Section {
+        title: path.clone(),
+        body: format!("{}\n{}", text(option, "description"), usage(&path, options(option))),
+    });
+}
+
+/// A usage line such as `/roll <expression>`, followed by a line for each
+/// option. Required options are shown in `<angle brackets>`, optional ones in
+/// `[square brackets]`, to match the rest of the documentation.
+fn usage(path: &str, options: &[Value]) -> String {
+    let mut line = path.to_owned();
+    let mut lines = Vec::new();
+    for option in options {
+        let name = text(option, "name");
+        line.push_str(&if is_required(option) {
+            format!(" <{name}>")
+        } else {
+            format!(" [{name}]")
+        });
+
+        let mut explanation = format!(
+            "**{name}** ({}): {}",
+            kind_name(kind(option)),
+            text(option, "description")
+        );
+        let choices: Vec<_> = choices(option)
+            .iter()
+            .map(|choice| format!("`{}`", text(choice, "name")))
+            .collect();
+        if !choices.is_empty() {
+            explanation.push_str(&format!(" One of {}.", choices.join(", ")));
+        }
+        lines.push(explanation);
+    }
+
+    lines.insert(0, format!("`{line}`"));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sections(count: usize, length: usize) -> Vec<Section> {
+        (0..count)
+            .map(|i| Section {
+                title: format!("/command{i}"),
+                body: "x".repeat(length),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn short_sections_fill_pages() {
+        let embed = CreateEmbed::new().title("Help");
+        let pages = paginate(&embed, &sections(PAGE_SIZE * 2 + 1, 10));
+        let sizes: Vec<_> = pages.iter().map(Vec::len).collect();
+        assert_eq!(sizes, [PAGE_SIZE, PAGE_SIZE, 1]);
+        assert!(paginate(&embed, &[]).is_empty());
+    }
+
+    #[test]
+    fn long_sections_fit_in_a_message() {
+        let embed = CreateEmbed::new()
+            .title("Help")
+            .description("x".repeat(DESCRIPTION_LIMIT));
+        let pages = paginate(&embed, &sections(PAGE_SIZE, 2 * FIELD_LIMIT));
+        assert_eq!(pages.len(), PAGE_SIZE);
+        for page in pages {
+            let footer = CreateEmbedFooter::new(format!("Page {PAGE_SIZE} of {PAGE_SIZE}"));
+            let embed = embed.clone().fields(page).footer(footer);
+            assert!(embed_length(&embed) <= MESSAGE_EMBED_LIMIT);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Campaign history: looking back on what changed in a campaign, and undoing
+//! it. Anyone may look, but only a campaign's DMs may undo.
+use std::collections::HashSet;
+
+use serenity::all::{
+    Color, CommandDataOption, CommandOptionType, CreateCommand, CreateCommandOption, CreateEmbed,
+    CreateEmbedFooter, Timestamp,
+};
+use serenity::prelude::*;
+
+use crate::archmage::Archmage;
+use crate::model::campaign::Campaign;
+use crate::model::character::POT;
+use crate::model::history::{Edit, Event, Target, DONE};
+use crate::model::inventory::Owner;
+use crate::model::monitor::Kind;
+
+use super::access::require_dm;
+use super::error::{CommandError, CommandResult};
+use super::invocation::{Invocation, Reply};
+use super::monitor;
+use super::options::{self, required_integer};
+use super::text::{truncate, DESCRIPTION_LIMIT};
+use super::{handle_fn, resolve, ArchmageCommand, CommandRegistration};
+
+/// How many events to show at once.
+const PAGE_SIZE: usize = 10;
+/// Values longer than this are cut short, so that one long description
+/// doesn't crowd out everything else.
+const VALUE_LENGTH: usize = 60;
+
+pub struct HistoryCommand;
+
+impl ArchmageCommand for HistoryCommand {
+    fn register() -> Vec<CommandRegistration> {
+        vec![
+            CommandRegistration::new(
+                "history",
+                CreateCommand::new("history")
+                    .description("Look back on what's changed in a campaign")
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Integer,
+                            "page",
+                            "Which page to show, starting from the newest changes",
+                        )
+                        .min_int_value(1),
+                    )
+                    .add_option(CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "character",
+                        "Only show changes to this character, or pot for the party pot",
+                    ))
+                    .add_option(campaign_option()),
+                handle_fn!(Self::history),
+            )
+            .help(
+                "Every change to inventories, the party pot, XP, XP multipliers, level tables, \
+                 quests and character info is kept, numbered, along with who made it and when. \
+                 Anyone may look back on them, newest first. DMs may undo a change with `/undo` and its number.",
+            )
+            .example("/history")
+            .example("/history 2")
+            .example("/history 1 Cal"),
+            CommandRegistration::new(
+                "undo",
+                CreateCommand::new("undo")
+                    .description("Undo a change to a campaign, putting things back as they were")
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Integer,
+                            "change",
+                            "The change's number, from /history",
+                        )
+                        .min_int_value(1)
+                        .required(true),
+                    )
+                    .add_option(campaign_option()),
+                handle_fn!(Self::undo),
+            )
+            .help(
+                "Only a campaign's DMs (and server administrators) may undo changes. A change \
+                 can only be undone if nothing it changed has changed again since; undo the \
+                 later changes first. Undoing is itself a change, so it shows up in `/history` \
+                 and can be undone in turn.",
+            )
+            .example("/undo 42"),
+        ]
+    }
+}
+
+impl HistoryCommand {
+    async fn history(archmage: &Archmage, invocation: &Invocation, ctx: &Context) -> CommandResult {
+        let options = invocation.options();
+        let campaign = resolve::campaign(archmage, invocation, ctx, options).await?;
+        let about = match options::string(options, "character") {
+            None => None,
+            Some(name) if name.trim().eq_ignore_ascii_case(POT) => Some(Owner::Pot(campaign.id)),
+            Some(name) => {
+                let character = resolve::character(archmage, &campaign, name).await?;
+                Some(Owner::Character(character.id))
+            }
+        };
+        let page = page(options);
+
+        let events = archmage
+            .db()
+            .history(&campaign.id, about, (page - 1) * PAGE_SIZE, PAGE_SIZE)
+            .await?;
+        let description = match (events.is_empty(), page) {
+            (true, 1) => "Nothing has changed yet.".to_owned(),
+            (true, _) => format!("There's no page {page}."),
+            (false, _) => events
+                .iter()
+                .map(describe_event)
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+        };
+
+        invocation
+            .respond(
+                ctx,
+                Reply::new().embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .title("History")
+                        .description(truncate(&description, DESCRIPTION_LIMIT))
+                        .footer(CreateEmbedFooter::new(format!(
+                            "{} · Page {page}",
+                            campaign.name
+                        )))
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn undo(archmage: &Archmage, invocation: &Invocation, ctx: &Context) -> CommandResult {
+        let options = invocation.options();
+        let campaign = resolve::campaign(archmage, invocation, ctx, options).await?;
+        let permissions = invocation.member_permissions(ctx).await?;
+        require_dm(archmage, &campaign, invocation.user().id, permissions).await?;
+
+        let id = required_integer(options, "change")?;
+        let undo = archmage
+            .db()
+            .undo(&campaign.id, &id, &invocation.user().id.get())
+            .await?
+            .map_err(CommandError::user)?;
+        changed(archmage, ctx, &campaign, &undo);
+
+        invocation
+            .respond(
+                ctx,
+                Reply::new().embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .title(format!("Undid #{id}"))
+                        .description(truncate(&describe_event(&undo), DESCRIPTION_LIMIT))
+                        .footer(CreateEmbedFooter::new(&campaign.name))
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// Let the campaign's monitors know what an event changed.
+fn changed(archmage: &Archmage, ctx: &Context, campaign: &Campaign, event: &Event) {
+    let kinds: HashSet<Kind> = event
+        .edits
+        .iter()
+        .filter_map(|edit| match edit.target {
+            Target::Item { .. } => Some(Kind::Inventory),
+            Target::Xp { .. } | Target::Level { .. } => Some(Kind::Characters),
+            Target::Multiplier { .. } | Target::Info { .. } => None,
+            Target::Quest { .. }
+            | Target::QuestField { .. }
+            | Target::Status { .. }
+            | Target::Objective { .. } => Some(Kind::Quests),
+        })
+        .collect();
+    for kind in kinds {
+        monitor::changed(archmage, ctx, campaign.id, kind);
+    }
+}
+
+/// The page asked for, counting from 1.
+fn page(options: &[CommandDataOption]) -> usize {
+    options
+        .iter()
+        .find(|o| o.name == "page")
+        .and_then(|o| o.value.as_i64())
+        .and_then(|page| usize::try_from(page).ok())
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// An event's number, who made it and when, and a line for each edit.
+fn describe_event(event: &Event) -> String {
+    let mut heading = format!(
+        "**#{}** by <@{}> <t:{}:R>",
+        event.id, event.user_id, event.created_at
+    );
+    if let Some(undid) = event.undoes {
+        heading.push_str(&format!(", undoing #{undid}"));
+    }
+    if let Some(by) = event.undone_by {
+        heading.push_str(&format!(" *(undone by #{by})*"));
+    }
+    let mut lines = vec![heading];
+    lines.extend(
+        event
+            .edits
+            .iter()
+            .map(|edit| format!("- {}", describe(edit))),
+    );
+    lines.join("\n")
+}
+
+/// What one edit changed, e.g. `Cal: Gold 3000 → none`.
+fn describe(edit: &Edit) -> String {
+    let subject = &edit.subject;
+    let value = |value: &Option<String>| match value {
+        Some(value) if !value.is_empty() => truncate(value, VALUE_LENGTH),
+        _ => "*none*".to_owned(),
+    };
+    let (before, after) = (value(&edit.before), value(&edit.after));
+    match &edit.target {
+        Target::Item { item, .. } => {
+            let after = match edit.after.as_deref() {
+                Some("0") | None => "none",
+                Some(after) => after,
+            };
+            let before = edit.before.as_deref().unwrap_or("0");
+            format!("{subject}: {item} {before} → {after}")
+        }
+        Target::Xp { .. } | Target::Level { .. } => format!("{subject}: {before} → {after} XP"),
+        Target::Multiplier { .. } => format!("{subject}: XP multiplier {before} → {after}"),
+        Target::Info { tag, .. } => match (&edit.before, &edit.after) {
+            (None, _) => format!("{subject}: added **{tag}**: {after}"),
+            (_, None) => format!("{subject}: removed **{tag}**"),
+            _ => format!("{subject}: **{tag}** {before} → {after}"),
+        },
+        Target::Quest { .. } => match edit.after {
+            Some(_) => format!("Added {subject}: {after}"),
+            None => format!("Removed {subject}: {before}"),
+        },
+        Target::QuestField { field, .. } => {
+            format!("{subject}: {} {before} → {after}", field.as_str())
+        }
+        Target::Status { .. } => format!("{subject}: {before} → {after}"),
+        Target::Objective { text, .. } => {
+            let text = truncate(text, VALUE_LENGTH);
+            match (&edit.before, edit.after.as_deref()) {
+                (_, None) => format!("{subject}: removed objective {text}"),
+                (None, _) => format!("{subject}: added objective {text}"),
+                (_, Some(DONE)) => format!("{subject}: checked off {text}"),
+                (_, Some(_)) => format!("{subject}: unchecked {text}"),
+            }
+        }
+    }
+}
+
+/// As elsewhere, the campaign is optional so that channels can imply it.
+/// Chat commands give it last, as in `!undo 42 "Lost Mine"`.
+fn campaign_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        "campaign",
+        "The campaign. Leave it out to use this channel's",
+    )
+}
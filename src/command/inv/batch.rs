@@ -0,0 +1,173 @@
+//! Reading batches of items such as `3000 Gold; 1 "Potion of Strength"`.
+//! Entries are separated by semicolons, except inside double quotes, and each
+//! may start with an amount.
+use crate::command::args::{split_batch, tokenize};
+use crate::model::inventory::{clean_item, Change, MAX_QUANTITY};
+
+/// A single entry in a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// How many, if a number was given.
+    pub amount: Option<i64>,
+    pub item: String,
+}
+
+/// Read a batch of entries. Errors are meant to be shown to the user as-is.
+pub fn parse(input: &str) -> Result<Vec<Entry>, String> {
+    let entries = split_batch(input)
+        .iter()
+        .filter(|part| !part.trim().is_empty())
+        .map(|part| entry(part))
+        .collect::<Result<Vec<_>, _>>()?;
+    match entries.is_empty() {
+        true => Err("Which items?".to_owned()),
+        false => Ok(entries),
+    }
+}
+
+/// Turn entries into changes for the `add`, `remove` or `set` subcommand.
+/// `add` adds one of anything without an amount, `remove` takes the whole
+/// stack, and `set` needs an amount for everything.
+pub fn changes(subcommand: &str, entries: Vec<Entry>) -> Result<Vec<(String, Change)>, String> {
+    entries
+        .into_iter()
+        .map(|entry| {
+            let change = match (subcommand, entry.amount) {
+                (_, Some(0)) if subcommand != "set" => {
+                    return Err(format!(
+                        "Adding or removing 0 {} wouldn't do anything.",
+                        entry.item
+                    ))
+                }
+                ("add", amount) => Change::Add(amount.unwrap_or(1)),
+                ("remove", amount) => Change::Remove(amount),
+                (_, Some(amount)) => Change::Set(amount),
+                (_, None) => {
+                    return Err(format!(
+                        "How many {} should there be? `set` needs an amount for every item.",
+                        entry.item
+                    ))
+                }
+            };
+            Ok((entry.item, change))
+        })
+        .collect()
+}
+
+fn entry(part: &str) -> Result<Entry, String> {
+    let tokens = tokenize(part);
+    let amount = match tokens.first() {
+        Some(first) if !first.quoted => amount(&first.text)?,
+        _ => None,
+    };
+    let words = &tokens[usize::from(amount.is_some())..];
+    if words.is_empty() {
+        return Err(format!("{} of what?", part.trim()));
+    }
+    let item = words
+        .iter()
+        .map(|w| w.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Ok(Entry {
+        amount,
+        item: clean_item(&item)?,
+    })
+}
+
+/// Read an amount such as `3` or `3,000`, or None if the word isn't a number
+/// and so must be part of the item's name.
+fn amount(word: &str) -> Result<Option<i64>, String> {
+    if !word.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+') {
+        return Ok(None);
+    }
+    let Ok(amount) = word.replace([',', '_'], "").parse::<i64>() else {
+        return Ok(None);
+    };
+    if amount < 0 {
+        return Err(format!("`{word}` is negative, and amounts can't be."));
+    }
+    if amount > MAX_QUANTITY {
+        return Err(format!("`{word}` is more than anyone could need."));
+    }
+    Ok(Some(amount))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(amount: Option<i64>, item: &str) -> Entry {
+        Entry {
+            amount,
+            item: item.to_owned(),
+        }
+    }
+
+    #[test]
+    fn amounts_are_optional() {
+        assert_eq!(
+            parse("3,000 Gold; Torch;  1 \"Potion  of Strength\" "),
+            Ok(vec![
+                entry(Some(3000), "Gold"),
+                entry(None, "Torch"),
+                entry(Some(1), "Potion of Strength"),
+            ])
+        );
+        // Quoted numbers and words that only start like numbers are items.
+        assert_eq!(
+            parse("\"3\" Dragons; 2nd Ring"),
+            Ok(vec![entry(None, "3 Dragons"), entry(None, "2nd Ring")])
+        );
+    }
+
+    #[test]
+    fn quotes_keep_semicolons() {
+        assert_eq!(
+            parse("2 \"Salt; Pepper\"; Rope"),
+            Ok(vec![entry(Some(2), "Salt; Pepper"), entry(None, "Rope")])
+        );
+    }
+
+    #[test]
+    fn rejects_bad_entries() {
+        assert_eq!(
+            parse("-3 Gold"),
+            Err("`-3` is negative, and amounts can't be.".to_owned())
+        );
+        assert_eq!(parse("3; Gold"), Err("3 of what?".to_owned()));
+        assert_eq!(parse(" ; "), Err("Which items?".to_owned()));
+        assert!(parse("10000000000000 Gold").is_err());
+    }
+
+    #[test]
+    fn changes_for_each_subcommand() {
+        let entries = || vec![entry(Some(2), "Gold"), entry(None, "Torch")];
+        assert_eq!(
+            changes("add", entries()),
+            Ok(vec![
+                ("Gold".to_owned(), Change::Add(2)),
+                ("Torch".to_owned(), Change::Add(1)),
+            ])
+        );
+        assert_eq!(
+            changes("remove", entries()),
+            Ok(vec![
+                ("Gold".to_owned(), Change::Remove(Some(2))),
+                ("Torch".to_owned(), Change::Remove(None)),
+            ])
+        );
+        assert!(changes("set", entries()).is_err());
+    }
+
+    #[test]
+    fn only_set_takes_zero() {
+        let zero = || vec![entry(Some(0), "Torch")];
+        assert!(changes("add", zero()).is_err());
+        assert!(changes("remove", zero()).is_err());
+        assert_eq!(
+            changes("set", zero()),
+            Ok(vec![("Torch".to_owned(), Change::Set(0))])
+        );
+    }
+}
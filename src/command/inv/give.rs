@@ -0,0 +1,189 @@
+//! Moving items between characters, and between characters and the party pot,
+//! all at once so that nothing goes missing halfway.
+use serenity::all::{
+    Color, CommandOptionType, CreateCommand, CreateCommandOption, CreateEmbed, Timestamp,
+};
+use serenity::prelude::*;
+
+use crate::archmage::Archmage;
+use crate::model::campaign::Campaign;
+use crate::model::character::{Character, POT};
+use crate::model::feature;
+use crate::model::inventory::{Changed, Contributor, Owner};
+use crate::model::monitor::Kind;
+
+use crate::command::access::require_player;
+use crate::command::error::{CommandError, CommandResult};
+use crate::command::invocation::{Invocation, Reply};
+use crate::command::monitor;
+use crate::command::options::required_string;
+use crate::command::text::{truncate, DESCRIPTION_LIMIT};
+use crate::command::{handle_fn, resolve, ArchmageCommand, CommandRegistration};
+
+use super::{batch, campaign_option, items_option};
+
+pub struct GiveCommand;
+
+impl ArchmageCommand for GiveCommand {
+    fn register() -> Vec<CommandRegistration> {
+        vec![CommandRegistration::new(
+            "give",
+            CreateCommand::new("give")
+                .description(
+                    "Move items from one character to another, or to or from the party pot",
+                )
+                .add_option(side_option("from", "Who to take the items from"))
+                .add_option(side_option("to", "Who to give the items to"))
+                .add_option(items_option(
+                    "Items to give, e.g. 50 Gold; Torch. Amounts default to 1",
+                ))
+                .add_option(campaign_option()),
+            handle_fn!(Self::run),
+        )
+        .leading("campaign")
+        .feature(feature::INVENTORY)
+        .help(
+            "Takes items from one inventory and gives them to another, in one go: if the \
+             giver doesn't have enough of any item, nothing changes hands. Use `pot` for \
+             the party pot. Only whoever plays the giving character, or the receiving \
+             character when taking from the pot, and the campaign's DMs may give.",
+        )
+        .example("/give Cal pot 50 Gold")
+        .example("/give pot Cal 2 Rations; Torch")
+        .example("/give Cal Bob \"Potion of Strength\"")]
+    }
+}
+
+impl GiveCommand {
+    async fn run(archmage: &Archmage, invocation: &Invocation, ctx: &Context) -> CommandResult {
+        let options = invocation.options();
+        let campaign = resolve::campaign(archmage, invocation, ctx, options).await?;
+        let from = side(archmage, &campaign, required_string(options, "from")?).await?;
+        let to = side(archmage, &campaign, required_string(options, "to")?).await?;
+
+        // Whoever acts for the character on either side vouches for the move,
+        // and is who the pot's ledger credits.
+        let character = match (&from, &to) {
+            (None, None) => {
+                return Err(CommandError::user(
+                    "The party pot can't give things to itself.",
+                ))
+            }
+            (Some(from), Some(to)) if from.id == to.id => {
+                return Err(CommandError::user(format!(
+                    "`{}` already has everything they have.",
+                    from.name
+                )))
+            }
+            (Some(character), _) | (None, Some(character)) => character,
+        };
+        let permissions = invocation.member_permissions(ctx).await?;
+        require_player(
+            archmage,
+            &campaign,
+            character,
+            invocation.user().id,
+            permissions,
+        )
+        .await?;
+
+        let items = batch::parse(required_string(options, "items")?)
+            .map_err(CommandError::user)?
+            .into_iter()
+            .map(|entry| match entry.amount {
+                Some(0) => Err(format!("Giving 0 {} wouldn't do anything.", entry.item)),
+                amount => Ok((entry.item, amount.unwrap_or(1))),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(CommandError::user)?;
+        let contributor = Contributor {
+            user_id: invocation.user().id.get(),
+            character: Some(character.name.clone()),
+        };
+        let moved = archmage
+            .db()
+            .transfer_items(
+                &owner(&campaign, &from),
+                &owner(&campaign, &to),
+                items,
+                &contributor,
+            )
+            .await?
+            .map_err(|e| CommandError::user(e.to_string()))?;
+        monitor::changed(archmage, ctx, campaign.id, Kind::Inventory);
+
+        let description = moved
+            .iter()
+            .map(|(taken, given)| describe(&from, taken, &to, given))
+            .collect::<Vec<_>>()
+            .join("\n");
+        invocation
+            .respond(
+                ctx,
+                Reply::new().embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .title(format!("{} → {}", label(&from), label(&to)))
+                        .description(truncate(&description, DESCRIPTION_LIMIT))
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// One side of a move: a character, or None for the party pot.
+async fn side(
+    archmage: &Archmage,
+    campaign: &Campaign,
+    name: &str,
+) -> CommandResult<Option<Character>> {
+    match name.trim().eq_ignore_ascii_case(POT) {
+        true => Ok(None),
+        false => Ok(Some(resolve::character(archmage, campaign, name).await?)),
+    }
+}
+
+fn owner(campaign: &Campaign, side: &Option<Character>) -> Owner {
+    match side {
+        Some(character) => Owner::Character(character.id),
+        None => Owner::Pot(campaign.id),
+    }
+}
+
+fn label(side: &Option<Character>) -> String {
+    match side {
+        Some(character) => character.name.clone(),
+        None => "Party Pot".to_owned(),
+    }
+}
+
+/// A line for each item moved, e.g. `50 Gold (Cal: 80 → 30, Party Pot: 0 → 50)`.
+fn describe(
+    from: &Option<Character>,
+    taken: &Changed,
+    to: &Option<Character>,
+    given: &Changed,
+) -> String {
+    format!(
+        "{} {} ({}: {} → {}, {}: {} → {})",
+        taken.before - taken.after,
+        taken.item,
+        label(from),
+        taken.before,
+        taken.after,
+        label(to),
+        given.before,
+        given.after
+    )
+}
+
+fn side_option(name: &str, description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        name,
+        format!("{description}: a character's short ID, or pot for the party pot"),
+    )
+    .required(true)
+}
@@ -0,0 +1,283 @@
+//! Character inventories, and the party pot each campaign shares. Anyone may
+//! look at them, but only a character's players and their campaign's DMs may
+//! change a character's, and only a campaign's players and DMs its pot.
+use eyre::eyre;
+use serenity::all::{
+    Color, CommandDataOption, CommandOptionType, CreateCommand, CreateCommandOption, CreateEmbed,
+    CreateEmbedFooter, Timestamp,
+};
+use serenity::prelude::*;
+
+use crate::archmage::Archmage;
+use crate::model::campaign::Campaign;
+use crate::model::character::POT;
+use crate::model::feature;
+use crate::model::inventory::{Changed, Contributor, Owner};
+use crate::model::monitor::Kind;
+
+use super::access::require_player;
+use super::error::{CommandError, CommandResult};
+use super::invocation::{Invocation, Reply};
+use super::monitor;
+use super::options::required_string;
+use super::text::{truncate, DESCRIPTION_LIMIT};
+use super::{handle_fn, options, resolve, ArchmageCommand, CommandRegistration};
+
+mod batch;
+pub mod give;
+pub mod pot;
+
+pub struct InvCommand;
+
+impl ArchmageCommand for InvCommand {
+    fn register() -> Vec<CommandRegistration> {
+        vec![CommandRegistration::new(
+            "inv",
+            CreateCommand::new("inv")
+                .description("Keep track of what characters are carrying")
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "show",
+                        "Show a character's inventory",
+                    )
+                    .add_sub_option(character_option())
+                    .add_sub_option(campaign_option()),
+                )
+                .add_option(change_subcommand(
+                    "add",
+                    "Add items to a character's inventory",
+                    "Items to add, e.g. 3000 Gold; 1 \"Potion of Strength\". Amounts default to 1",
+                ))
+                .add_option(change_subcommand(
+                    "remove",
+                    "Remove items from a character's inventory",
+                    "Items to remove, e.g. 2 Rations; Torch. Without an amount, the whole stack goes",
+                ))
+                .add_option(change_subcommand(
+                    "set",
+                    "Set exactly how many of each item a character has",
+                    "Items and how many there should be, e.g. 50 Gold; 0 Torches",
+                )),
+            handle_fn!(Self::run),
+        )
+        .leading("campaign")
+        .leading("character")
+        .default_subcommand("show")
+        .feature(feature::INVENTORY)
+        .help(
+            "Anyone may look at an inventory, but only the character's players and their \
+             campaign's DMs may change it. Use `pot` as the character to get at the \
+             party pot instead.\n\n\
+             Several items can be changed at once by separating them with semicolons. Put \
+             items containing semicolons in double quotes. Amounts can't be negative: `add` \
+             adds one unless told otherwise, `remove` takes the whole stack unless told \
+             otherwise, and `set` always needs an amount. Items that run out are removed. If \
+             any item in a batch can't be changed, none of them are.",
+        )
+        .example("/inv show Cal")
+        .example("/inv show pot")
+        .example("/inv add Cal 3000 Gold; 1 \"Potion of Strength\"")
+        .example("/inv remove Cal 2 Rations; Torch")
+        .example("/inv set Cal 50 Gold")]
+    }
+}
+
+impl InvCommand {
+    async fn run(archmage: &Archmage, invocation: &Invocation, ctx: &Context) -> CommandResult {
+        let Some((subcommand, options)) = options::subcommand(invocation.options()) else {
+            return Err(eyre!("/inv used without a subcommand").into());
+        };
+
+        let campaign = resolve::campaign(archmage, invocation, ctx, options).await?;
+        let name = required_string(options, "character")?;
+        if name.trim().eq_ignore_ascii_case(POT) {
+            return pot::run(archmage, invocation, ctx, subcommand, &campaign, options).await;
+        }
+        let character = resolve::character(archmage, &campaign, name).await?;
+        let inventory = Inventory {
+            campaign_id: campaign.id,
+            owner: Owner::Character(character.id),
+            title: format!("{}'s Inventory", character.name),
+        };
+        match subcommand {
+            "show" => show(archmage, invocation, ctx, &campaign, &inventory).await,
+            "add" | "remove" | "set" => {
+                let permissions = invocation.member_permissions(ctx).await?;
+                require_player(
+                    archmage,
+                    &campaign,
+                    &character,
+                    invocation.user().id,
+                    permissions,
+                )
+                .await?;
+                let contributor = Contributor {
+                    user_id: invocation.user().id.get(),
+                    character: Some(character.name.clone()),
+                };
+                change(
+                    archmage,
+                    invocation,
+                    ctx,
+                    subcommand,
+                    &inventory,
+                    options,
+                    &contributor,
+                )
+                .await
+            }
+            other => Err(eyre!("Unknown /inv subcommand {other}").into()),
+        }
+    }
+}
+
+/// An inventory being looked at or changed, and what to call it.
+struct Inventory {
+    campaign_id: i64,
+    owner: Owner,
+    title: String,
+}
+
+async fn show(
+    archmage: &Archmage,
+    invocation: &Invocation,
+    ctx: &Context,
+    campaign: &Campaign,
+    inventory: &Inventory,
+) -> CommandResult {
+    let stacks = archmage.db().inventory(&inventory.owner).await?;
+    let description = match stacks.is_empty() {
+        true => "Nothing yet.".to_owned(),
+        false => stacks
+            .iter()
+            .map(|stack| format!("{} {}", stack.quantity, stack.item))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+
+    invocation
+        .respond(
+            ctx,
+            Reply::new().embed(
+                CreateEmbed::new()
+                    .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                    .title(&inventory.title)
+                    .description(truncate(&description, DESCRIPTION_LIMIT))
+                    .footer(CreateEmbedFooter::new(&campaign.name))
+                    .timestamp(Timestamp::now()),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+async fn change(
+    archmage: &Archmage,
+    invocation: &Invocation,
+    ctx: &Context,
+    subcommand: &str,
+    inventory: &Inventory,
+    options: &[CommandDataOption],
+    contributor: &Contributor,
+) -> CommandResult {
+    let changes = batch::parse(required_string(options, "items")?)
+        .and_then(|entries| batch::changes(subcommand, entries))
+        .map_err(CommandError::user)?;
+
+    let changed = archmage
+        .db()
+        .change_items(&inventory.owner, changes, contributor)
+        .await?
+        .map_err(|e| CommandError::user(e.to_string()))?;
+    monitor::changed(archmage, ctx, inventory.campaign_id, Kind::Inventory);
+    invocation
+        .respond(
+            ctx,
+            Reply::new().embed(
+                CreateEmbed::new()
+                    .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                    .title(&inventory.title)
+                    .description(truncate(&describe(&changed), DESCRIPTION_LIMIT))
+                    .timestamp(Timestamp::now()),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// A line for each item changed, e.g. `Gold: 3000 → 3003`. Items left as
+/// they were, like one set to 0 that wasn't there, are left out, as they are
+/// from the history.
+fn describe(changed: &[Changed]) -> String {
+    let lines: Vec<_> = changed
+        .iter()
+        .filter(|c| c.before != c.after)
+        .map(|c| match c.after {
+            0 => format!("{}: {} → none", c.item, c.before),
+            after => format!("{}: {} → {after}", c.item, c.before),
+        })
+        .collect();
+    match lines.is_empty() {
+        true => "Nothing changed.".to_owned(),
+        false => lines.join("\n"),
+    }
+}
+
+fn change_subcommand(name: &str, description: &str, items: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::SubCommand, name, description)
+        .add_sub_option(character_option())
+        .add_sub_option(items_option(items))
+        .add_sub_option(campaign_option())
+}
+
+fn items_option(description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::String, "items", description).required(true)
+}
+
+/// Chat commands give the character before the subcommand, as in
+/// `!inv Cal add 3 Gold`.
+fn character_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        "character",
+        "The character's short ID, or pot for the party pot",
+    )
+    .required(true)
+}
+
+/// As with the character, chat commands give the campaign first, as in
+/// `!inv "Lost Mine" Cal`.
+fn campaign_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        "campaign",
+        "The campaign. Leave it out to use this channel's",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed(item: &str, before: i64, after: i64) -> Changed {
+        Changed {
+            item: item.to_owned(),
+            before,
+            after,
+        }
+    }
+
+    #[test]
+    fn describes_each_change() {
+        let changes = [changed("Gold", 3000, 3003), changed("Torch", 2, 0)];
+        assert_eq!(describe(&changes), "Gold: 3000 → 3003\nTorch: 2 → none");
+    }
+
+    #[test]
+    fn leaves_out_what_didnt_change() {
+        let changes = [changed("Torch", 0, 0), changed("Gold", 5, 6)];
+        assert_eq!(describe(&changes), "Gold: 5 → 6");
+        assert_eq!(describe(&changes[..1]), "Nothing changed.");
+    }
+}
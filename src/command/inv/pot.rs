@@ -0,0 +1,191 @@
+//! The party pot: an inventory shared by everyone in a campaign. Every change
+//! to it is entered in a ledger, along with the character who made it.
+use eyre::eyre;
+use serenity::all::{
+    Color, CommandDataOption, CommandOptionType, CreateCommand, CreateCommandOption, CreateEmbed,
+    CreateEmbedFooter, Timestamp,
+};
+use serenity::prelude::*;
+
+use crate::archmage::Archmage;
+use crate::model::campaign::Campaign;
+use crate::model::feature;
+use crate::model::inventory::{Contribution, Contributor, Owner};
+
+use crate::command::access::require_party;
+use crate::command::error::CommandResult;
+use crate::command::invocation::{Invocation, Reply};
+use crate::command::text::{truncate, DESCRIPTION_LIMIT};
+use crate::command::{handle_fn, options, resolve, ArchmageCommand, CommandRegistration};
+
+use super::{campaign_option, change, items_option, show, Inventory};
+
+/// How many of the ledger's most recent entries to show.
+const LEDGER_ENTRIES: usize = 20;
+
+pub struct PotCommand;
+
+impl ArchmageCommand for PotCommand {
+    fn register() -> Vec<CommandRegistration> {
+        vec![CommandRegistration::new(
+            "pot",
+            CreateCommand::new("pot")
+                .description("Keep track of the party's shared inventory")
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "show",
+                        "Show the party pot",
+                    )
+                    .add_sub_option(campaign_option()),
+                )
+                .add_option(change_subcommand(
+                    "add",
+                    "Put items in the party pot",
+                    "Items to add, e.g. 3000 Gold; 1 \"Potion of Strength\". Amounts default to 1",
+                ))
+                .add_option(change_subcommand(
+                    "remove",
+                    "Take items out of the party pot",
+                    "Items to remove, e.g. 2 Rations; Torch. Without an amount, the whole stack goes",
+                ))
+                .add_option(change_subcommand(
+                    "set",
+                    "Set exactly how many of each item the party pot has",
+                    "Items and how many there should be, e.g. 50 Gold; 0 Torches",
+                ))
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "ledger",
+                        "Show who recently put what in the party pot, or took it out",
+                    )
+                    .add_sub_option(campaign_option()),
+                ),
+            handle_fn!(Self::run),
+        )
+        .leading("campaign")
+        .feature(feature::INVENTORY)
+        .default_subcommand("show")
+        .help(
+            "Anyone may look at the party pot, but only players and DMs of the campaign may \
+             change it. Each change is recorded in the pot's ledger against the character \
+             who made it; if you play several characters in the campaign, Archmage asks \
+             which one. Items are added, removed and set just as with `/inv`.",
+        )
+        .example("/pot show")
+        .example("/pot add 50 Gold")
+        .example("/pot remove 2 Rations; Torch")
+        .example("/pot ledger")]
+    }
+}
+
+impl PotCommand {
+    async fn run(archmage: &Archmage, invocation: &Invocation, ctx: &Context) -> CommandResult {
+        let Some((subcommand, options)) = options::subcommand(invocation.options()) else {
+            return Err(eyre!("/pot used without a subcommand").into());
+        };
+
+        let campaign = resolve::campaign(archmage, invocation, ctx, options).await?;
+        match subcommand {
+            "ledger" => ledger(archmage, invocation, ctx, &campaign).await,
+            _ => run(archmage, invocation, ctx, subcommand, &campaign, options).await,
+        }
+    }
+}
+
+/// Show or change a campaign's party pot, for both `/pot` and `/inv pot`.
+pub(super) async fn run(
+    archmage: &Archmage,
+    invocation: &Invocation,
+    ctx: &Context,
+    subcommand: &str,
+    campaign: &Campaign,
+    options: &[CommandDataOption],
+) -> CommandResult {
+    let inventory = Inventory {
+        campaign_id: campaign.id,
+        owner: Owner::Pot(campaign.id),
+        title: "Party Pot".to_owned(),
+    };
+    match subcommand {
+        "show" => show(archmage, invocation, ctx, campaign, &inventory).await,
+        "add" | "remove" | "set" => {
+            let character = resolve::own_character(archmage, invocation, ctx, campaign).await?;
+            let permissions = invocation.member_permissions(ctx).await?;
+            require_party(
+                archmage,
+                campaign,
+                character.as_ref(),
+                invocation.user().id,
+                permissions,
+            )
+            .await?;
+            let contributor = Contributor {
+                user_id: invocation.user().id.get(),
+                character: character.map(|c| c.name),
+            };
+            change(
+                archmage,
+                invocation,
+                ctx,
+                subcommand,
+                &inventory,
+                options,
+                &contributor,
+            )
+            .await
+        }
+        other => Err(eyre!("Unknown party pot subcommand {other}").into()),
+    }
+}
+
+async fn ledger(
+    archmage: &Archmage,
+    invocation: &Invocation,
+    ctx: &Context,
+    campaign: &Campaign,
+) -> CommandResult {
+    let entries = archmage
+        .db()
+        .pot_ledger(&campaign.id, LEDGER_ENTRIES)
+        .await?;
+    let description = match entries.is_empty() {
+        true => "Nothing has been put in the party pot yet.".to_owned(),
+        false => entries.iter().map(describe).collect::<Vec<_>>().join("\n"),
+    };
+
+    invocation
+        .respond(
+            ctx,
+            Reply::new().embed(
+                CreateEmbed::new()
+                    .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                    .title("Party Pot Ledger")
+                    .description(truncate(&description, DESCRIPTION_LIMIT))
+                    .footer(CreateEmbedFooter::new(&campaign.name))
+                    .timestamp(Timestamp::now()),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// A line for a ledger entry, e.g. `Cal` put in 50 Gold, 3 hours ago.
+fn describe(entry: &Contribution) -> String {
+    let who = match &entry.contributor.character {
+        Some(character) => format!("`{character}`"),
+        None => format!("<@{}>", entry.contributor.user_id),
+    };
+    let what = match entry.amount > 0 {
+        true => format!("put in {} {}", entry.amount, entry.item),
+        false => format!("took out {} {}", -entry.amount, entry.item),
+    };
+    format!("{who} {what}, <t:{}:R>", entry.created_at)
+}
+
+fn change_subcommand(name: &str, description: &str, items: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::SubCommand, name, description)
+        .add_sub_option(items_option(items))
+        .add_sub_option(campaign_option())
+}
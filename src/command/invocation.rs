@@ -0,0 +1,320 @@
+//! A single use of a command, however it was invoked. Handlers work with an
+//! [Invocation] rather than with a raw interaction so that the same handler
+//! serves both `/roll 1d20` and `!roll 1d20`.
+use eyre::{eyre, Result};
+use serde::Serialize;
+use serenity::all::{
+    ChannelId, CommandDataOption, CommandInteraction, Context, CreateActionRow, CreateEmbed,
+    CreateInteractionResponse, CreateInteractionResponseFollowup,
+    CreateInteractionResponseMessage, CreateMessage, EditInteractionResponse, EditMessage, GuildId,
+    Message, MessageId, Permissions, User,
+};
+use tokio::sync::Mutex;
+
+/// Where an invocation came from.
+#[derive(Debug, Serialize)]
+pub enum Source {
+    /// A slash command.
+    Slash(Box<CommandInteraction>),
+    /// A chat message starting with the guild's prefix.
+    Prefix(Box<Message>),
+}
+
+/// How far along responding to a slash command is. Discord wants a response
+/// within three seconds, but will wait up to fifteen minutes for the real
+/// answer once the interaction has been deferred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseState {
+    /// Nothing has been sent yet.
+    Pending,
+    /// Discord is showing "Archmage is thinking..." until the response is
+    /// edited in.
+    Deferred,
+    /// The initial response has been sent, so anything else is a follow-up.
+    Responded,
+}
+
+/// A response that's been sent, so that it can be changed afterwards with
+/// [Invocation::edit].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sent {
+    /// The slash command's own response.
+    Original,
+    /// A follow-up to a slash command, or a message replying to a chat
+    /// command.
+    Message(MessageId),
+}
+
+#[derive(Debug, Serialize)]
+pub struct Invocation {
+    name: String,
+    options: Vec<CommandDataOption>,
+    source: Source,
+    /// Locked for the whole of every response, so that an automatic deferral
+    /// can never race the handler's own response.
+    #[serde(skip)]
+    state: Mutex<ResponseState>,
+}
+
+impl Invocation {
+    /// Wrap a slash command.
+    pub fn from_interaction(command: CommandInteraction) -> Self {
+        Self {
+            name: command.data.name.clone(),
+            options: command.data.options.clone(),
+            source: Source::Slash(Box::new(command)),
+            state: Mutex::new(ResponseState::Pending),
+        }
+    }
+
+    /// Wrap a chat command whose arguments have already been parsed into
+    /// options, e.g. by [super::prefix::parse_options].
+    pub fn from_message(message: Message, name: String, options: Vec<CommandDataOption>) -> Self {
+        Self {
+            name,
+            options,
+            source: Source::Prefix(Box::new(message)),
+            state: Mutex::new(ResponseState::Pending),
+        }
+    }
+
+    /// The name of the command being run.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The options given to the command, in the same shape Discord sends
+    /// them for slash commands.
+    pub fn options(&self) -> &[CommandDataOption] {
+        &self.options
+    }
+
+    /// The user running the command.
+    pub fn user(&self) -> &User {
+        match &self.source {
+            Source::Slash(command) => &command.user,
+            Source::Prefix(message) => &message.author,
+        }
+    }
+
+    pub fn guild_id(&self) -> Option<GuildId> {
+        match &self.source {
+            Source::Slash(command) => command.guild_id,
+            Source::Prefix(message) => message.guild_id,
+        }
+    }
+
+    pub fn channel_id(&self) -> ChannelId {
+        match &self.source {
+            Source::Slash(command) => command.channel_id,
+            Source::Prefix(message) => message.channel_id,
+        }
+    }
+
+    /// The invoking user's permissions in the guild, or None outside of one.
+    pub async fn member_permissions(&self, ctx: &Context) -> Result<Option<Permissions>> {
+        match &self.source {
+            Source::Slash(command) => Ok(command.member.as_ref().and_then(|m| m.permissions)),
+            Source::Prefix(message) => {
+                if message.guild_id.is_none() {
+                    return Ok(None);
+                }
+                let member = message.member(ctx).await.map_err(|e| eyre!(e))?;
+                let guild = message
+                    .guild(&ctx.cache)
+                    .ok_or(eyre!("Guild for message {} is not cached", message.id))?;
+                Ok(Some(guild.member_permissions(&member)))
+            }
+        }
+    }
+
+    /// Let Discord know the command is being worked on, buying up to fifteen
+    /// minutes to respond. Does nothing if a response was already sent. For
+    /// chat commands, this shows Archmage as typing instead.
+    ///
+    /// The dispatcher defers automatically when a handler takes a while, so
+    /// handlers only need to call this if they know up front they'll be slow.
+    pub async fn defer(&self, ctx: &Context) -> Result<()> {
+        let mut state = self.state.lock().await;
+        if *state != ResponseState::Pending {
+            return Ok(());
+        }
+
+        match &self.source {
+            Source::Slash(command) => {
+                command
+                    .create_response(
+                        &ctx.http,
+                        CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new()),
+                    )
+                    .await
+                    .map_err(|e| eyre!(e))?;
+                *state = ResponseState::Deferred;
+            }
+            Source::Prefix(message) => {
+                message
+                    .channel_id
+                    .broadcast_typing(&ctx.http)
+                    .await
+                    .map_err(|e| eyre!(e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a reply to whoever ran the command. Slash commands get an
+    /// interaction response; chat commands get a message replying to theirs.
+    ///
+    /// This may be called more than once. Deferred responses are filled in,
+    /// and anything after the first response is sent as a follow-up.
+    pub async fn respond(&self, ctx: &Context, reply: Reply) -> Result<()> {
+        self.send(ctx, reply).await.map(|_| ())
+    }
+
+    /// [Invocation::respond], saying where the reply was sent so that it can
+    /// be edited later.
+    pub async fn send(&self, ctx: &Context, reply: Reply) -> Result<Sent> {
+        let mut state = self.state.lock().await;
+        let command = match &self.source {
+            Source::Slash(command) => command,
+            Source::Prefix(message) => {
+                return message
+                    .channel_id
+                    .send_message(&ctx.http, reply.into_message().reference_message(message.as_ref()))
+                    .await
+                    .map(|sent| Sent::Message(sent.id))
+                    .map_err(|e| eyre!(e));
+            }
+        };
+
+        let sent = match *state {
+            ResponseState::Pending => {
+                command
+                    .create_response(
+                        &ctx.http,
+                        CreateInteractionResponse::Message(reply.into_interaction_response()),
+                    )
+                    .await
+                    .map_err(|e| eyre!(e))?;
+                Sent::Original
+            }
+            // A deferred response is public, so a private reply has to
+            // replace it rather than be edited into it.
+            ResponseState::Deferred if reply.ephemeral => {
+                command
+                    .delete_response(&ctx.http)
+                    .await
+                    .map_err(|e| eyre!(e))?;
+                let followup = command
+                    .create_followup(&ctx.http, reply.into_followup())
+                    .await
+                    .map_err(|e| eyre!(e))?;
+                Sent::Message(followup.id)
+            }
+            ResponseState::Deferred => {
+                let _ = command
+                    .edit_response(&ctx.http, reply.into_edit())
+                    .await
+                    .map_err(|e| eyre!(e))?;
+                Sent::Original
+            }
+            ResponseState::Responded => {
+                let followup = command
+                    .create_followup(&ctx.http, reply.into_followup())
+                    .await
+                    .map_err(|e| eyre!(e))?;
+                Sent::Message(followup.id)
+            }
+        };
+        *state = ResponseState::Responded;
+        Ok(sent)
+    }
+
+    /// Replace a reply that was sent earlier with another.
+    pub async fn edit(&self, ctx: &Context, sent: Sent, reply: Reply) -> Result<()> {
+        let edited = match (&self.source, sent) {
+            (Source::Slash(command), Sent::Original) => {
+                command.edit_response(&ctx.http, reply.into_edit()).await
+            }
+            (Source::Slash(command), Sent::Message(id)) => {
+                command
+                    .edit_followup(&ctx.http, id, reply.into_followup())
+                    .await
+            }
+            (Source::Prefix(message), Sent::Message(id)) => {
+                message
+                    .channel_id
+                    .edit_message(&ctx.http, id, reply.into_message_edit())
+                    .await
+            }
+            (Source::Prefix(_), Sent::Original) => {
+                return Err(eyre!("Chat commands have no response of their own to edit"))
+            }
+        };
+        edited.map(|_| ()).map_err(|e| eyre!(e))
+    }
+}
+
+/// The contents of a response to a command, independent of how the command
+/// was invoked.
+#[derive(Debug, Clone, Default)]
+pub struct Reply {
+    embeds: Vec<CreateEmbed>,
+    components: Vec<CreateActionRow>,
+    ephemeral: bool,
+}
+
+impl Reply {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn embed(mut self, embed: CreateEmbed) -> Self {
+        self.embeds.push(embed);
+        self
+    }
+
+    pub fn components(mut self, components: Vec<CreateActionRow>) -> Self {
+        self.components = components;
+        self
+    }
+
+    /// Only show the reply to the invoking user. Chat commands can't be
+    /// replied to privately, so for them this has no effect.
+    pub fn ephemeral(mut self, ephemeral: bool) -> Self {
+        self.ephemeral = ephemeral;
+        self
+    }
+
+    fn into_interaction_response(self) -> CreateInteractionResponseMessage {
+        CreateInteractionResponseMessage::new()
+            .embeds(self.embeds)
+            .components(self.components)
+            .ephemeral(self.ephemeral)
+    }
+
+    fn into_edit(self) -> EditInteractionResponse {
+        EditInteractionResponse::new()
+            .embeds(self.embeds)
+            .components(self.components)
+    }
+
+    fn into_followup(self) -> CreateInteractionResponseFollowup {
+        CreateInteractionResponseFollowup::new()
+            .embeds(self.embeds)
+            .components(self.components)
+            .ephemeral(self.ephemeral)
+    }
+
+    fn into_message(self) -> CreateMessage {
+        CreateMessage::new()
+            .embeds(self.embeds)
+            .components(self.components)
+    }
+
+    fn into_message_edit(self) -> EditMessage {
+        EditMessage::new()
+            .embeds(self.embeds)
+            .components(self.components)
+    }
+}
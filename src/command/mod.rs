@@ -1,22 +1,53 @@
-use std::{future::Future, pin::Pin};
+use std::{future::Future, pin::{pin, Pin}, time::Duration};
 
 use eyre::{bail, Result};
 use hashbrown::HashMap;
 use serenity::{
-    all::{Context, CreateCommand},
+    all::{Context, CreateCommand, CreateEmbed},
     model::prelude::*,
 };
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::archmage::Archmage;
+use crate::archmage::{awaiting_approval, Archmage};
+use crate::model::campaign::Campaign;
+use cooldown::{Cooldown, CooldownTracker};
+use error::{reply_to_component, CommandError};
+use invocation::{Invocation, Reply};
+use prefix::ChatSyntax;
 
+pub mod access;
+pub mod admin;
+pub mod args;
+pub mod campaign;
+pub mod character;
+pub mod config;
+pub mod cooldown;
+pub mod error;
+pub mod help;
+pub mod history;
+pub mod inv;
+pub mod invocation;
+pub mod monitor;
+pub mod options;
 // mod music;
 pub mod ping;
+pub mod prefix;
+pub mod quest;
+pub mod resolve;
 pub mod roll;
+pub mod schema;
+pub mod sync;
+pub mod text;
+pub mod xp;
 //pub mod pbp;
 
+/// Custom IDs of components that the handler which sent them waits for with
+/// a collector, such as [resolve]'s campaign menu, start with this.
+pub const AWAITED: &str = "awaited:";
+
 pub struct CommandDispatcher {
-    commands: HashMap<String, (CreateCommand, HandleFn)>,
+    commands: HashMap<String, CommandRegistration>,
+    cooldowns: CooldownTracker,
 }
 
 impl CommandDispatcher {
@@ -25,6 +56,7 @@ impl CommandDispatcher {
     pub fn new() -> Self {
         Self {
             commands: HashMap::new(),
+            cooldowns: CooldownTracker::default(),
         }
     }
 
@@ -34,48 +66,409 @@ impl CommandDispatcher {
     where
         T: ArchmageCommand,
     {
-        for (name, create_command, runner) in T::register() {
+        for registration in T::register() {
+            let registration = registration.intents(T::intents());
+            let name = registration.name.clone();
             info!("Registered {name}");
             if self.commands.contains_key(&name) {
                 bail!("Dispatcher already contains command named {name}");
             }
-            let _ = self.commands.insert(name, (create_command, runner));
+            let _ = self.commands.insert(name, registration);
         }
 
         Ok(())
     }
 
-    /// Get the runnable function associated with the given key, or None.
-    pub fn get_runner(&self, k: impl AsRef<str>) -> Option<&HandleFn> {
-        self.commands.get(k.as_ref()).map(|(_, f)| f)
+    /// Get the registration associated with the given key, or None.
+    pub fn get(&self, k: impl AsRef<str>) -> Option<&CommandRegistration> {
+        self.commands.get(k.as_ref())
     }
 
     /// Shorthand to quickly run a given command. If the command is not present, returns None.
+    ///
+    /// Commands are subject to their cooldowns here. Overrides in the bot's
+    /// configuration take precedence over the defaults set at registration.
+    /// A rate-limited user is told to slow down, and the command is not run.
+    ///
+    /// If the command hasn't responded by the time configured in
+    /// `defer_after_ms`, its response is deferred; see [Invocation::defer].
+    ///
+    /// Errors the user can fix are explained to them here. Only internal
+    /// errors are returned.
     pub async fn run(
         &self,
-        k: impl AsRef<str>,
         server: &Archmage,
-        command: &CommandInteraction,
+        invocation: &Invocation,
         ctx: &Context,
     ) -> Option<Result<()>> {
-        match self.get_runner(k) {
-            None => None,
-            Some(f) => Some(f(server, command, ctx).await),
+        let name = invocation.name();
+        let registration = self.get(name)?;
+
+        let missing = registration.intents - server.intents();
+        if !missing.is_empty() {
+            return Some(Self::unavailable(invocation, ctx).await);
+        }
+
+        let config = server.config();
+        let group = registration.cooldown_group();
+        let cooldowns = config
+            .cooldowns
+            .get(name)
+            .or_else(|| config.cooldowns.get(group))
+            .unwrap_or(&registration.cooldowns);
+        if let Some(wait) = self.cooldowns.check(group, cooldowns, invocation) {
+            return Some(Self::slow_down(invocation, ctx, wait).await);
         }
+
+        // Discord gives up on interactions that aren't answered within three
+        // seconds, so slow handlers are deferred to buy them more time.
+        let mut handler = pin!(async {
+            // Commands that name their campaign are checked once they've
+            // found it, by [resolve::campaign].
+            if options::nested_string(invocation.options(), "campaign").is_none() {
+                self.require_channel_feature(server, ctx, name, invocation.channel_id())
+                    .await?;
+            }
+            (registration.handler)(server, invocation, ctx).await
+        });
+        let threshold = Duration::from_millis(config.defer_after_ms);
+        let result = tokio::select! {
+            result = &mut handler => result,
+            _ = tokio::time::sleep(threshold) => {
+                if let Err(e) = invocation.defer(ctx).await {
+                    warn!("Unable to defer slow command '{name}': {e}");
+                }
+                handler.await
+            }
+        };
+
+        Some(match result {
+            Ok(()) => Ok(()),
+            Err(CommandError::Internal(report)) => Err(report),
+            Err(error) => {
+                let embed = error.embed().expect("Only internal errors lack an embed");
+                invocation
+                    .respond(ctx, Reply::new().ephemeral(true).embed(embed))
+                    .await
+            }
+        })
     }
 
-    /// Get all metadata objects for all registered commands. Plural form of
-    /// [CommandDispatcher::get_def].
+    /// Route a message component interaction (a button press, a select menu
+    /// choice...) to the command that created it. Component custom IDs are
+    /// expected to be prefixed with the name of that command and a colon,
+    /// e.g. `help:2`. Returns None if no command claims the component.
+    ///
+    /// Components whose custom IDs start with [AWAITED] are claimed without
+    /// being routed anywhere, as the handler that sent them is waiting for
+    /// them itself. Components in guilds that aren't allowed aren't routed,
+    /// as commands there aren't run.
+    ///
+    /// As with [CommandDispatcher::run], only internal errors are returned.
+    pub async fn run_component(
+        &self,
+        server: &Archmage,
+        component: &ComponentInteraction,
+        ctx: &Context,
+    ) -> Option<Result<()>> {
+        if component.data.custom_id.starts_with(AWAITED) {
+            return Some(Ok(()));
+        }
+        let (name, _) = component.data.custom_id.split_once(':')?;
+        let handler = self.get(name)?.component_handler.as_ref()?;
+        // Buttons can outlive their guild being allowed, and their feature
+        // being on.
+        if !server.guild_permitted(component.guild_id).await {
+            return Some(reply_to_component(component, ctx, awaiting_approval()).await);
+        }
+        let result = match self
+            .require_channel_feature(server, ctx, name, component.channel_id)
+            .await
+        {
+            Ok(()) => handler(server, component, ctx).await,
+            Err(error) => Err(error),
+        };
+        Some(match result {
+            Ok(()) => Ok(()),
+            Err(CommandError::Internal(report)) => Err(report),
+            Err(error) => {
+                let embed = error.embed().expect("Only internal errors lack an embed");
+                reply_to_component(component, ctx, embed).await
+            }
+        })
+    }
+
+    /// Every gateway intent needed by at least one registered command.
+    pub fn intents(&self) -> GatewayIntents {
+        self.commands
+            .values()
+            .fold(GatewayIntents::empty(), |all, r| all | r.intents)
+    }
+
+    /// Get all metadata objects for all registered commands.
     pub fn get_all_defs(&self) -> impl Iterator<Item = &CreateCommand> {
-        self.commands.values().map(|(cc, _)| cc)
+        self.commands.values().map(|r| &r.definition)
+    }
+
+    /// Get every registered command, in no particular order.
+    pub fn registrations(&self) -> impl Iterator<Item = &CommandRegistration> {
+        self.commands.values()
+    }
+
+    /// Every feature that registered commands belong to, in order. These are
+    /// what DMs may turn on and off in their campaigns.
+    pub fn features(&self) -> Vec<&str> {
+        let mut features: Vec<&str> = self
+            .commands
+            .values()
+            .filter_map(|r| r.feature.as_deref())
+            .collect();
+        features.sort_unstable();
+        features.dedup();
+        features
+    }
+
+    /// Refuse to run a command in a campaign that has turned off the feature
+    /// it belongs to. The dispatcher checks the campaign the channel is bound
+    /// to before running the command; commands that find their campaign some
+    /// other way check it themselves, as [resolve::campaign] does.
+    pub async fn require_feature(
+        &self,
+        server: &Archmage,
+        name: &str,
+        campaign: &Campaign,
+    ) -> error::CommandResult {
+        let Some(feature) = self.get(name).and_then(|r| r.feature.as_deref()) else {
+            return Ok(());
+        };
+        match server.db().is_feature_enabled(&campaign.id, feature).await? {
+            true => Ok(()),
+            false => Err(CommandError::disabled(format!(
+                "`{name}` is part of {feature}, which is turned off in **{}**. A DM can turn it \
+                 back on with `/campaign enable {feature}`.",
+                campaign.name
+            ))),
+        }
+    }
+
+    /// [CommandDispatcher::require_feature], in the campaign a channel is
+    /// bound to. Channels that aren't bound to one don't refuse anything.
+    async fn require_channel_feature(
+        &self,
+        server: &Archmage,
+        ctx: &Context,
+        name: &str,
+        channel: ChannelId,
+    ) -> error::CommandResult {
+        // Most commands aren't part of a feature, and needn't look up the
+        // channel at all.
+        if self.get(name).and_then(|r| r.feature.as_ref()).is_none() {
+            return Ok(());
+        }
+        match resolve::bound(server, ctx, channel).await? {
+            Some(campaign) => self.require_feature(server, name, &campaign).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Tell the user a command can't be used, because Discord refused an
+    /// intent it needs.
+    async fn unavailable(invocation: &Invocation, ctx: &Context) -> Result<()> {
+        invocation
+            .respond(
+                ctx,
+                Reply::new().ephemeral(true).embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0xFF, 0xA5, 0x00))
+                        .description(format!(
+                            "`{}` has been disabled, as Archmage lacks the permissions it needs from Discord.",
+                            invocation.name()
+                        ))
+                        .title("Unavailable")
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await
+    }
+
+    /// Tell a rate-limited user when they may use the command again. Only
+    /// they can see the message, so it doesn't add to the flood.
+    async fn slow_down(invocation: &Invocation, ctx: &Context, wait: Duration) -> Result<()> {
+        let retry_at = chrono::Utc::now() + wait;
+        invocation
+            .respond(
+                ctx,
+                Reply::new().ephemeral(true).embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0xFF, 0xA5, 0x00))
+                        .description(format!(
+                            "The weave needs a moment to settle. You can use `{}` again <t:{}:R>.",
+                            invocation.name(),
+                            // Round up, so we never tell the user to come back too early.
+                            retry_at.timestamp() + 1
+                        ))
+                        .title("Slow down!")
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await
     }
 }
 
-pub type HandleFnResult = Result<(), eyre::Report>;
+/// Everything the dispatcher knows about a single command: the definition
+/// sent to Discord, the function that runs it, how often it may be run, and
+/// how to explain it to users.
+pub struct CommandRegistration {
+    name: String,
+    definition: CreateCommand,
+    handler: HandleFn,
+    component_handler: Option<ComponentFn>,
+    cooldowns: Vec<Cooldown>,
+    cooldown_group: Option<String>,
+    intents: GatewayIntents,
+    help: Option<String>,
+    examples: Vec<String>,
+    chat: ChatSyntax,
+    feature: Option<String>,
+}
+
+impl CommandRegistration {
+    /// Pair a command definition with its handler. The command has no
+    /// cooldowns until some are added with [CommandRegistration::cooldown].
+    pub fn new(name: impl Into<String>, definition: CreateCommand, handler: HandleFn) -> Self {
+        Self {
+            name: name.into(),
+            definition,
+            handler,
+            component_handler: None,
+            cooldowns: Vec::new(),
+            cooldown_group: None,
+            intents: GatewayIntents::empty(),
+            help: None,
+            examples: Vec::new(),
+            chat: ChatSyntax::default(),
+            feature: None,
+        }
+    }
+
+    /// Handle message components (buttons, select menus...) attached to this
+    /// command's responses. Their custom IDs must start with `<name>:`.
+    pub fn on_component(mut self, handler: ComponentFn) -> Self {
+        self.component_handler = Some(handler);
+        self
+    }
+
+    /// Add a default cooldown to this command. Every cooldown must have room
+    /// for the command to be run.
+    pub fn cooldown(mut self, cooldown: Cooldown) -> Self {
+        self.cooldowns.push(cooldown);
+        self
+    }
+
+    /// Share cooldowns with the command of the given name, as aliases do, so
+    /// that switching between them doesn't get around them. Overrides in the
+    /// bot's configuration given for that command apply to this one too,
+    /// unless it has its own.
+    pub fn shares_cooldowns_with(mut self, group: impl Into<String>) -> Self {
+        self.cooldown_group = Some(group.into());
+        self
+    }
+
+    /// Require gateway intents for this command, in addition to any it
+    /// already requires. Usually these come from [ArchmageCommand::intents].
+    pub fn intents(mut self, intents: GatewayIntents) -> Self {
+        self.intents |= intents;
+        self
+    }
+
+    /// Long-form help text shown by `/help <command>`, in addition to the
+    /// command's description.
+    pub fn help(mut self, text: impl Into<String>) -> Self {
+        self.help = Some(text.into());
+        self
+    }
+
+    /// Add an example invocation, shown by `/help <command>`.
+    pub fn example(mut self, example: impl Into<String>) -> Self {
+        self.examples.push(example.into());
+        self
+    }
+
+    /// Mark an option as one that chat commands give before the subcommand's
+    /// name rather than after it, like the campaign in
+    /// `!campaign <Campaign> set`. Leading options are given in the order
+    /// they're marked in. See [prefix] for how they're bound.
+    pub fn leading(mut self, option: impl Into<String>) -> Self {
+        self.chat.leading.push(option.into());
+        self
+    }
+
+    /// The subcommand chat commands use when none is named, like `show` for
+    /// `!inv <Character>`. Subcommands in groups are given by their path, like
+    /// `level set`.
+    pub fn default_subcommand(mut self, subcommand: impl Into<String>) -> Self {
+        self.chat.defaults.push(subcommand.into());
+        self
+    }
+
+    /// Make this command part of a feature, such as
+    /// [crate::model::feature::QUESTS], which DMs may turn off in their
+    /// campaigns. See [CommandDispatcher::require_feature].
+    pub fn feature(mut self, feature: impl Into<String>) -> Self {
+        self.feature = Some(feature.into());
+        self
+    }
+
+    /// The name the command is invoked by.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name of the command whose cooldowns this one counts against,
+    /// which is usually its own.
+    pub fn cooldown_group(&self) -> &str {
+        self.cooldown_group.as_deref().unwrap_or(&self.name)
+    }
+
+    /// The gateway intents this command needs to work.
+    pub fn required_intents(&self) -> GatewayIntents {
+        self.intents
+    }
+
+    /// The definition sent to Discord.
+    pub fn definition(&self) -> &CreateCommand {
+        &self.definition
+    }
+
+    /// How the command's chat form differs from its slash form.
+    pub fn chat_syntax(&self) -> &ChatSyntax {
+        &self.chat
+    }
+
+    /// Long-form help text, if any was given.
+    pub fn help_text(&self) -> Option<&str> {
+        self.help.as_deref()
+    }
+
+    /// Example invocations, if any were given.
+    pub fn examples(&self) -> &[String] {
+        &self.examples
+    }
+}
+
+pub type HandleFnResult = error::CommandResult;
 pub type HandleFnReturn<'a> = Pin<Box<dyn Future<Output=HandleFnResult> + Send + 'a>>;
-pub type HandleFn = Box<dyn for<'a> Fn(&'a Archmage, &'a CommandInteraction, &'a Context) -> HandleFnReturn<'a> + Send + Sync>;
+pub type HandleFn = Box<dyn for<'a> Fn(&'a Archmage, &'a Invocation, &'a Context) -> HandleFnReturn<'a> + Send + Sync>;
+pub type ComponentFn = Box<dyn for<'a> Fn(&'a Archmage, &'a ComponentInteraction, &'a Context) -> HandleFnReturn<'a> + Send + Sync>;
 pub trait ArchmageCommand {
-    fn register() -> Vec<(String, CreateCommand, HandleFn)>;
+    fn register() -> Vec<CommandRegistration>;
+
+    /// The gateway intents these commands need, e.g. to see voice states.
+    /// Archmage only asks Discord for intents something needs, so commands
+    /// that need anything beyond slash and chat commands must say so here.
+    fn intents() -> GatewayIntents {
+        GatewayIntents::empty()
+    }
 }
 
 macro_rules! handle_fn {
@@ -89,9 +482,12 @@ macro_rules! registerable_tuples {
     ($($i:ident),+) => {
         #[allow(unused_parens)]
         impl<$( $i ),+> ArchmageCommand for ( $( $i ),+ , ) where $( $i: ArchmageCommand ),+ {
-            fn register() -> Vec<(String, CreateCommand, HandleFn)> {
+            fn register() -> Vec<CommandRegistration> {
                 vec![
-                    $( $i::register() ),+
+                    $( $i::register()
+                        .into_iter()
+                        .map(|r| r.intents($i::intents()))
+                        .collect::<Vec<_>>() ),+
                 ]
                 .into_iter()
                 .flatten()
@@ -110,3 +506,10 @@ registerable_tuples!(T1, T2, T3, T4, T5, T6);
 registerable_tuples!(T1, T2, T3, T4, T5, T6, T7);
 registerable_tuples!(T1, T2, T3, T4, T5, T6, T7, T8);
 registerable_tuples!(T1, T2, T3, T4, T5, T6, T7, T8, T9);
+registerable_tuples!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
+registerable_tuples!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11);
+registerable_tuples!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12);
+registerable_tuples!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13);
+registerable_tuples!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14);
+registerable_tuples!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15);
+registerable_tuples!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16);
@@ -0,0 +1,298 @@
+//! Monitors: messages Archmage keeps up to date in a channel, showing a
+//! campaign's inventories, quests or characters. Commands that change what a
+//! monitor shows call [changed], and the monitor is redrawn shortly after, so
+//! that a burst of changes only edits its messages once. Messages that were
+//! deleted are sent again. Monitors of a feature the campaign has turned off
+//! aren't redrawn until it's turned back on.
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use eyre::{eyre, Result};
+use serenity::all::{
+    ChannelId, Color, CreateEmbed, CreateEmbedFooter, CreateMessage, EditMessage, Http, MessageId,
+    Timestamp,
+};
+use serenity::prelude::*;
+use tracing::warn;
+
+use crate::archmage::Archmage;
+use crate::database::MageDB;
+use crate::model::campaign::Campaign;
+use crate::model::inventory::Owner;
+use crate::model::monitor::Kind;
+use crate::model::quest::{QuestFilter, Status};
+use crate::model::xp;
+
+use super::quest;
+use super::text::{
+    embed_length, truncate, DESCRIPTION_LIMIT, EMBEDS_PER_MESSAGE, FIELD_LIMIT, MESSAGE_EMBED_LIMIT,
+};
+
+/// How long to wait after a change before redrawing, so that changes made
+/// in quick succession are drawn together.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Monitors waiting to be redrawn, and a lock so that only one is redrawn at a
+/// time. Without it, two redraws of a monitor could both send its messages.
+#[derive(Debug, Default)]
+pub struct Monitors {
+    pending: Arc<Mutex<HashSet<(i64, Kind)>>>,
+    redrawing: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Monitors {
+    /// Redraw a campaign's monitor of one kind once [DEBOUNCE] has passed,
+    /// unless a redraw is already waiting. Errors are logged, as there's no
+    /// one to show them to.
+    fn schedule(&self, db: &MageDB, http: &Arc<Http>, campaign_id: i64, kind: Kind) {
+        let newly = self
+            .pending
+            .lock()
+            .expect("Monitor lock poisoned")
+            .insert((campaign_id, kind));
+        if !newly {
+            return;
+        }
+
+        let (pending, redrawing) = (self.pending.clone(), self.redrawing.clone());
+        let (db, http) = (db.clone(), http.clone());
+        // Dropping the handle leaves the task running in the background.
+        drop(tokio::spawn(async move {
+            tokio::time::sleep(DEBOUNCE).await;
+            let _redrawing = redrawing.lock().await;
+            // Changes from here on are drawn by the next redraw. If there's
+            // nothing to claim, it's already been drawn by [Monitors::flush].
+            let claimed = pending
+                .lock()
+                .expect("Monitor lock poisoned")
+                .remove(&(campaign_id, kind));
+            if !claimed {
+                return;
+            }
+            if let Err(e) = redraw(&db, &http, campaign_id, kind).await {
+                warn!(
+                    "Unable to redraw the {} monitor of campaign {campaign_id}: {e}",
+                    kind.as_str()
+                );
+            }
+        }));
+    }
+
+    /// Redraw every monitor waiting to be redrawn right away, as on shutdown,
+    /// when there's no time to wait for them.
+    pub async fn flush(&self, db: &MageDB, http: &Http) {
+        let _redrawing = self.redrawing.lock().await;
+        let pending: Vec<_> = self
+            .pending
+            .lock()
+            .expect("Monitor lock poisoned")
+            .drain()
+            .collect();
+        for (campaign_id, kind) in pending {
+            if let Err(e) = redraw(db, http, campaign_id, kind).await {
+                warn!(
+                    "Unable to redraw the {} monitor of campaign {campaign_id}: {e}",
+                    kind.as_str()
+                );
+            }
+        }
+    }
+
+    /// Redraw a campaign's monitor of one kind right away.
+    pub async fn redraw(
+        &self,
+        db: &MageDB,
+        http: &Http,
+        campaign_id: i64,
+        kind: Kind,
+    ) -> Result<()> {
+        let _redrawing = self.redrawing.lock().await;
+        redraw(db, http, campaign_id, kind).await
+    }
+}
+
+/// Note that something a campaign's monitor of one kind shows has changed.
+/// Does nothing if the campaign doesn't monitor it.
+pub fn changed(archmage: &Archmage, ctx: &Context, campaign_id: i64, kind: Kind) {
+    archmage
+        .monitors()
+        .schedule(archmage.db(), &ctx.http, campaign_id, kind);
+}
+
+/// Delete a monitor's messages, as far as possible. Messages that are already
+/// gone, or can't be deleted, are left be.
+pub async fn clear(http: &Http, channel_id: u64, message_ids: &[u64]) {
+    let channel = ChannelId::new(channel_id);
+    for &id in message_ids {
+        let _ = channel.delete_message(http, MessageId::new(id)).await;
+    }
+}
+
+/// Bring a monitor's messages up to date, editing those it has and sending
+/// more as needed. If one was deleted, it and those after it are sent again,
+/// so that they stay in order.
+async fn redraw(db: &MageDB, http: &Http, campaign_id: i64, kind: Kind) -> Result<()> {
+    let Some(monitor) = db.monitor(&campaign_id, kind).await? else {
+        return Ok(());
+    };
+    let Some(campaign) = db.campaign_by_id(&campaign_id).await? else {
+        return Ok(());
+    };
+    if !db.is_feature_enabled(&campaign_id, kind.feature()).await? {
+        return Ok(());
+    }
+    let pages = pack(render(db, &campaign, kind).await?);
+
+    let channel = ChannelId::new(monitor.channel_id);
+    let mut sent = Vec::with_capacity(pages.len());
+    let mut resend = false;
+    for (i, embeds) in pages.into_iter().enumerate() {
+        let previous = monitor.message_ids.get(i).map(|&id| MessageId::new(id));
+        if let (Some(id), false) = (previous, resend) {
+            let edit = EditMessage::new().embeds(embeds.clone());
+            match channel.edit_message(http, id, edit).await {
+                Ok(_) => {
+                    sent.push(id.get());
+                    continue;
+                }
+                Err(e) if is_unknown_message(&e) => {
+                    resend = true;
+                    clear(http, monitor.channel_id, &monitor.message_ids[i..]).await;
+                }
+                Err(e) => return Err(eyre!(e)),
+            }
+        }
+        let message = channel
+            .send_message(http, CreateMessage::new().embeds(embeds))
+            .await
+            .map_err(|e| eyre!(e))?;
+        sent.push(message.id.get());
+    }
+    if !resend && monitor.message_ids.len() > sent.len() {
+        clear(http, monitor.channel_id, &monitor.message_ids[sent.len()..]).await;
+    }
+
+    db.set_monitor_messages(&campaign_id, kind, &monitor.channel_id, sent)
+        .await
+}
+
+/// Whether Discord refused an edit because the message is gone.
+fn is_unknown_message(error: &SerenityError) -> bool {
+    /// Unknown Message.
+    const UNKNOWN_MESSAGE: isize = 10008;
+    match error {
+        SerenityError::Http(HttpError::UnsuccessfulRequest(response)) => {
+            response.error.code == UNKNOWN_MESSAGE
+        }
+        _ => false,
+    }
+}
+
+/// Share embeds out between as few messages as Discord allows, in order.
+fn pack(embeds: Vec<CreateEmbed>) -> Vec<Vec<CreateEmbed>> {
+    let mut pages: Vec<Vec<CreateEmbed>> = Vec::new();
+    let mut room = 0;
+    for embed in embeds {
+        let length = embed_length(&embed);
+        match pages.last_mut() {
+            Some(page) if page.len() < EMBEDS_PER_MESSAGE && length <= room => {
+                page.push(embed);
+                room -= length;
+            }
+            _ => {
+                pages.push(vec![embed]);
+                room = MESSAGE_EMBED_LIMIT.saturating_sub(length);
+            }
+        }
+    }
+    pages
+}
+
+/// Everything a monitor shows, as embeds.
+async fn render(db: &MageDB, campaign: &Campaign, kind: Kind) -> Result<Vec<CreateEmbed>> {
+    let footer = || CreateEmbedFooter::new(&campaign.name);
+    let mut embeds = Vec::new();
+    match kind {
+        Kind::Inventory => {
+            let mut owners = vec![(Owner::Pot(campaign.id), "Party Pot".to_owned())];
+            for character in db.campaign_characters(&campaign.id).await? {
+                let title = format!("{}'s Inventory", character.name);
+                owners.push((Owner::Character(character.id), title));
+            }
+            for (owner, title) in owners {
+                let stacks = db.inventory(&owner).await?;
+                let description = match stacks.is_empty() {
+                    true => "Nothing yet.".to_owned(),
+                    false => stacks
+                        .iter()
+                        .map(|stack| format!("{} {}", stack.quantity, stack.item))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                };
+                embeds.push(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .title(title)
+                        .description(truncate(&description, DESCRIPTION_LIMIT))
+                        .footer(footer())
+                        .timestamp(Timestamp::now()),
+                );
+            }
+        }
+        Kind::Quests => {
+            let active = QuestFilter {
+                status: Some(Status::Active),
+                giver: None,
+            };
+            let quests = db.quests(&campaign.id, &active).await?;
+            embeds.extend(quests.iter().map(|q| quest::render(campaign, q)));
+            if embeds.is_empty() {
+                embeds.push(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .title("Active Quests")
+                        .description("No active quests.")
+                        .footer(footer())
+                        .timestamp(Timestamp::now()),
+                );
+            }
+        }
+        Kind::Characters => {
+            let levels = db.levels(&campaign.id).await?;
+            for character in db.campaign_characters(&campaign.id).await? {
+                let xp = db.experience(&character.id).await?.map_or(0, |e| e.xp);
+                let players = db.players(&character.id).await?;
+                let players = match players.is_empty() {
+                    true => "*None*".to_owned(),
+                    false => players
+                        .iter()
+                        .map(|p| format!("<@{p}>"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                };
+                embeds.push(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .title(&character.name)
+                        .field("Level", xp::level(&levels, xp).to_string(), true)
+                        .field("XP", xp.to_string(), true)
+                        .field("Players", truncate(&players, FIELD_LIMIT), true)
+                        .footer(footer())
+                        .timestamp(Timestamp::now()),
+                );
+            }
+            if embeds.is_empty() {
+                embeds.push(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .title("Characters")
+                        .description("No characters yet.")
+                        .footer(footer())
+                        .timestamp(Timestamp::now()),
+                );
+            }
+        }
+    }
+    Ok(embeds)
+}
@@ -0,0 +1,70 @@
+//! Reading the options a command was invoked with. Discord (or, for chat
+//! commands, [super::prefix]) makes sure required options are present, so a
+//! missing one is an internal error rather than the user's.
+use eyre::eyre;
+use serenity::all::{CommandDataOption, CommandDataOptionValue, UserId};
+
+use super::error::CommandResult;
+
+/// The subcommand (or subcommand group) that was chosen, and its options.
+pub fn subcommand(options: &[CommandDataOption]) -> Option<(&str, &[CommandDataOption])> {
+    let option = options.first()?;
+    match &option.value {
+        CommandDataOptionValue::SubCommand(nested)
+        | CommandDataOptionValue::SubCommandGroup(nested) => Some((&option.name, nested)),
+        _ => None,
+    }
+}
+
+/// A text option, if it was given.
+pub fn string<'a>(options: &'a [CommandDataOption], name: &str) -> Option<&'a str> {
+    options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_str())
+}
+
+/// A text option, if it was given to the command or to whichever of its
+/// subcommands was chosen.
+pub fn nested_string<'a>(options: &'a [CommandDataOption], name: &str) -> Option<&'a str> {
+    string(options, name).or_else(|| nested_string(subcommand(options)?.1, name))
+}
+
+/// A required text option.
+pub fn required_string<'a>(
+    options: &'a [CommandDataOption],
+    name: &str,
+) -> CommandResult<&'a str> {
+    string(options, name).ok_or(eyre!("Missing option '{name}'").into())
+}
+
+/// A user option, if it was given.
+pub fn user(options: &[CommandDataOption], name: &str) -> Option<UserId> {
+    options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_user_id())
+}
+
+/// A required user option.
+pub fn required_user(options: &[CommandDataOption], name: &str) -> CommandResult<UserId> {
+    user(options, name).ok_or(eyre!("Missing option '{name}'").into())
+}
+
+/// A required integer option.
+pub fn required_integer(options: &[CommandDataOption], name: &str) -> CommandResult<i64> {
+    options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_i64())
+        .ok_or(eyre!("Missing option '{name}'").into())
+}
+
+/// A required number option.
+pub fn required_number(options: &[CommandDataOption], name: &str) -> CommandResult<f64> {
+    options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_f64())
+        .ok_or(eyre!("Missing option '{name}'").into())
+}
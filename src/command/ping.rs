@@ -1,47 +1,44 @@
-use eyre::{eyre, Result};
 use serenity::{
-    all::{Color, CommandInteraction, CreateCommand, CreateEmbed, CreateInteractionResponseMessage, Timestamp},
+    all::{Color, CreateCommand, CreateEmbed, Timestamp},
     prelude::*,
 };
 use crate::{archmage::Archmage, command::handle_fn};
-use super::ArchmageCommand;
+use super::{error::CommandResult, invocation::{Invocation, Reply}, ArchmageCommand, CommandRegistration};
 
 pub struct PingCommand;
 
 impl ArchmageCommand for PingCommand {
-    fn register() -> Vec<(String, CreateCommand, super::HandleFn)> {
-        vec![(
-            "ping".to_owned(),
+    fn register() -> Vec<CommandRegistration> {
+        vec![CommandRegistration::new(
+            "ping",
             CreateCommand::new("ping").description("Determine server command (not network) latency"),
             handle_fn!(Self::run),
-        )]
+        )
+        .help("Measures how long Archmage takes to notice a command, not how long it takes to reach you.")]
     }
 }
 
 impl PingCommand {
     async fn run(
         _client: &Archmage,
-        command: &CommandInteraction,
+        invocation: &Invocation,
         ctx: &Context,
-    ) -> Result<()> {
-        command
-            .create_response(
-                &ctx.http,
-                serenity::all::CreateInteractionResponse::Message(
-                    CreateInteractionResponseMessage::new().embed(
-                        CreateEmbed::new()
-                            .color(Color::from_rgb(0x00, 0xFF, 0x00))
-                            .description(format!(
-                                "Received {} UTC",
-                                chrono::Utc::now().naive_utc().format("%Y-%m-%d %H:%M:%S%.6f")
-                            ))
-                            .title("Pong!")
-                            .timestamp(Timestamp::now()),
-                    ),
+    ) -> CommandResult {
+        invocation
+            .respond(
+                ctx,
+                Reply::new().embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .description(format!(
+                            "Received {} UTC",
+                            chrono::Utc::now().naive_utc().format("%Y-%m-%d %H:%M:%S%.6f")
+                        ))
+                        .title("Pong!")
+                        .timestamp(Timestamp::now()),
                 ),
             )
-            .await
-            .map(|_| ())
-            .map_err(|e| eyre!(e))
+            .await?;
+        Ok(())
     }
 }
\ No newline at end of file
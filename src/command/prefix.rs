@@ -0,0 +1,470 @@
+//! Chat commands such as `!roll 1d20`. Their arguments are bound to the same
+//! options a slash command would receive, using the command's own definition,
+//! so that every command works both ways without any extra code.
+//!
+//! Arguments are matched to options in the order they are defined. For
+//! commands with subcommands, the first word naming a subcommand selects it.
+//! Words before it fill the command's leading options (see
+//! [super::CommandRegistration::leading]), which allows the
+//! `!campaign [Campaign] set <#channel>` style used throughout CMDAPI.md.
+//! When there are fewer words than leading options, the earliest optional
+//! ones are left out, as in `!inv [Campaign] <Character> add <Item>` and
+//! `!char [Campaign] <Character> [Tag]`. Commands without
+//! leading options use any words before the subcommand as its first options.
+//! If no word names a subcommand, the command's default subcommand (if it has
+//! one) is used, with every word counting as coming before it. Subcommand
+//! groups may have defaults too, as in `!campaign [Campaign] level 2 1000`,
+//! but words after a group's name still come after its default's.
+use serde_json::{json, Value};
+use serenity::all::{CommandDataOption, CommandOptionType, CreateCommand};
+
+use super::args::{tokenize, Token};
+use super::schema::{
+    self, choices, definition_json, is_required, is_subcommand, kind, kind_name, text,
+};
+
+/// The prefix used in guilds that haven't chosen their own.
+pub const DEFAULT_PREFIX: &str = "!";
+
+/// How a command's chat form differs from its slash form. Set with
+/// [super::CommandRegistration::leading] and
+/// [super::CommandRegistration::default_subcommand].
+#[derive(Debug, Default)]
+pub struct ChatSyntax {
+    /// Options given before the subcommand's name, in order.
+    pub leading: Vec<String>,
+    /// The subcommands used when none is named, as paths such as `show`, or
+    /// `level set` for one in a group.
+    pub defaults: Vec<String>,
+}
+
+/// Split a message into the (lowercase) command name and the rest of the
+/// message, if it begins with the prefix.
+pub fn split_command<'a>(content: &'a str, prefix: &str) -> Option<(String, &'a str)> {
+    let rest = content.strip_prefix(prefix)?;
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    if end == 0 {
+        return None;
+    }
+    Some((rest[..end].to_lowercase(), rest[end..].trim()))
+}
+
+/// Bind the arguments of a chat command to the options in its definition.
+/// Errors are meant to be shown to the user as-is.
+pub fn parse_options(
+    definition: &CreateCommand,
+    syntax: &ChatSyntax,
+    args: &str,
+) -> Result<Vec<CommandDataOption>, String> {
+    let definition = definition_json(definition);
+    let tokens = tokenize(args);
+    let bound = bind(
+        schema::options(&definition),
+        syntax,
+        args,
+        &[],
+        &[],
+        &tokens,
+    )?;
+    serde_json::from_value(Value::Array(bound))
+        .map_err(|e| format!("Those arguments didn't make sense to me ({e})"))
+}
+
+/// Bind tokens to a level of options, recursing into the chosen subcommand.
+/// `path` names the groups above this level, and `leading` holds the words
+/// that came before the subcommand's name.
+fn bind(
+    options: &[Value],
+    syntax: &ChatSyntax,
+    input: &str,
+    path: &[&str],
+    leading: &[Token],
+    tokens: &[Token],
+) -> Result<Vec<Value>, String> {
+    let subcommands: Vec<&Value> = options.iter().filter(|o| is_subcommand(o)).collect();
+    if !subcommands.is_empty() {
+        let chosen = tokens.iter().enumerate().find_map(|(i, token)| {
+            let subcommand = subcommands
+                .iter()
+                .find(|s| !token.quoted && text(s, "name").eq_ignore_ascii_case(&token.text))?;
+            Some((i, i + 1, subcommand))
+        });
+        let default = || {
+            let subcommand = subcommands.iter().find(|s| {
+                let mut default = path.to_vec();
+                default.push(text(s, "name"));
+                syntax.defaults.contains(&default.join(" "))
+            })?;
+            // At the top level the words all come before the default, but in
+            // a group they come after the group's name.
+            let end = if path.is_empty() { tokens.len() } else { 0 };
+            Some((end, end, subcommand))
+        };
+        let Some((end, start, subcommand)) = chosen.or_else(default) else {
+            let names: Vec<_> = subcommands
+                .iter()
+                .map(|s| format!("`{}`", text(s, "name")))
+                .collect();
+            return Err(format!("Expected one of {}.", names.join(", ")));
+        };
+
+        let mut before = leading.to_vec();
+        before.extend_from_slice(&tokens[..end]);
+        let after = &tokens[start..];
+        let mut path = path.to_vec();
+        path.push(text(subcommand, "name"));
+        let nested = bind(schema::options(subcommand), syntax, input, &path, &before, after)?;
+        return Ok(vec![json!({
+            "name": text(subcommand, "name"),
+            "type": subcommand.get("type"),
+            "options": nested,
+        })]);
+    }
+
+    let reserved: Vec<&Value> = syntax
+        .leading
+        .iter()
+        .filter_map(|name| options.iter().find(|o| text(o, "name") == name))
+        .collect();
+    let options: Vec<&Value> = options
+        .iter()
+        .filter(|o| !syntax.leading.iter().any(|name| name == text(o, "name")))
+        .collect();
+    let (mut bound, words): (Vec<Value>, Vec<&Token>) = match reserved.is_empty() {
+        true => (Vec::new(), leading.iter().chain(tokens).collect()),
+        false => (bind_leading(&reserved, leading)?, tokens.iter().collect()),
+    };
+    // How many of the words came before the subcommand's name.
+    let before = words.len() - tokens.len();
+
+    let mut cursor = 0;
+    for (index, option) in options.iter().enumerate() {
+        let name = text(option, "name");
+        let Some(word) = words.get(cursor) else {
+            if is_required(option) {
+                return Err(format!("Missing a value for `{name}`."));
+            }
+            continue;
+        };
+
+        // Mentions are never meant as text, so optional text options leave
+        // them for the options after, e.g. the channel in `!campaign set #dnd`.
+        let text_option = kind(option) == Some(CommandOptionType::String);
+        if text_option && !is_required(option) && !word.quoted && is_mention(&word.text) {
+            continue;
+        }
+
+        // The last text option soaks up the rest of the line, so that items
+        // and values don't need to be quoted just because they have spaces.
+        let last = index + 1 == options.len();
+        if last && text_option && words.len() - cursor > 1 {
+            bound.push(json!({
+                "name": name,
+                "type": option.get("type"),
+                "value": rest_of_line(input, before, cursor, &words),
+            }));
+            cursor = words.len();
+            continue;
+        }
+
+        match convert(option, &word.text) {
+            Some(value) => {
+                bound.push(json!({ "name": name, "type": option.get("type"), "value": value }));
+                cursor += 1;
+            }
+            // Optional options may be skipped, e.g. the amount in `add Gold`.
+            None if !is_required(option) => continue,
+            None => {
+                return Err(format!(
+                    "`{}` isn't a valid {} for `{name}`.",
+                    word.text,
+                    kind_name(kind(option))
+                ))
+            }
+        }
+    }
+
+    if let Some(extra) = words.get(cursor) {
+        return Err(format!("I don't know what to do with `{}`.", extra.text));
+    }
+    Ok(bound)
+}
+
+/// Bind the words before the subcommand's name to its leading options, one
+/// each. If there are fewer words than options, the earliest optional ones
+/// are left out.
+fn bind_leading(options: &[&Value], words: &[Token]) -> Result<Vec<Value>, String> {
+    if words.len() > options.len() {
+        return Err(format!("I don't know what to do with `{}`.", words[0].text));
+    }
+    let mut skip = options.len() - words.len();
+    let mut filled = Vec::with_capacity(words.len());
+    for option in options {
+        match skip > 0 && !is_required(option) {
+            true => skip -= 1,
+            false => filled.push(*option),
+        }
+    }
+    if let Some(missing) = filled.get(words.len()) {
+        return Err(format!("Missing a value for `{}`.", text(missing, "name")));
+    }
+
+    filled
+        .iter()
+        .zip(words)
+        .map(|(option, word)| {
+            let name = text(option, "name");
+            match convert(option, &word.text) {
+                Some(value) => Ok(json!({ "name": name, "type": option.get("type"), "value": value })),
+                None => Err(format!(
+                    "`{}` isn't a valid {} for `{name}`.",
+                    word.text,
+                    kind_name(kind(option))
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Everything from the word at `cursor` onwards. If those words all came after
+/// the subcommand they are taken verbatim, quotes and all, so that commands
+/// which accept several quoted values at once can split them up themselves.
+fn rest_of_line(input: &str, leading: usize, cursor: usize, words: &[&Token]) -> String {
+    if cursor >= leading {
+        return input[words[cursor].start..].trim_end().to_owned();
+    }
+    words[cursor..]
+        .iter()
+        .map(|w| match w.quoted {
+            true => format!("\"{}\"", w.text),
+            false => w.text.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Convert a word to the JSON Discord would send for the given option, or
+/// None if it isn't a valid value for it.
+fn convert(option: &Value, word: &str) -> Option<Value> {
+    let choices = choices(option);
+    if !choices.is_empty() {
+        return choices
+            .iter()
+            .find(|c| {
+                text(c, "name").eq_ignore_ascii_case(word)
+                    || c.get("value").is_some_and(|v| match v {
+                        Value::String(s) => s.eq_ignore_ascii_case(word),
+                        other => other.as_i64().is_some() && other.as_i64() == word.parse().ok(),
+                    })
+            })
+            .and_then(|c| c.get("value").cloned());
+    }
+
+    match kind(option)? {
+        CommandOptionType::String => Some(json!(word)),
+        CommandOptionType::Integer => word.parse::<i64>().ok().map(|n| json!(n)),
+        CommandOptionType::Number => word.parse::<f64>().ok().map(|n| json!(n)),
+        CommandOptionType::Boolean => match word.to_lowercase().as_str() {
+            "true" | "yes" | "on" => Some(json!(true)),
+            "false" | "no" | "off" => Some(json!(false)),
+            _ => None,
+        },
+        CommandOptionType::User => mention(word, &["<@!", "<@"]),
+        CommandOptionType::Channel => mention(word, &["<#"]),
+        CommandOptionType::Role => mention(word, &["<@&"]),
+        CommandOptionType::Mentionable => mention(word, &["<@&", "<@!", "<@"]),
+        _ => None,
+    }
+}
+
+/// Whether a word is a user, role or channel mention.
+fn is_mention(word: &str) -> bool {
+    (word.starts_with("<@") || word.starts_with("<#")) && word.ends_with('>')
+}
+
+/// Read an ID out of a mention such as `<@123>`, or a bare ID.
+fn mention(word: &str, prefixes: &[&str]) -> Option<Value> {
+    let id = prefixes
+        .iter()
+        .find_map(|p| word.strip_prefix(p)?.strip_suffix('>'))
+        .unwrap_or(word);
+    id.parse::<u64>().ok().map(|id| json!(id.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use serenity::all::CreateCommandOption;
+
+    use super::*;
+    use crate::command::campaign::CampaignCommand;
+    use crate::command::inv::InvCommand;
+    use crate::command::xp::XpCommand;
+    use crate::command::{ArchmageCommand, CommandRegistration};
+
+    /// Bind arguments the way the first of `registrations` would, as the
+    /// JSON Discord would have sent for them.
+    fn parse(registrations: Vec<CommandRegistration>, args: &str) -> Result<Value, String> {
+        let registration = &registrations[0];
+        parse_options(registration.definition(), registration.chat_syntax(), args)
+            .map(|options| serde_json::to_value(options).unwrap())
+    }
+
+    /// A command with no subcommands: `!note <title> [count] [text]`.
+    fn note(args: &str) -> Result<Value, String> {
+        let definition = CreateCommand::new("note")
+            .description("Take a note")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::String, "title", "Title")
+                    .required(true),
+            )
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "count",
+                "Count",
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "text",
+                "Text",
+            ));
+        parse_options(&definition, &ChatSyntax::default(), args)
+            .map(|options| serde_json::to_value(options).unwrap())
+    }
+
+    #[test]
+    fn splits_off_the_command() {
+        assert_eq!(
+            split_command("!Roll 1d20 + 5 ", "!"),
+            Some(("roll".to_owned(), "1d20 + 5"))
+        );
+        assert_eq!(split_command("!help", "!"), Some(("help".to_owned(), "")));
+        assert_eq!(split_command("! roll", "!"), None);
+        assert_eq!(split_command("roll", "!"), None);
+    }
+
+    #[test]
+    fn inventory_example() {
+        assert_eq!(
+            parse(
+                InvCommand::register(),
+                "Calmasis add 1 \"Potion of Strength\""
+            ),
+            Ok(json!([{
+                "name": "add",
+                "type": 1,
+                "options": [
+                    { "name": "character", "type": 3, "value": "Calmasis" },
+                    { "name": "items", "type": 3, "value": "1 \"Potion of Strength\"" },
+                ],
+            }]))
+        );
+    }
+
+    #[test]
+    fn campaign_example() {
+        assert_eq!(
+            parse(CampaignCommand::register(), "\"Lost Mine\" add <@123> Cal"),
+            Ok(json!([{
+                "name": "add",
+                "type": 1,
+                "options": [
+                    { "name": "campaign", "type": 3, "value": "Lost Mine" },
+                    { "name": "player", "type": 6, "value": "123" },
+                    { "name": "character", "type": 3, "value": "Cal" },
+                ],
+            }]))
+        );
+    }
+
+    #[test]
+    fn xp_example() {
+        assert_eq!(
+            parse(XpCommand::register(), "Cal add 100"),
+            Ok(json!([{
+                "name": "add",
+                "type": 1,
+                "options": [
+                    { "name": "character", "type": 3, "value": "Cal" },
+                    { "name": "amount", "type": 4, "value": 100 },
+                ],
+            }]))
+        );
+    }
+
+    #[test]
+    fn leading_options_fill_from_the_end() {
+        let bound = parse(XpCommand::register(), "\"Lost Mine\" Cal add 100").unwrap();
+        assert_eq!(
+            bound[0]["options"][0],
+            json!({ "name": "campaign", "type": 3, "value": "Lost Mine" })
+        );
+        assert_eq!(
+            parse(XpCommand::register(), "Lost Mine Cal add 100"),
+            Err("I don't know what to do with `Lost`.".to_owned())
+        );
+        assert_eq!(
+            parse(XpCommand::register(), "add 100"),
+            Err("Missing a value for `character`.".to_owned())
+        );
+    }
+
+    #[test]
+    fn default_subcommand() {
+        assert_eq!(
+            parse(InvCommand::register(), "Cal"),
+            Ok(json!([{
+                "name": "show",
+                "type": 1,
+                "options": [{ "name": "character", "type": 3, "value": "Cal" }],
+            }]))
+        );
+        // Quoted words are never subcommand names.
+        let bound = parse(InvCommand::register(), "\"add\"").unwrap();
+        assert_eq!(bound[0]["name"], "show");
+        assert_eq!(bound[0]["options"][0]["value"], "add");
+        // Nor are words after a group's name, which go to its default.
+        let bound = parse(CampaignCommand::register(), "level 2 1000").unwrap();
+        assert_eq!(bound[0]["name"], "level");
+        assert_eq!(bound[0]["options"][0]["name"], "set");
+    }
+
+    #[test]
+    fn rest_of_line() {
+        assert_eq!(
+            note("Shopping 3 Rope, \"50 ft\";  chalk"),
+            Ok(json!([
+                { "name": "title", "type": 3, "value": "Shopping" },
+                { "name": "count", "type": 4, "value": 3 },
+                { "name": "text", "type": 3, "value": "Rope, \"50 ft\";  chalk" },
+            ]))
+        );
+        // Optional options are skipped when the word doesn't fit them.
+        assert_eq!(
+            note("Shopping Rope"),
+            Ok(json!([
+                { "name": "title", "type": 3, "value": "Shopping" },
+                { "name": "text", "type": 3, "value": "Rope" },
+            ]))
+        );
+        assert_eq!(
+            note("\"Long title\""),
+            Ok(json!([{ "name": "title", "type": 3, "value": "Long title" }]))
+        );
+        assert_eq!(note(""), Err("Missing a value for `title`.".to_owned()));
+    }
+
+    #[test]
+    fn mentions() {
+        assert_eq!(
+            parse(CampaignCommand::register(), "Lost add Cal <@123>"),
+            Err("`Cal` isn't a valid user for `player`.".to_owned())
+        );
+        // Optional text options leave mentions to the options after them.
+        let bound = parse(CampaignCommand::register(), "set <#456>").unwrap();
+        assert_eq!(
+            bound[0]["options"],
+            json!([{ "name": "channel", "type": 7, "value": "456" }])
+        );
+    }
+}
@@ -0,0 +1,523 @@
+//! Quest logs. Anyone may read a campaign's quests, but only its players and
+//! DMs may add or change them. Quests are known by their number in the log.
+use eyre::eyre;
+use serenity::all::{
+    Color, CommandDataOption, CommandOptionType, CreateCommand, CreateCommandOption, CreateEmbed,
+    CreateEmbedAuthor, CreateEmbedFooter, Timestamp,
+};
+use serenity::prelude::*;
+
+use crate::archmage::Archmage;
+use crate::model::campaign::Campaign;
+use crate::model::feature;
+use crate::model::monitor::Kind;
+use crate::model::quest::{check_objective, check_title, Field, Quest, QuestFilter, Status};
+
+use super::access::require_party;
+use super::args::{split_batch, tokenize};
+use super::error::{CommandError, CommandResult};
+use super::invocation::{Invocation, Reply};
+use super::monitor;
+use super::options::{self, required_integer, required_string};
+use super::text::{truncate, DESCRIPTION_LIMIT, FIELD_LIMIT};
+use super::{handle_fn, resolve, ArchmageCommand, CommandRegistration};
+
+/// The `status` filter's choice for quests of every status.
+const ANY_STATUS: &str = "all";
+
+pub struct QuestCommand;
+
+impl ArchmageCommand for QuestCommand {
+    fn register() -> Vec<CommandRegistration> {
+        vec![CommandRegistration::new(
+            "quest",
+            CreateCommand::new("quest")
+                .description("Keep the party's quest log")
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "list",
+                        "List the campaign's quests",
+                    )
+                    .add_sub_option(status_option())
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "giver",
+                        "Only list quests from givers whose names include this",
+                    ))
+                    .add_sub_option(campaign_option()),
+                )
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "show",
+                        "Show a quest and its objectives",
+                    )
+                    .add_sub_option(quest_option())
+                    .add_sub_option(campaign_option()),
+                )
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "add",
+                        "Add a quest to the log",
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::String,
+                            "title",
+                            "What the quest is called",
+                        )
+                        .required(true),
+                    )
+                    .add_sub_option(campaign_option()),
+                )
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "edit",
+                        "Change a quest's title, description, giver or reward",
+                    )
+                    .add_sub_option(quest_option())
+                    .add_sub_option(field_option())
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "value",
+                        "The new value. Leave it out to clear it",
+                    ))
+                    .add_sub_option(campaign_option()),
+                )
+                .add_option(status_subcommand("complete", "Mark a quest completed"))
+                .add_option(status_subcommand("fail", "Mark a quest failed"))
+                .add_option(status_subcommand("abandon", "Mark a quest abandoned"))
+                .add_option(status_subcommand(
+                    "reopen",
+                    "Make a finished quest active again",
+                ))
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommandGroup,
+                        "objective",
+                        "Change a quest's checklist of objectives",
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "add",
+                            "Add objectives to the end of a quest's checklist",
+                        )
+                        .add_sub_option(quest_option())
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::String,
+                                "objectives",
+                                "Objectives to add, e.g. Find the cave; Defeat Klarg",
+                            )
+                            .required(true),
+                        )
+                        .add_sub_option(campaign_option()),
+                    )
+                    .add_sub_option(objective_subcommand("check", "Tick off an objective"))
+                    .add_sub_option(objective_subcommand(
+                        "uncheck",
+                        "Mark an objective as not done after all",
+                    ))
+                    .add_sub_option(objective_subcommand(
+                        "remove",
+                        "Remove an objective from a quest's checklist",
+                    )),
+                ),
+            handle_fn!(Self::run),
+        )
+        .leading("campaign")
+        .feature(feature::QUESTS)
+        .default_subcommand("list")
+        .help(
+            "Anyone may read a campaign's quest log, but only its players and DMs may change \
+             it. Quests are numbered in the order they're added, and named by their number.\n\n\
+             `list` shows active quests unless given another status, or `all`. Quests stay in \
+             the log once they're completed, failed or abandoned, and can be reopened.\n\n\
+             Several objectives can be added at once by separating them with semicolons. \
+             Objectives are numbered in order, for checking them off or removing them.",
+        )
+        .example("/quest list")
+        .example("/quest add Rescue Gundren")
+        .example("/quest edit 1 giver Sildar Hallwinter")
+        .example("/quest objective add 1 Find Cragmaw Castle; Free Gundren")
+        .example("/quest objective check 1 2")
+        .example("/quest complete 1")]
+    }
+}
+
+impl QuestCommand {
+    async fn run(archmage: &Archmage, invocation: &Invocation, ctx: &Context) -> CommandResult {
+        let Some((subcommand, options)) = options::subcommand(invocation.options()) else {
+            return Err(eyre!("/quest used without a subcommand").into());
+        };
+        let (group, subcommand, options) = match subcommand {
+            "objective" => {
+                let (subcommand, options) = options::subcommand(options)
+                    .ok_or(eyre!("/quest objective used without a subcommand"))?;
+                (Some("objective"), subcommand, options)
+            }
+            _ => (None, subcommand, options),
+        };
+
+        let campaign = resolve::campaign(archmage, invocation, ctx, options).await?;
+        match (group, subcommand) {
+            (None, "list") => {
+                return Self::list(archmage, invocation, ctx, &campaign, options).await
+            }
+            (None, "show") => {
+                let quest = quest(archmage, &campaign, options).await?;
+                invocation
+                    .respond(ctx, Reply::new().embed(render(&campaign, &quest)))
+                    .await?;
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let played = archmage
+            .db()
+            .characters_played(&campaign.id, &invocation.user().id.get())
+            .await?;
+        let permissions = invocation.member_permissions(ctx).await?;
+        require_party(
+            archmage,
+            &campaign,
+            played.first(),
+            invocation.user().id,
+            permissions,
+        )
+        .await?;
+
+        let user_id = invocation.user().id.get();
+        let (quest, description) = match (group, subcommand) {
+            (None, "add") => {
+                let title =
+                    check_title(required_string(options, "title")?).map_err(CommandError::user)?;
+                let quest = archmage
+                    .db()
+                    .add_quest(&campaign.id, &title, &user_id)
+                    .await?;
+                (quest, "Quest added.".to_owned())
+            }
+            (None, "edit") => Self::edit(archmage, &campaign, options, &user_id).await?,
+            (None, "complete" | "fail" | "abandon" | "reopen") => {
+                let status = match subcommand {
+                    "complete" => Status::Completed,
+                    "fail" => Status::Failed,
+                    "abandon" => Status::Abandoned,
+                    _ => Status::Active,
+                };
+                let quest = quest(archmage, &campaign, options).await?;
+                if quest.status == status {
+                    return Err(CommandError::user(format!(
+                        "Quest #{} is already {}.",
+                        quest.number,
+                        status.as_str()
+                    )));
+                }
+                archmage
+                    .db()
+                    .set_quest_status(&quest.id, status, &user_id)
+                    .await?;
+                (quest, format!("Quest {}.", status.as_str()))
+            }
+            (Some("objective"), _) => {
+                Self::change_objectives(archmage, &campaign, subcommand, options, &user_id).await?
+            }
+            (_, other) => return Err(eyre!("Unknown /quest subcommand {other}").into()),
+        };
+        monitor::changed(archmage, ctx, campaign.id, Kind::Quests);
+
+        // Show the quest as it is now, after the change.
+        let Some(quest) = archmage.db().quest(&campaign.id, &quest.number).await? else {
+            return Err(eyre!("Quest #{} disappeared while being changed", quest.number).into());
+        };
+        let embed = render(&campaign, &quest).author(CreateEmbedAuthor::new(description));
+        invocation.respond(ctx, Reply::new().embed(embed)).await?;
+        Ok(())
+    }
+
+    async fn list(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        campaign: &Campaign,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let status = match options::string(options, "status") {
+            None => Some(Status::Active),
+            Some(ANY_STATUS) => None,
+            Some(status) => {
+                Some(Status::parse(status).ok_or(eyre!("Unknown quest status '{status}'"))?)
+            }
+        };
+        let filter = QuestFilter {
+            status,
+            giver: options::string(options, "giver")
+                .map(str::trim)
+                .filter(|giver| !giver.is_empty())
+                .map(str::to_owned),
+        };
+        let quests = archmage.db().quests(&campaign.id, &filter).await?;
+
+        let title = match status {
+            Some(status) => format!("{} Quests", status.label()),
+            None => "Quests".to_owned(),
+        };
+        let description = match quests.is_empty() {
+            true => "No quests match.".to_owned(),
+            false => quests
+                .iter()
+                .map(|quest| summary(quest, status.is_none()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        invocation
+            .respond(
+                ctx,
+                Reply::new().embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .title(title)
+                        .description(truncate(&description, DESCRIPTION_LIMIT))
+                        .footer(CreateEmbedFooter::new(&campaign.name))
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn edit(
+        archmage: &Archmage,
+        campaign: &Campaign,
+        options: &[CommandDataOption],
+        user_id: &u64,
+    ) -> CommandResult<(Quest, String)> {
+        let field = required_string(options, "field")?;
+        let field = Field::parse(field).ok_or(eyre!("Unknown quest field '{field}'"))?;
+        let value = field
+            .check(options::string(options, "value").unwrap_or_default())
+            .map_err(CommandError::user)?;
+        let quest = quest(archmage, campaign, options).await?;
+        let description = match value {
+            Some(_) => format!("Set the {}.", field.as_str()),
+            None => format!("Cleared the {}.", field.as_str()),
+        };
+        archmage
+            .db()
+            .edit_quest(&quest.id, field, value, user_id)
+            .await?;
+        Ok((quest, description))
+    }
+
+    async fn change_objectives(
+        archmage: &Archmage,
+        campaign: &Campaign,
+        subcommand: &str,
+        options: &[CommandDataOption],
+        user_id: &u64,
+    ) -> CommandResult<(Quest, String)> {
+        let quest = quest(archmage, campaign, options).await?;
+        if subcommand == "add" {
+            let objectives =
+                objectives(required_string(options, "objectives")?).map_err(CommandError::user)?;
+            let count = objectives.len();
+            archmage
+                .db()
+                .add_objectives(&quest.id, objectives, user_id)
+                .await?
+                .map_err(CommandError::user)?;
+            let description = match count {
+                1 => "Added an objective.".to_owned(),
+                count => format!("Added {count} objectives."),
+            };
+            return Ok((quest, description));
+        }
+
+        let number = required_integer(options, "number")?;
+        let index = usize::try_from(number).unwrap_or_default();
+        let missing = || {
+            CommandError::not_found(format!(
+                "Quest #{} has no objective {number}.",
+                quest.number
+            ))
+        };
+        let description = match subcommand {
+            "check" | "uncheck" => {
+                let objective = archmage
+                    .db()
+                    .set_objective_done(&quest.id, index, subcommand == "check", user_id)
+                    .await?
+                    .ok_or_else(missing)?;
+                match objective.done {
+                    true => format!("Checked off {}.", objective.text),
+                    false => format!("Unchecked {}.", objective.text),
+                }
+            }
+            "remove" => {
+                let text = archmage
+                    .db()
+                    .remove_objective(&quest.id, index, user_id)
+                    .await?
+                    .ok_or_else(missing)?;
+                format!("Removed {text}.")
+            }
+            other => return Err(eyre!("Unknown /quest objective subcommand {other}").into()),
+        };
+        Ok((quest, description))
+    }
+}
+
+/// The quest named by the `quest` option.
+async fn quest(
+    archmage: &Archmage,
+    campaign: &Campaign,
+    options: &[CommandDataOption],
+) -> CommandResult<Quest> {
+    let number = required_integer(options, "quest")?;
+    archmage
+        .db()
+        .quest(&campaign.id, &number)
+        .await?
+        .ok_or_else(|| {
+            CommandError::not_found(format!(
+                "**{}** has no quest #{number}. `/quest list all` shows every quest.",
+                campaign.name
+            ))
+        })
+}
+
+/// Read a batch of objectives separated by semicolons. A quoted objective
+/// loses its quotes.
+fn objectives(input: &str) -> Result<Vec<String>, String> {
+    let objectives = split_batch(input)
+        .iter()
+        .filter(|part| !part.trim().is_empty())
+        .map(|part| match tokenize(part).as_slice() {
+            [only] if only.quoted => check_objective(&only.text),
+            _ => check_objective(part),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    match objectives.is_empty() {
+        true => Err("Which objectives?".to_owned()),
+        false => Ok(objectives),
+    }
+}
+
+/// A line for a quest in a list, e.g. `#1 Rescue Gundren (1/2), from Sildar`.
+fn summary(quest: &Quest, with_status: bool) -> String {
+    let mut line = format!("**#{}** {}", quest.number, quest.title);
+    let (done, total) = quest.progress();
+    if total > 0 {
+        line.push_str(&format!(" ({done}/{total})"));
+    }
+    if let Some(giver) = &quest.giver {
+        line.push_str(&format!(", from {giver}"));
+    }
+    if with_status && quest.status != Status::Active {
+        line.push_str(&format!(" *{}*", quest.status.label()));
+    }
+    line
+}
+
+/// The whole of a quest, coloured by its status.
+pub(super) fn render(campaign: &Campaign, quest: &Quest) -> CreateEmbed {
+    let color = match quest.status {
+        Status::Active => Color::from_rgb(0x00, 0xFF, 0x00),
+        Status::Completed => Color::from_rgb(0xFF, 0xD7, 0x00),
+        Status::Failed => Color::from_rgb(0xFF, 0x00, 0x00),
+        Status::Abandoned => Color::from_rgb(0x80, 0x80, 0x80),
+    };
+    let mut description = quest.description.clone().unwrap_or_default();
+    if !quest.objectives.is_empty() {
+        if !description.is_empty() {
+            description.push_str("\n\n");
+        }
+        for (i, objective) in quest.objectives.iter().enumerate() {
+            let check = if objective.done { "☑" } else { "☐" };
+            description.push_str(&format!("\n{check} {}. {}", i + 1, objective.text));
+        }
+    }
+
+    let mut embed = CreateEmbed::new()
+        .color(color)
+        .title(format!("#{} {}", quest.number, quest.title))
+        .description(truncate(description.trim_start(), DESCRIPTION_LIMIT))
+        .field("Status", quest.status.label(), true);
+    if let Some(giver) = &quest.giver {
+        embed = embed.field("Giver", truncate(giver, FIELD_LIMIT), true);
+    }
+    if let Some(reward) = &quest.reward {
+        embed = embed.field("Reward", truncate(reward, FIELD_LIMIT), true);
+    }
+    embed
+        .field("Added by", format!("<@{}>", quest.added_by), true)
+        .footer(CreateEmbedFooter::new(&campaign.name))
+        .timestamp(Timestamp::now())
+}
+
+fn status_subcommand(name: &str, description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::SubCommand, name, description)
+        .add_sub_option(quest_option())
+        .add_sub_option(campaign_option())
+}
+
+fn objective_subcommand(name: &str, description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::SubCommand, name, description)
+        .add_sub_option(quest_option())
+        .add_sub_option(
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "number",
+                "The objective's number in the checklist",
+            )
+            .min_int_value(1)
+            .required(true),
+        )
+        .add_sub_option(campaign_option())
+}
+
+fn status_option() -> CreateCommandOption {
+    let option = CreateCommandOption::new(
+        CommandOptionType::String,
+        "status",
+        "Only list quests with this status. Leave it out for active quests",
+    );
+    Status::ALL
+        .into_iter()
+        .fold(option, |option, status| {
+            option.add_string_choice(status.label(), status.as_str())
+        })
+        .add_string_choice("All", ANY_STATUS)
+}
+
+fn field_option() -> CreateCommandOption {
+    let option = CreateCommandOption::new(CommandOptionType::String, "field", "What to change")
+        .required(true);
+    Field::ALL.into_iter().fold(option, |option, field| {
+        option.add_string_choice(field.as_str(), field.as_str())
+    })
+}
+
+fn quest_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::Integer,
+        "quest",
+        "The quest's number in the log",
+    )
+    .min_int_value(1)
+    .required(true)
+}
+
+fn campaign_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        "campaign",
+        "The campaign. Leave it out to use this channel's",
+    )
+}
@@ -0,0 +1,293 @@
+//! Working out which campaign (and character) a command is about, for every
+//! command that acts on one. Users rarely have to name the campaign: channels
+//! can be bound to one with `/campaign set`, and failing that, Archmage asks.
+use std::time::Duration;
+
+use eyre::eyre;
+use serenity::all::{
+    ChannelId, ChannelType, Color, CommandDataOption, ComponentInteraction,
+    ComponentInteractionCollector, ComponentInteractionDataKind, CreateActionRow, CreateEmbed,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateSelectMenu,
+    CreateSelectMenuKind, CreateSelectMenuOption, GuildId, Timestamp,
+};
+use serenity::prelude::*;
+
+use crate::archmage::Archmage;
+use crate::model::campaign::Campaign;
+use crate::model::character::Character;
+
+use super::error::{CommandError, CommandResult};
+use super::invocation::{Invocation, Reply};
+use super::{options, AWAITED};
+
+/// How long to wait for someone to pick a campaign from the menu.
+const CHOICE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Discord allows at most this many options in a select menu.
+const MAX_CHOICES: usize = 25;
+
+/// The campaign a command is about. That's the one named in its `campaign`
+/// option if there is one, then the one bound to the channel it was used in,
+/// then the one bound to the thread's parent channel, and failing all of
+/// those, whichever the user picks from a menu.
+///
+/// Picking from the menu uses up the command's first response, so anything
+/// the handler sends afterwards is a follow-up.
+///
+/// Commands that are part of a feature the campaign has turned off are
+/// refused here; see [super::CommandDispatcher::require_feature]. The
+/// dispatcher has already refused them if the channel's campaign has, but
+/// the campaign may have been named or picked instead.
+pub async fn campaign(
+    archmage: &Archmage,
+    invocation: &Invocation,
+    ctx: &Context,
+    options: &[CommandDataOption],
+) -> CommandResult<Campaign> {
+    let campaign = locate(archmage, invocation, ctx, options).await?;
+    archmage
+        .commands()
+        .require_feature(archmage, invocation.name(), &campaign)
+        .await?;
+    Ok(campaign)
+}
+
+async fn locate(
+    archmage: &Archmage,
+    invocation: &Invocation,
+    ctx: &Context,
+    options: &[CommandDataOption],
+) -> CommandResult<Campaign> {
+    let Some(guild_id) = invocation.guild_id() else {
+        return Err(CommandError::user("Campaigns only exist in servers."));
+    };
+    if let Some(name) = options::string(options, "campaign") {
+        return named(archmage, guild_id, name).await;
+    }
+
+    if let Some(campaign) = bound(archmage, ctx, invocation.channel_id()).await? {
+        return Ok(campaign);
+    }
+    choose(archmage, invocation, ctx, guild_id).await
+}
+
+/// The campaign a channel is bound to, or failing that, the one its thread's
+/// parent channel is bound to, if either is.
+pub async fn bound(
+    archmage: &Archmage,
+    ctx: &Context,
+    channel: ChannelId,
+) -> CommandResult<Option<Campaign>> {
+    if let Some(campaign) = archmage.db().channel_campaign(&channel.get()).await? {
+        return Ok(Some(campaign));
+    }
+    match thread_parent(ctx, channel).await? {
+        Some(parent) => Ok(archmage.db().channel_campaign(&parent.get()).await?),
+        None => Ok(None),
+    }
+}
+
+/// A guild's campaign, by name.
+pub async fn named(archmage: &Archmage, guild_id: GuildId, name: &str) -> CommandResult<Campaign> {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    archmage
+        .db()
+        .campaign(&guild_id.get(), &name)
+        .await?
+        .ok_or(CommandError::not_found(format!(
+            "This server has no campaign called **{name}**."
+        )))
+}
+
+/// A campaign's character, by the ID players use.
+pub async fn character(
+    archmage: &Archmage,
+    campaign: &Campaign,
+    name: &str,
+) -> CommandResult<Character> {
+    let name = name.trim();
+    archmage
+        .db()
+        .character(&campaign.id, name)
+        .await?
+        .ok_or(CommandError::not_found(format!(
+            "**{}** has no character called `{name}`.",
+            campaign.name
+        )))
+}
+
+/// The channel a thread was started in, or None if the channel isn't a
+/// thread.
+async fn thread_parent(ctx: &Context, channel: ChannelId) -> CommandResult<Option<ChannelId>> {
+    let channel = channel.to_channel(ctx).await.map_err(|e| eyre!(e))?;
+    Ok(channel
+        .guild()
+        .filter(|c| {
+            matches!(
+                c.kind,
+                ChannelType::PublicThread | ChannelType::PrivateThread | ChannelType::NewsThread
+            )
+        })
+        .and_then(|c| c.parent_id))
+}
+
+/// Ask the user which campaign they meant, and wait for them to pick one.
+async fn choose(
+    archmage: &Archmage,
+    invocation: &Invocation,
+    ctx: &Context,
+    guild_id: GuildId,
+) -> CommandResult<Campaign> {
+    let mut campaigns = archmage.db().campaigns(&guild_id.get()).await?;
+    if campaigns.is_empty() {
+        return Err(CommandError::user(
+            "This server has no campaigns yet. A server administrator can start one with \
+             `/config dm`.",
+        ));
+    }
+
+    let mut description =
+        "This channel isn't bound to a campaign, so which one did you mean?".to_owned();
+    if campaigns.len() > MAX_CHOICES {
+        description.push_str(&format!(
+            " Only the first {MAX_CHOICES} are listed; name the campaign in the command to use \
+             another."
+        ));
+    }
+    let labels = campaigns.iter().map(|c| c.name.as_str()).collect();
+    let (index, choice) = pick(invocation, ctx, "campaign", description, labels).await?;
+    let campaign = campaigns.swap_remove(index);
+    confirm(
+        ctx,
+        &choice,
+        &campaign.name,
+        "To skip this question next time, a DM can bind this channel to a campaign with \
+         `/campaign set`.",
+    )
+    .await?;
+    Ok(campaign)
+}
+
+/// The character a user is acting as in a campaign: the one they play, or if
+/// they play several, whichever they pick from a menu. None if they don't
+/// play any, as DMs usually don't.
+pub async fn own_character(
+    archmage: &Archmage,
+    invocation: &Invocation,
+    ctx: &Context,
+    campaign: &Campaign,
+) -> CommandResult<Option<Character>> {
+    let mut characters = archmage
+        .db()
+        .characters_played(&campaign.id, &invocation.user().id.get())
+        .await?;
+    if characters.len() <= 1 {
+        return Ok(characters.pop());
+    }
+
+    let description = format!(
+        "You play several characters in **{}**, so which one is this for?",
+        campaign.name
+    );
+    let labels = characters.iter().map(|c| c.name.as_str()).collect();
+    let (index, choice) = pick(invocation, ctx, "character", description, labels).await?;
+    let character = characters.swap_remove(index);
+    confirm(
+        ctx,
+        &choice,
+        &character.name,
+        &format!("Acting as `{}`.", character.name),
+    )
+    .await?;
+    Ok(Some(character))
+}
+
+/// Ask the user to pick one of a list of things from a menu, and wait for
+/// them to. Returns the index of their choice, and the interaction to answer.
+async fn pick(
+    invocation: &Invocation,
+    ctx: &Context,
+    what: &str,
+    description: String,
+    labels: Vec<&str>,
+) -> CommandResult<(usize, ComponentInteraction)> {
+    let custom_id = format!("{AWAITED}{what}:{}", uuid::Uuid::new_v4().as_simple());
+    let menu = CreateSelectMenu::new(
+        &custom_id,
+        CreateSelectMenuKind::String {
+            options: labels
+                .iter()
+                .take(MAX_CHOICES)
+                .enumerate()
+                .map(|(i, label)| CreateSelectMenuOption::new(*label, i.to_string()))
+                .collect(),
+        },
+    )
+    .placeholder(format!("Choose a {what}"));
+    let question = Reply::new().ephemeral(true).embed(
+        CreateEmbed::new()
+            .color(Color::from_rgb(0xFF, 0xA5, 0x00))
+            .title(format!("Which {what}?"))
+            .description(description)
+            .timestamp(Timestamp::now()),
+    );
+    let sent = invocation
+        .send(
+            ctx,
+            question
+                .clone()
+                .components(vec![CreateActionRow::SelectMenu(menu)]),
+        )
+        .await?;
+
+    let Some(choice) = ComponentInteractionCollector::new(&ctx.shard)
+        .custom_ids(vec![custom_id])
+        .author_id(invocation.user().id)
+        .timeout(CHOICE_TIMEOUT)
+        .await
+    else {
+        // Nothing is waiting for the menu any more, so it's taken away
+        // rather than left to fail when used.
+        invocation.edit(ctx, sent, question).await?;
+        return Err(CommandError::user(format!(
+            "No {what} was chosen in time, so nothing was done."
+        )));
+    };
+    let ComponentInteractionDataKind::StringSelect { values } = &choice.data.kind else {
+        return Err(eyre!("Choice '{}' isn't a string select", choice.data.custom_id).into());
+    };
+    let Some(index) = values
+        .first()
+        .and_then(|i| i.parse::<usize>().ok())
+        .filter(|i| *i < labels.len())
+    else {
+        return Err(eyre!("Unknown {what} chosen: {values:?}").into());
+    };
+    Ok((index, choice))
+}
+
+/// Replace the menu with what was chosen.
+async fn confirm(
+    ctx: &Context,
+    choice: &ComponentInteraction,
+    title: &str,
+    description: &str,
+) -> CommandResult {
+    choice
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .embed(
+                        CreateEmbed::new()
+                            .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                            .title(title)
+                            .description(description)
+                            .timestamp(Timestamp::now()),
+                    )
+                    .components(vec![]),
+            ),
+        )
+        .await
+        .map_err(|e| eyre!(e))?;
+    Ok(())
+}
@@ -1,26 +1,36 @@
 //! A dice and arithmetic parsing and rolling utility.
-use eyre::{bail, eyre, Result};
+use eyre::eyre;
 use parser::DiceParser;
 use serenity::all::{
-    Color, CommandDataOptionValue, CommandInteraction, CommandOptionType, CreateCommand,
-    CreateCommandOption, CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage,
-    Timestamp,
+    Color, CommandDataOptionValue, CommandOptionType, CreateCommand, CreateCommandOption,
+    CreateEmbed, Timestamp,
 };
 use serenity::prelude::*;
 
 use crate::archmage::Archmage;
 
-use super::{handle_fn, ArchmageCommand, HandleFn};
+use super::error::{CommandError, CommandResult};
+use super::invocation::{Invocation, Reply};
+use super::{cooldown::Cooldown, handle_fn, ArchmageCommand, CommandRegistration};
 
 mod parser;
 
 pub struct RollCommand;
 
+/// Generous enough for a full round of attacks, but not for spamming.
+const ROLL_USER_COOLDOWN: Cooldown = Cooldown::per_user(5, 10);
+/// Keeps a whole table rolling at once under Discord's channel rate limit.
+const ROLL_CHANNEL_COOLDOWN: Cooldown = Cooldown::per_channel(20, 30);
+
+const ROLL_HELP: &str = "Dice are written `NdS`, rolling N dice with S sides each. A missing N means one die. \
+Dice and whole numbers can be combined with `+`, `-`, `*`, `/` and parentheses. \
+Every individual roll is shown alongside the total.";
+
 impl ArchmageCommand for RollCommand {
-    fn register() -> Vec<(String, CreateCommand, HandleFn)> {
+    fn register() -> Vec<CommandRegistration> {
         vec![
-            (
-                "roll".to_owned(),
+            CommandRegistration::new(
+                "roll",
                 CreateCommand::new("roll")
                     .description("Roll a die or calculate a value")
                     .add_option(
@@ -32,9 +42,15 @@ impl ArchmageCommand for RollCommand {
                         .required(true),
                     ),
                 handle_fn!(Self::run),
-            ),
-            (
-                "r".to_owned(),
+            )
+            .cooldown(ROLL_USER_COOLDOWN)
+            .cooldown(ROLL_CHANNEL_COOLDOWN)
+            .help(ROLL_HELP)
+            .example("/roll 1d20 + 5")
+            .example("/roll (2d6 + 3) * 2")
+            .example("/roll d100"),
+            CommandRegistration::new(
+                "r",
                 CreateCommand::new("r")
                     .description("Roll a die or calculate a value")
                     .add_option(
@@ -47,88 +63,54 @@ impl ArchmageCommand for RollCommand {
                     ),
                 handle_fn!(Self::run),
             )
+            .cooldown(ROLL_USER_COOLDOWN)
+            .cooldown(ROLL_CHANNEL_COOLDOWN)
+            .shares_cooldowns_with("roll")
+            .help(ROLL_HELP)
+            .example("/r 4d6"),
         ]
     }
 }
 
 impl RollCommand {
-    async fn run(_archmage: &Archmage, command: &CommandInteraction, ctx: &Context) -> Result<()> {
-        let option = &command
-            .data
-            .options
+    async fn run(_archmage: &Archmage, invocation: &Invocation, ctx: &Context) -> CommandResult {
+        let option = &invocation
+            .options()
             .first()
             .ok_or(eyre!("Expected dice or calculation expression"))?
             .value;
 
         match option {
-            CommandDataOptionValue::String(input) => roll_handler(ctx, command, input).await,
-            _ => bail!("Unexpected input type"),
+            CommandDataOptionValue::String(input) => roll_handler(ctx, invocation, input).await,
+            _ => Err(eyre!("Unexpected input type").into()),
         }
     }
 }
 
 // RollHandler is a recursive descent dice and calculation expression parser.
-async fn roll_handler(ctx: &Context, command: &CommandInteraction, input: &str) -> Result<()> {
-    let mut parser = match DiceParser::new(input) {
-        Ok(p) => p,
-        Err(e) => {
-            return command
-                .create_response(
-                    &ctx.http,
-                    CreateInteractionResponse::Message(
-                        CreateInteractionResponseMessage::new().embed(
-                            CreateEmbed::new()
-                                .color(Color::from_rgb(0xFF, 0x00, 0x00))
-                                .description(e.to_string())
-                                .title("An Error Occurred")
-                                .timestamp(Timestamp::now()),
-                        ),
-                    ),
-                )
-                .await
-                .map(|_| ())
-                .map_err(|e| eyre!(e))
-        }
-    };
+async fn roll_handler(ctx: &Context, invocation: &Invocation, input: &str) -> CommandResult {
+    let mut parser = DiceParser::new(input).map_err(|e| CommandError::user(e.to_string()))?;
     let expr = parser.expr();
 
     let (result, work) = expr.eval();
 
-    if !parser.errors().is_empty() {
-        command
-            .create_response(
-                &ctx.http,
-                CreateInteractionResponse::Message(
-                    CreateInteractionResponseMessage::new().embed(
-                        CreateEmbed::new()
-                            .color(Color::from_rgb(0xFF, 0x00, 0x00))
-                            .description(parser.errors()[0].clone())
-                            .title("An Error Occurred")
-                            .timestamp(Timestamp::now()),
-                    ),
-                ),
-            )
-            .await
-            .map(|_| ())
-            .map_err(|e| eyre!(e))
-    } else {
-        command
-            .create_response(
-                &ctx.http,
-                CreateInteractionResponse::Message(
-                    CreateInteractionResponseMessage::new().embed(
-                        CreateEmbed::new()
-                            .color(Color::from_rgb(0x00, 0xFF, 0x00))
-                            .description(input)
-                            .field("Rolls", work, false)
-                            .field("Result", result.to_string(), false)
-                            .title(format!("{} Rolled {}", command.user.name, result))
-                            .timestamp(Timestamp::now()),
-                    ),
-                ),
-            )
-            .await
-            .map(|_| ())
-            .map_err(|e| eyre!(e))
+    if let Some(error) = parser.errors().first() {
+        return Err(CommandError::user(error.clone()));
     }
+
+    invocation
+        .respond(
+            ctx,
+            Reply::new().embed(
+                CreateEmbed::new()
+                    .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                    .description(input)
+                    .field("Rolls", work, false)
+                    .field("Result", result.to_string(), false)
+                    .title(format!("{} Rolled {}", invocation.user().name, result))
+                    .timestamp(Timestamp::now()),
+            ),
+        )
+        .await?;
+    Ok(())
 }
@@ -0,0 +1,69 @@
+//! Reading command definitions back out of their serialized form. The fields
+//! of serenity's [CreateCommand] aren't public, but its serialized form is
+//! exactly what Discord sees, which is also exactly what we need to explain
+//! (`/help`) or parse (chat commands) a command.
+use serde_json::Value;
+use serenity::all::{CommandOptionType, CreateCommand};
+
+/// Serialize a definition so its fields can be read.
+pub fn definition_json(definition: &CreateCommand) -> Value {
+    serde_json::to_value(definition).unwrap_or(Value::Null)
+}
+
+/// A string field of a command or option, or the empty string.
+pub fn text<'a>(value: &'a Value, key: &str) -> &'a str {
+    value.get(key).and_then(Value::as_str).unwrap_or_default()
+}
+
+/// The options (or subcommands) nested directly under a command or option.
+pub fn options(value: &Value) -> &[Value] {
+    value
+        .get("options")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+pub fn kind(option: &Value) -> Option<CommandOptionType> {
+    option
+        .get("type")
+        .and_then(Value::as_u64)
+        .and_then(|k| u8::try_from(k).ok())
+        .map(CommandOptionType::from)
+}
+
+pub fn is_required(option: &Value) -> bool {
+    option.get("required").and_then(Value::as_bool).unwrap_or(false)
+}
+
+pub fn is_subcommand(option: &Value) -> bool {
+    matches!(
+        kind(option),
+        Some(CommandOptionType::SubCommand | CommandOptionType::SubCommandGroup)
+    )
+}
+
+/// The choices an option is limited to, if any.
+pub fn choices(option: &Value) -> &[Value] {
+    option
+        .get("choices")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+/// A user-friendly name for the kind of value an option takes.
+pub fn kind_name(kind: Option<CommandOptionType>) -> &'static str {
+    match kind {
+        Some(CommandOptionType::String) => "text",
+        Some(CommandOptionType::Integer) => "whole number",
+        Some(CommandOptionType::Number) => "number",
+        Some(CommandOptionType::Boolean) => "true or false",
+        Some(CommandOptionType::User) => "user",
+        Some(CommandOptionType::Channel) => "channel",
+        Some(CommandOptionType::Role) => "role",
+        Some(CommandOptionType::Mentionable) => "user or role",
+        Some(CommandOptionType::Attachment) => "file",
+        _ => "value",
+    }
+}
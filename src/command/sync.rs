@@ -0,0 +1,210 @@
+//! Comparison between the command definitions Archmage wants registered and
+//! the ones Discord already has, so that reconnecting doesn't re-push every
+//! definition when nothing has changed.
+use serde::Serialize;
+use serde_json::{Map, Value};
+use serenity::all::{Command, CreateCommand};
+use std::collections::BTreeMap;
+
+/// Top-level command fields that Archmage controls. Everything else (IDs,
+/// versions, the command type) is filled in by Discord.
+const COMMAND_FIELDS: &[&str] = &[
+    "name",
+    "name_localizations",
+    "description",
+    "description_localizations",
+    "options",
+    "default_member_permissions",
+    "nsfw",
+];
+
+/// Option fields that Archmage controls, at every level of nesting.
+const OPTION_FIELDS: &[&str] = &[
+    "type",
+    "name",
+    "name_localizations",
+    "description",
+    "description_localizations",
+    "required",
+    "choices",
+    "options",
+    "channel_types",
+    "min_value",
+    "max_value",
+    "min_length",
+    "max_length",
+    "autocomplete",
+];
+
+/// Returns true if `existing` contains exactly the commands in `desired`,
+/// with no differences that Discord would show to a user.
+pub fn definitions_match<'a>(
+    existing: &[Command],
+    desired: impl IntoIterator<Item = &'a CreateCommand>,
+) -> bool {
+    match (by_name(existing), by_name(desired)) {
+        (Some(existing), Some(desired)) => existing == desired,
+        // If either side can't be represented, assume they differ so the
+        // definitions get pushed again.
+        _ => false,
+    }
+}
+
+/// Normalize every command and key it by name.
+fn by_name<T: Serialize>(
+    commands: impl IntoIterator<Item = T>,
+) -> Option<BTreeMap<String, Value>> {
+    commands
+        .into_iter()
+        .map(|command| {
+            let command = normalize(serde_json::to_value(command).ok()?, COMMAND_FIELDS);
+            let name = command.get("name")?.as_str()?.to_owned();
+            Some((name, command))
+        })
+        .collect()
+}
+
+/// Keep only the given fields of an object, dropping any that are empty or
+/// at their default, since Discord and serenity disagree on whether those are
+/// `null`, `false`, `[]`, `{}` or simply missing.
+fn normalize(value: Value, fields: &[&str]) -> Value {
+    let Value::Object(object) = value else {
+        return value;
+    };
+
+    let mut normalized = Map::new();
+    for (key, value) in object {
+        if !fields.contains(&key.as_str()) || is_empty(&value) {
+            continue;
+        }
+        let value = match (key.as_str(), value) {
+            ("options", Value::Array(options)) => Value::Array(
+                options
+                    .into_iter()
+                    .map(|option| normalize(option, OPTION_FIELDS))
+                    .collect(),
+            ),
+            (_, value) => value,
+        };
+        let _ = normalized.insert(key, value);
+    }
+    Value::Object(normalized)
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null | Value::Bool(false) => true,
+        Value::Array(a) => a.is_empty(),
+        Value::Object(o) => o.is_empty(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use serenity::all::{CommandOptionType, CreateCommandOption};
+
+    use super::*;
+
+    fn roll() -> CreateCommand {
+        CreateCommand::new("roll")
+            .description("Roll a die")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::String, "expression", "The dice")
+                    .required(true),
+            )
+    }
+
+    fn ping() -> CreateCommand {
+        CreateCommand::new("ping").description("Check that the bot is alive")
+    }
+
+    /// A command as Discord would send it back, with everything it fills in.
+    fn registered(id: u64, command: Value) -> Command {
+        let Value::Object(mut command) = command else {
+            panic!("commands are objects");
+        };
+        for (key, value) in [
+            ("id", json!(id.to_string())),
+            ("application_id", json!("1")),
+            ("version", json!("1")),
+            ("type", json!(1)),
+            ("dm_permission", json!(true)),
+        ] {
+            let _ = command.insert(key.to_owned(), value);
+        }
+        serde_json::from_value(Value::Object(command)).unwrap()
+    }
+
+    fn as_registered(id: u64, command: &CreateCommand) -> Command {
+        registered(id, serde_json::to_value(command).unwrap())
+    }
+
+    #[test]
+    fn identical() {
+        let existing = [as_registered(2, &roll()), as_registered(3, &ping())];
+        assert!(definitions_match(&existing, &[roll(), ping()]));
+        assert!(definitions_match(&[], &[]));
+    }
+
+    #[test]
+    fn order_and_defaults_dont_matter() {
+        let existing = [
+            registered(
+                3,
+                json!({
+                    "nsfw": false,
+                    "description": "Check that the bot is alive",
+                    "name": "ping",
+                    "default_member_permissions": null,
+                    "options": [],
+                }),
+            ),
+            registered(
+                2,
+                json!({
+                    "options": [{
+                        "required": true,
+                        "description": "The dice",
+                        "name": "expression",
+                        "type": 3,
+                        "autocomplete": false,
+                        "name_localizations": null,
+                    }],
+                    "description": "Roll a die",
+                    "name": "roll",
+                    "name_localizations": {},
+                }),
+            ),
+        ];
+        assert!(definitions_match(&existing, &[roll(), ping()]));
+    }
+
+    #[test]
+    fn changed_description() {
+        let existing = [as_registered(2, &roll())];
+        assert!(!definitions_match(
+            &existing,
+            &[roll().description("Roll some dice")]
+        ));
+    }
+
+    #[test]
+    fn added_option() {
+        let existing = [as_registered(2, &roll())];
+        let roll = roll().add_option(CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            "secret",
+            "Only show the result to you",
+        ));
+        assert!(!definitions_match(&existing, &[roll]));
+    }
+
+    #[test]
+    fn added_or_removed_command() {
+        let existing = [as_registered(2, &roll())];
+        assert!(!definitions_match(&existing, &[roll(), ping()]));
+        assert!(!definitions_match(&existing, &[]));
+    }
+}
@@ -0,0 +1,48 @@
+//! Fitting text into Discord's limits.
+use serde_json::Value;
+use serenity::all::CreateEmbed;
+
+/// Discord rejects embed fields longer than this.
+pub const FIELD_LIMIT: usize = 1024;
+/// Discord rejects embed descriptions longer than this.
+pub const DESCRIPTION_LIMIT: usize = 4096;
+/// Discord rejects embeds with more fields than this.
+pub const FIELDS_PER_EMBED: usize = 25;
+/// Discord rejects messages with more embeds than this.
+pub const EMBEDS_PER_MESSAGE: usize = 10;
+/// Discord rejects messages whose embeds add up to more characters than this.
+pub const MESSAGE_EMBED_LIMIT: usize = 6000;
+
+/// Cut text down to at most `limit` characters, marking that it was cut.
+pub fn truncate(body: &str, limit: usize) -> String {
+    if body.chars().count() <= limit {
+        return body.to_owned();
+    }
+    let mut cut: String = body.chars().take(limit - 1).collect();
+    cut.push('…');
+    cut
+}
+
+/// How many characters of an embed count towards [MESSAGE_EMBED_LIMIT]: its
+/// title, description, footer, author, and fields' names and values.
+pub fn embed_length(embed: &CreateEmbed) -> usize {
+    let Ok(embed) = serde_json::to_value(embed) else {
+        return 0;
+    };
+    let length = |value: Option<&Value>| {
+        value
+            .and_then(Value::as_str)
+            .map_or(0, |s| s.chars().count())
+    };
+    let fields: usize = embed["fields"].as_array().map_or(0, |fields| {
+        fields
+            .iter()
+            .map(|f| length(f.get("name")) + length(f.get("value")))
+            .sum()
+    });
+    length(embed.get("title"))
+        + length(embed.get("description"))
+        + length(embed.pointer("/footer/text"))
+        + length(embed.pointer("/author/name"))
+        + fields
+}
@@ -0,0 +1,295 @@
+//! Experience points. Anyone may look at a character's XP, but only the
+//! character's players and their campaign's DMs may change it, and only DMs
+//! may change what it's multiplied by. Levels follow from XP using the
+//! campaign's table, which DMs change with `/campaign level`.
+use eyre::eyre;
+use serenity::all::{
+    Color, CommandDataOption, CommandOptionType, CreateCommand, CreateCommandOption, CreateEmbed,
+    CreateEmbedFooter, Timestamp,
+};
+use serenity::prelude::*;
+
+use crate::archmage::Archmage;
+use crate::model::campaign::Campaign;
+use crate::model::character::Character;
+use crate::model::feature;
+use crate::model::monitor::Kind;
+use crate::model::xp::{self, check_multiplier, XpChange};
+
+use super::access::{require_dm, require_player};
+use super::error::{CommandError, CommandResult};
+use super::invocation::{Invocation, Reply};
+use super::monitor;
+use super::options::{self, required_integer, required_number, required_string};
+use super::{handle_fn, resolve, ArchmageCommand, CommandRegistration};
+
+pub struct XpCommand;
+
+impl ArchmageCommand for XpCommand {
+    fn register() -> Vec<CommandRegistration> {
+        vec![CommandRegistration::new(
+            "xp",
+            CreateCommand::new("xp")
+                .description("Keep track of characters' XP and levels")
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "show",
+                        "Show a character's XP and level",
+                    )
+                    .add_sub_option(character_option())
+                    .add_sub_option(campaign_option()),
+                )
+                .add_option(change_subcommand(
+                    "add",
+                    "Give a character XP, multiplied by their XP multiplier",
+                ))
+                .add_option(change_subcommand("remove", "Take XP away from a character"))
+                .add_option(change_subcommand(
+                    "set",
+                    "Set exactly how much XP a character has",
+                ))
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "mod",
+                        "Set what XP added to a character is multiplied by",
+                    )
+                    .add_sub_option(character_option())
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Number,
+                            "multiplier",
+                            "The multiplier, e.g. 1.02 for +2%. Use 1 for none",
+                        )
+                        .required(true),
+                    )
+                    .add_sub_option(campaign_option()),
+                ),
+            handle_fn!(Self::run),
+        )
+        .leading("campaign")
+        .leading("character")
+        .default_subcommand("show")
+        .feature(feature::CHARACTERS)
+        .help(
+            "Anyone may look at a character's XP, but only the character's players and their \
+             campaign's DMs may change it. Only DMs may set a character's multiplier with \
+             `mod`.\n\n\
+             XP added with `add` is multiplied by the character's multiplier; XP removed or \
+             set isn't. Levels follow from the campaign's XP table (see `/campaign level`), \
+             and are kept up to date in the character's info post.",
+        )
+        .example("/xp show Cal")
+        .example("/xp add Cal 100")
+        .example("/xp mod Cal 1.02")]
+    }
+}
+
+impl XpCommand {
+    async fn run(archmage: &Archmage, invocation: &Invocation, ctx: &Context) -> CommandResult {
+        let Some((subcommand, options)) = options::subcommand(invocation.options()) else {
+            return Err(eyre!("/xp used without a subcommand").into());
+        };
+
+        let campaign = resolve::campaign(archmage, invocation, ctx, options).await?;
+        let name = required_string(options, "character")?;
+        let character = resolve::character(archmage, &campaign, name).await?;
+        if subcommand == "show" {
+            return Self::show(archmage, invocation, ctx, &campaign, &character).await;
+        }
+
+        let permissions = invocation.member_permissions(ctx).await?;
+        match subcommand {
+            // Multipliers are house rules, so they're up to the DMs.
+            "mod" => {
+                require_dm(archmage, &campaign, invocation.user().id, permissions).await?;
+                Self::set_multiplier(archmage, invocation, ctx, &character, options).await
+            }
+            "add" | "remove" | "set" => {
+                require_player(
+                    archmage,
+                    &campaign,
+                    &character,
+                    invocation.user().id,
+                    permissions,
+                )
+                .await?;
+                Self::change(
+                    archmage, invocation, ctx, subcommand, &campaign, &character, options,
+                )
+                .await
+            }
+            other => Err(eyre!("Unknown /xp subcommand {other}").into()),
+        }
+    }
+
+    async fn show(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        campaign: &Campaign,
+        character: &Character,
+    ) -> CommandResult {
+        let Some(experience) = archmage.db().experience(&character.id).await? else {
+            return Err(CommandError::not_found(format!(
+                "`{}` has since been removed.",
+                character.name
+            )));
+        };
+        let levels = archmage.db().levels(&campaign.id).await?;
+
+        let mut description = format!(
+            "`{}` (Level {}) has {} XP",
+            character.name,
+            xp::level(&levels, experience.xp),
+            experience.xp
+        );
+        if let Some(next) = xp::next(&levels, experience.xp) {
+            description.push_str(&format!("\nLevel {} at {} XP", next.level, next.xp));
+        }
+        if experience.multiplier != 1.0 {
+            description.push_str(&format!("\n\nXP Multiplier: {}", experience.multiplier));
+        }
+
+        invocation
+            .respond(
+                ctx,
+                Reply::new().embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .title(&character.name)
+                        .description(description)
+                        .footer(CreateEmbedFooter::new(&campaign.name))
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn change(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        subcommand: &str,
+        campaign: &Campaign,
+        character: &Character,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let amount = required_integer(options, "amount")?;
+        if amount < 0 {
+            return Err(CommandError::user(
+                "Amounts can't be negative. Use `remove` to take XP away.",
+            ));
+        }
+        let change = match subcommand {
+            "add" => XpChange::Add(amount),
+            "remove" => XpChange::Remove(amount),
+            _ => XpChange::Set(amount),
+        };
+        let (before, after) = archmage
+            .db()
+            .change_xp(&character.id, change, &invocation.user().id.get())
+            .await?
+            .map_err(CommandError::user)?;
+        monitor::changed(archmage, ctx, campaign.id, Kind::Characters);
+
+        let description = match change {
+            XpChange::Add(_) => format!("{} XP added to `{}`.", after - before, character.name),
+            XpChange::Remove(_) => format!("{amount} XP removed from `{}`.", character.name),
+            XpChange::Set(_) => format!("`{}` now has {after} XP.", character.name),
+        };
+        let mut reply = Reply::new().embed(
+            CreateEmbed::new()
+                .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                .title(&character.name)
+                .description(description)
+                .timestamp(Timestamp::now()),
+        );
+
+        let levels = archmage.db().levels(&campaign.id).await?;
+        let (was, now) = (xp::level(&levels, before), xp::level(&levels, after));
+        if was != now {
+            let announcement = match now > was {
+                true => format!("`{}` reached level {now}!", character.name),
+                false => format!("`{}` dropped to level {now}.", character.name),
+            };
+            reply = reply.embed(
+                CreateEmbed::new()
+                    .color(Color::from_rgb(0xFF, 0xD7, 0x00))
+                    .title(match now > was {
+                        true => "Level up!",
+                        false => "Level down",
+                    })
+                    .description(announcement)
+                    .timestamp(Timestamp::now()),
+            );
+        }
+
+        invocation.respond(ctx, reply).await?;
+        Ok(())
+    }
+
+    async fn set_multiplier(
+        archmage: &Archmage,
+        invocation: &Invocation,
+        ctx: &Context,
+        character: &Character,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let multiplier = check_multiplier(required_number(options, "multiplier")?)
+            .map_err(CommandError::user)?;
+        archmage
+            .db()
+            .set_xp_multiplier(&character.id, multiplier, &invocation.user().id.get())
+            .await?;
+
+        invocation
+            .respond(
+                ctx,
+                Reply::new().embed(
+                    CreateEmbed::new()
+                        .color(Color::from_rgb(0x00, 0xFF, 0x00))
+                        .title(&character.name)
+                        .description(format!(
+                            "Set XP multiplier for `{}` to {multiplier}.",
+                            character.name
+                        ))
+                        .timestamp(Timestamp::now()),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+fn change_subcommand(name: &str, description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::SubCommand, name, description)
+        .add_sub_option(character_option())
+        .add_sub_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "amount", "How much XP")
+                .min_int_value(0)
+                .required(true),
+        )
+        .add_sub_option(campaign_option())
+}
+
+/// Chat commands give the character before the subcommand, as in
+/// `!xp Cal add 100`.
+fn character_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        "character",
+        "The character's short ID",
+    )
+    .required(true)
+}
+
+fn campaign_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        "campaign",
+        "The campaign. Leave it out to use this channel's",
+    )
+}
@@ -0,0 +1,469 @@
+//! Archmage's configuration, and where it comes from.
+//!
+//! Settings are layered, with later layers taking precedence:
+//!
+//! 1. Defaults, for everything that has one.
+//! 2. The configuration file, `secret/config.toml` unless `--config` says
+//!    otherwise.
+//! 3. Environment variables, for the settings that differ between
+//!    deployments: `ARCHMAGE_TOKEN`, `ARCHMAGE_APPID`,
+//!    `ARCHMAGE_ALLOWED_GUILDS` (comma-separated) and `DATABASE_URL`.
+//!    Variables can also be given in a `.env` file in the working directory,
+//!    though real environment variables take precedence over it.
+//!
+//! Sending Archmage `SIGHUP` reloads the configuration without restarting,
+//! though the token, application ID and database can't be changed that way.
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use eyre::{bail, eyre, Result};
+use serde::Deserialize;
+use serenity::all::GatewayIntents;
+use toml::value::{Table, Value};
+use tracing::{event, Level};
+
+use crate::command::cooldown::Cooldown;
+
+/// Where the configuration file is looked for if no other path is given.
+const DEFAULT_CONFIG_PATH: &str = "secret/config.toml";
+/// Discord gives up on interactions that aren't answered within this long.
+const INTERACTION_DEADLINE_MS: u64 = 3000;
+
+const USAGE: &str = "Usage: archmage [--config <path>] [--check-config]
+
+Options:
+  -c, --config <path>  Read configuration from <path> instead of secret/config.toml
+      --check-config   Check the configuration for problems, then exit
+  -h, --help           Show this message, then exit";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    /// Guilds Archmage may work in. Owners can allow more at runtime with
+    /// `/admin guilds allow`.
+    #[serde(default, alias = "allowed-guilds")]
+    pub allowed_guilds: Vec<u64>,
+
+    /// What to do when added to a guild that hasn't been allowed.
+    #[serde(default, alias = "unlisted-guilds")]
+    pub unlisted_guilds: UnlistedGuilds,
+
+    #[serde(alias = "app-id")]
+    pub appid: u64,
+
+    #[serde(alias = "secret")]
+    pub token: String,
+
+    /// Users trusted with owner-only commands such as `/admin`, in addition
+    /// to whoever owns the application in the Discord developer portal.
+    #[serde(default)]
+    pub owners: Vec<u64>,
+
+    /// How long a command may run before its response is deferred, in
+    /// milliseconds. Discord requires some response within three seconds.
+    #[serde(default = "default_defer_after_ms", alias = "defer-after-ms")]
+    pub defer_after_ms: u64,
+
+    /// Where to keep Archmage's database.
+    #[serde(default = "default_database")]
+    pub database: PathBuf,
+
+    /// Per-command cooldown overrides, keyed by command name. A command
+    /// listed here uses exactly these cooldowns instead of its defaults,
+    /// so an empty list disables rate limiting for it entirely.
+    #[serde(default)]
+    pub cooldowns: HashMap<String, Vec<Cooldown>>,
+
+    /// Register commands once for the whole application instead of separately
+    /// in every allowed guild. Global commands can take a while to show up in
+    /// Discord, so per-guild registration is the default.
+    #[serde(default, alias = "global-commands")]
+    pub global_commands: bool,
+
+    /// The gateway intents to request, by name (e.g. `"GUILD_MESSAGES"`),
+    /// instead of those the registered commands ask for. Any features that
+    /// need intents left out here won't work.
+    #[serde(default)]
+    pub intents: Option<Vec<String>>,
+
+    /// Remove commands left behind by the registration mode not in use, e.g.
+    /// per-guild commands after switching to global commands. Without this,
+    /// users would see every command twice after switching.
+    #[serde(default, alias = "purge-stale-commands")]
+    pub purge_stale_commands: bool,
+}
+
+/// What Archmage does in a guild that hasn't been allowed.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnlistedGuilds {
+    /// Leave the guild straight away.
+    #[default]
+    Leave,
+    /// Stay in the guild, but ignore commands until an owner allows it.
+    Pending,
+}
+
+fn default_defer_after_ms() -> u64 {
+    1500
+}
+
+fn default_database() -> PathBuf {
+    PathBuf::from("data/archmage.db")
+}
+
+/// What Archmage was asked to do on the command line.
+#[derive(Debug, Default)]
+pub struct Cli {
+    /// The configuration file to use, if not the default.
+    pub config: Option<PathBuf>,
+    /// Only check the configuration, rather than starting the bot.
+    pub check_config: bool,
+    /// Only show usage information.
+    pub help: bool,
+}
+
+impl Cli {
+    /// Parse the arguments Archmage was started with, not including the
+    /// program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut cli = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" | "--config" => {
+                    let path = args
+                        .next()
+                        .ok_or(eyre!("{arg} needs a path to a configuration file\n\n{USAGE}"))?;
+                    cli.config = Some(PathBuf::from(path));
+                }
+                "--check-config" => cli.check_config = true,
+                "-h" | "--help" => cli.help = true,
+                other => match other.strip_prefix("--config=") {
+                    Some(path) => cli.config = Some(PathBuf::from(path)),
+                    None => bail!("Unknown argument '{other}'\n\n{USAGE}"),
+                },
+            }
+        }
+        Ok(cli)
+    }
+
+    /// Usage information for the command line.
+    pub fn usage() -> &'static str {
+        USAGE
+    }
+}
+
+impl Config {
+    /// The gateway intents set in the configuration, if any. Unknown names
+    /// are rejected by validation, so are ignored here.
+    pub fn intents(&self) -> Option<GatewayIntents> {
+        self.intents.as_ref().map(|names| {
+            names
+                .iter()
+                .filter_map(|name| GatewayIntents::from_name(&name.to_uppercase()))
+                .fold(GatewayIntents::empty(), |all, intent| all | intent)
+        })
+    }
+
+    /// Load the configuration from every layer, and check it for problems.
+    ///
+    /// The configuration file may only be left out if it wasn't asked for by
+    /// name and the environment provides every required setting.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let vars = Variables::load()?;
+        let explicit = path.is_some();
+        let path = path.unwrap_or(Path::new(DEFAULT_CONFIG_PATH));
+
+        let mut source = path.display().to_string();
+        let table = match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str::<Table>(&contents)
+                .map_err(|e| eyre!("{source} is not valid TOML: {e}"))?,
+            Err(e) if e.kind() == ErrorKind::NotFound && !explicit => {
+                source = format!("the environment (there is no {source})");
+                Table::new()
+            }
+            Err(e) => bail!("Unable to read {source}: {e}"),
+        };
+        Self::build(table, &vars, &source)
+    }
+
+    /// Layer the environment over the configuration file's settings, and
+    /// check the result. `source` says where the settings came from.
+    fn build(mut table: Table, vars: &Variables, source: &str) -> Result<Self> {
+        vars.apply(&mut table)?;
+        let config: Config = Value::Table(table)
+            .try_into()
+            .map_err(|e| eyre!("Invalid configuration in {source}: {e}"))?;
+        config.validate(source)?;
+        Ok(config)
+    }
+
+    /// Check for settings that are well-formed but can't work. Every problem
+    /// is reported at once, so they can all be fixed in one go.
+    fn validate(&self, source: &str) -> Result<()> {
+        let mut problems = Vec::new();
+
+        if self.token.trim().is_empty() {
+            problems.push("`token` is empty".to_owned());
+        } else if self.token.contains(char::is_whitespace) {
+            problems.push("`token` contains whitespace".to_owned());
+        }
+        if self.appid == 0 {
+            problems.push("`appid` must be the application's ID, not 0".to_owned());
+        }
+        if self.defer_after_ms >= INTERACTION_DEADLINE_MS {
+            problems.push(format!(
+                "`defer_after_ms` must be under {INTERACTION_DEADLINE_MS}, or Discord will give up on slow commands first"
+            ));
+        }
+        for (command, cooldowns) in &self.cooldowns {
+            if cooldowns.iter().any(|c| c.uses == 0 || c.seconds == 0) {
+                problems.push(format!(
+                    "cooldowns for `{command}` need at least one use and one second"
+                ));
+            }
+        }
+
+        for name in self.intents.iter().flatten() {
+            if GatewayIntents::from_name(&name.to_uppercase()).is_none() {
+                problems.push(format!("`{name}` in `intents` isn't a gateway intent"));
+            }
+        }
+
+        if self.allowed_guilds.is_empty() && self.unlisted_guilds == UnlistedGuilds::Leave {
+            event!(
+                Level::WARN,
+                "No allowed guilds are configured, so Archmage will leave every server it hasn't been allowed into at runtime"
+            );
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        bail!(
+            "Invalid configuration in {source}:\n  - {}",
+            problems.join("\n  - ")
+        )
+    }
+}
+
+/// Environment variables, including any read from `.env`.
+struct Variables {
+    env: HashMap<String, String>,
+    dotenv: HashMap<String, String>,
+}
+
+impl Variables {
+    fn load() -> Result<Self> {
+        let dotenv = match fs::read_to_string(".env") {
+            Ok(contents) => parse_dotenv(&contents),
+            Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(e) => bail!("Unable to read .env: {e}"),
+        };
+        Ok(Self {
+            env: env::vars().collect(),
+            dotenv,
+        })
+    }
+
+    fn get(&self, name: &str) -> Option<String> {
+        self.env
+            .get(name)
+            .or_else(|| self.dotenv.get(name))
+            .cloned()
+            .filter(|value| !value.trim().is_empty())
+    }
+
+    /// Override settings in the configuration file with any that are set in
+    /// the environment.
+    fn apply(&self, table: &mut Table) -> Result<()> {
+        if let Some(token) = self.get("ARCHMAGE_TOKEN") {
+            set(table, "token", &["secret"], Value::String(token.trim().to_owned()));
+        }
+        if let Some(appid) = self.get("ARCHMAGE_APPID") {
+            set(table, "appid", &["app-id"], Value::Integer(snowflake("ARCHMAGE_APPID", &appid)?));
+        }
+        if let Some(guilds) = self.get("ARCHMAGE_ALLOWED_GUILDS") {
+            let guilds = guilds
+                .split(',')
+                .map(str::trim)
+                .filter(|g| !g.is_empty())
+                .map(|g| snowflake("ARCHMAGE_ALLOWED_GUILDS", g).map(Value::Integer))
+                .collect::<Result<_>>()?;
+            set(table, "allowed_guilds", &["allowed-guilds"], Value::Array(guilds));
+        }
+        if let Some(url) = self.get("DATABASE_URL") {
+            let path = url.strip_prefix("sqlite:").unwrap_or(&url).to_owned();
+            set(table, "database", &[], Value::String(path));
+        }
+        Ok(())
+    }
+}
+
+/// Set a key, removing any of its aliases so they can't conflict with it.
+fn set(table: &mut Table, key: &str, aliases: &[&str], value: Value) {
+    for alias in aliases {
+        let _ = table.remove(*alias);
+    }
+    let _ = table.insert(key.to_owned(), value);
+}
+
+/// Parse a Discord ID from an environment variable.
+fn snowflake(variable: &str, value: &str) -> Result<i64> {
+    value
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .filter(|id| *id > 0)
+        .ok_or(eyre!("{variable} must be a Discord ID, not '{value}'"))
+}
+
+/// Read `KEY=value` lines, ignoring blank lines and `#` comments. Values may
+/// be wrapped in single or double quotes.
+fn parse_dotenv(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = ['"', '\'']
+                .iter()
+                .find_map(|q| value.strip_prefix(*q)?.strip_suffix(*q))
+                .unwrap_or(value);
+            Some((key.trim().to_owned(), value.to_owned()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(env: &[(&str, &str)], dotenv: &str) -> Variables {
+        Variables {
+            env: env
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            dotenv: parse_dotenv(dotenv),
+        }
+    }
+
+    fn file(contents: &str) -> Table {
+        toml::from_str(contents).unwrap()
+    }
+
+    fn args(args: &[&str]) -> Result<Cli> {
+        Cli::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn parses_the_command_line() {
+        let cli = args(&[]).unwrap();
+        assert_eq!(cli.config, None);
+        assert!(!cli.check_config && !cli.help);
+
+        let cli = args(&["--check-config", "-c", "dev.toml"]).unwrap();
+        assert_eq!(cli.config, Some(PathBuf::from("dev.toml")));
+        assert!(cli.check_config);
+        let cli = args(&["--config=prod.toml", "--config", "dev.toml", "-h"]).unwrap();
+        assert_eq!(cli.config, Some(PathBuf::from("dev.toml")));
+        assert!(cli.help);
+
+        assert!(args(&["--config"]).is_err());
+        assert!(args(&["--verbose"]).is_err());
+    }
+
+    #[test]
+    fn parses_dotenv() {
+        let parsed = parse_dotenv(
+            "# Archmage\n\
+             ARCHMAGE_TOKEN = \"abc def\"\n\
+             export ARCHMAGE_APPID='123'\n\
+             \n\
+             DATABASE_URL=sqlite:data/a=b.db\n\
+             not a variable\n",
+        );
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed["ARCHMAGE_TOKEN"], "abc def");
+        assert_eq!(parsed["ARCHMAGE_APPID"], "123");
+        assert_eq!(parsed["DATABASE_URL"], "sqlite:data/a=b.db");
+    }
+
+    #[test]
+    fn environment_alone_is_enough() {
+        let vars = vars(&[("ARCHMAGE_TOKEN", "token"), ("ARCHMAGE_APPID", "42")], "");
+        let config = Config::build(Table::new(), &vars, "the environment").unwrap();
+        assert_eq!((config.token.as_str(), config.appid), ("token", 42));
+        assert!(config.allowed_guilds.is_empty());
+        assert_eq!(config.defer_after_ms, default_defer_after_ms());
+        assert_eq!(config.database, default_database());
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let table = file(
+            "secret = \"file\"\n\
+             app-id = 1\n\
+             allowed-guilds = [1, 2]\n\
+             database = \"file.db\"\n\
+             defer_after_ms = 100\n",
+        );
+
+        let config = Config::build(table.clone(), &vars(&[], ""), "file").unwrap();
+        assert_eq!((config.token.as_str(), config.appid), ("file", 1));
+        assert_eq!(config.allowed_guilds, [1, 2]);
+
+        // Real variables beat .env, which beats the file. Blank variables
+        // leave the setting alone.
+        let vars = vars(
+            &[
+                ("ARCHMAGE_TOKEN", "env"),
+                ("ARCHMAGE_ALLOWED_GUILDS", " 3, 4 ,"),
+                ("DATABASE_URL", " "),
+            ],
+            "ARCHMAGE_TOKEN=dotenv\nARCHMAGE_APPID=2\nDATABASE_URL=sqlite:dotenv.db",
+        );
+        let config = Config::build(table, &vars, "file").unwrap();
+        assert_eq!((config.token.as_str(), config.appid), ("env", 2));
+        assert_eq!(config.allowed_guilds, [3, 4]);
+        assert_eq!(config.database, PathBuf::from("file.db"));
+        assert_eq!(config.defer_after_ms, 100);
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let table = file(
+            "token = \"a b\"\n\
+             appid = 0\n\
+             defer_after_ms = 5000\n\
+             intents = [\"guild_messages\", \"GUILD_SPELLS\"]\n\
+             [[cooldowns.roll]]\n\
+             scope = \"user\"\n\
+             uses = 0\n\
+             seconds = 10\n",
+        );
+        let error = Config::build(table, &vars(&[], ""), "test.toml")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            "Invalid configuration in test.toml:\n  \
+             - `token` contains whitespace\n  \
+             - `appid` must be the application's ID, not 0\n  \
+             - `defer_after_ms` must be under 3000, or Discord will give up on slow commands first\n  \
+             - cooldowns for `roll` need at least one use and one second\n  \
+             - `GUILD_SPELLS` in `intents` isn't a gateway intent"
+        );
+
+        let vars = vars(&[("ARCHMAGE_APPID", "-5")], "");
+        assert!(Config::build(file("token = \"t\""), &vars, "test.toml").is_err());
+    }
+}
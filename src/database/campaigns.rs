@@ -0,0 +1,329 @@
+use eyre::Result;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::model::campaign::Campaign;
+
+fn campaign(row: &Row) -> rusqlite::Result<Campaign> {
+    Ok(Campaign {
+        id: row.get(0)?,
+        guild_id: row.get::<_, i64>(1)? as u64,
+        name: row.get(2)?,
+    })
+}
+
+fn find(conn: &Connection, guild_id: i64, name: &str) -> rusqlite::Result<Option<Campaign>> {
+    conn.query_row(
+        "SELECT id, guild_id, name FROM campaigns WHERE guild_id = ?1 AND name = ?2",
+        params![guild_id, name],
+        campaign,
+    )
+    .optional()
+}
+
+impl super::MageDB {
+    /// Start a new campaign. Returns None if the guild already has one by that
+    /// name.
+    pub async fn create_campaign(&self, guild_id: &u64, name: &str) -> Result<Option<Campaign>> {
+        let guild_id = *guild_id as i64;
+        let name = name.to_owned();
+        self.with(move |conn| {
+            let inserted = conn.execute(
+                "INSERT OR IGNORE INTO campaigns (guild_id, name, created_at) VALUES (?1, ?2, ?3)",
+                params![guild_id, name, chrono::Utc::now().timestamp()],
+            )?;
+            match inserted {
+                0 => Ok(None),
+                _ => find(conn, guild_id, &name),
+            }
+        })
+        .await
+    }
+
+    /// Find a campaign by name, starting it if the guild doesn't have one by
+    /// that name yet. Returns the campaign, and whether it was just started.
+    pub async fn ensure_campaign(&self, guild_id: &u64, name: &str) -> Result<(Campaign, bool)> {
+        let guild_id = *guild_id as i64;
+        let name = name.to_owned();
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let created = transaction.execute(
+                "INSERT OR IGNORE INTO campaigns (guild_id, name, created_at) VALUES (?1, ?2, ?3)",
+                params![guild_id, name, chrono::Utc::now().timestamp()],
+            )? > 0;
+            let campaign = transaction.query_row(
+                "SELECT id, guild_id, name FROM campaigns WHERE guild_id = ?1 AND name = ?2",
+                params![guild_id, name],
+                campaign,
+            )?;
+            transaction.commit()?;
+            Ok((campaign, created))
+        })
+        .await
+    }
+
+    /// A guild's campaign, by name. Names are matched ignoring case.
+    pub async fn campaign(&self, guild_id: &u64, name: &str) -> Result<Option<Campaign>> {
+        let guild_id = *guild_id as i64;
+        let name = name.to_owned();
+        self.with(move |conn| find(conn, guild_id, &name)).await
+    }
+
+    /// A campaign, by ID.
+    pub async fn campaign_by_id(&self, id: &i64) -> Result<Option<Campaign>> {
+        let id = *id;
+        self.with(move |conn| {
+            conn.query_row(
+                "SELECT id, guild_id, name FROM campaigns WHERE id = ?1",
+                params![id],
+                campaign,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    /// Every campaign in a guild, by name.
+    pub async fn campaigns(&self, guild_id: &u64) -> Result<Vec<Campaign>> {
+        let guild_id = *guild_id as i64;
+        self.with(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT id, guild_id, name FROM campaigns WHERE guild_id = ?1 ORDER BY name",
+            )?;
+            let campaigns = statement.query_map(params![guild_id], campaign)?.collect();
+            campaigns
+        })
+        .await
+    }
+
+    /// Rename a campaign. Returns false if another campaign in the same guild
+    /// already has the name.
+    pub async fn rename_campaign(&self, id: &i64, name: &str) -> Result<bool> {
+        let id = *id;
+        let name = name.to_owned();
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let taken = transaction
+                .query_row(
+                    "SELECT 1 FROM campaigns
+                     WHERE guild_id = (SELECT guild_id FROM campaigns WHERE id = ?1)
+                       AND name = ?2 AND id != ?1",
+                    params![id, name],
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some();
+            if taken {
+                return Ok(false);
+            }
+            let _ = transaction.execute(
+                "UPDATE campaigns SET name = ?2 WHERE id = ?1",
+                params![id, name],
+            )?;
+            transaction.commit()?;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Delete a campaign and everything belonging to it. Returns false if it
+    /// didn't exist.
+    pub async fn delete_campaign(&self, id: &i64) -> Result<bool> {
+        let id = *id;
+        self.with(move |conn| {
+            conn.execute("DELETE FROM campaigns WHERE id = ?1", params![id])
+                .map(|deleted| deleted > 0)
+        })
+        .await
+    }
+
+    /// Make a user one of a campaign's DMs. Returns false if they already were.
+    pub async fn add_dm(&self, campaign_id: &i64, user_id: &u64) -> Result<bool> {
+        let (campaign_id, user_id) = (*campaign_id, *user_id as i64);
+        self.with(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO campaign_dms (campaign_id, user_id, added_at)
+                 VALUES (?1, ?2, ?3)",
+                params![campaign_id, user_id, chrono::Utc::now().timestamp()],
+            )
+            .map(|inserted| inserted > 0)
+        })
+        .await
+    }
+
+    /// Stop a user being one of a campaign's DMs. Returns false if they weren't
+    /// one. The campaign is kept even if it no longer has any DMs.
+    pub async fn remove_dm(&self, campaign_id: &i64, user_id: &u64) -> Result<bool> {
+        let (campaign_id, user_id) = (*campaign_id, *user_id as i64);
+        self.with(move |conn| {
+            conn.execute(
+                "DELETE FROM campaign_dms WHERE campaign_id = ?1 AND user_id = ?2",
+                params![campaign_id, user_id],
+            )
+            .map(|deleted| deleted > 0)
+        })
+        .await
+    }
+
+    /// A campaign's DMs, in the order they were added.
+    pub async fn dms(&self, campaign_id: &i64) -> Result<Vec<u64>> {
+        let campaign_id = *campaign_id;
+        self.with(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT user_id FROM campaign_dms WHERE campaign_id = ?1 ORDER BY added_at, user_id",
+            )?;
+            let dms = statement
+                .query_map(params![campaign_id], |row| row.get::<_, i64>(0))?
+                .map(|id| id.map(|id| id as u64))
+                .collect();
+            dms
+        })
+        .await
+    }
+
+    /// Whether a user is one of a campaign's DMs.
+    pub async fn is_dm(&self, campaign_id: &i64, user_id: &u64) -> Result<bool> {
+        let (campaign_id, user_id) = (*campaign_id, *user_id as i64);
+        self.with(move |conn| {
+            conn.query_row(
+                "SELECT 1 FROM campaign_dms WHERE campaign_id = ?1 AND user_id = ?2",
+                params![campaign_id, user_id],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|row| row.is_some())
+        })
+        .await
+    }
+
+    /// The campaign a channel is bound to, if any.
+    pub async fn channel_campaign(&self, channel_id: &u64) -> Result<Option<Campaign>> {
+        let channel_id = *channel_id as i64;
+        self.with(move |conn| {
+            conn.query_row(
+                "SELECT c.id, c.guild_id, c.name FROM campaign_channels b
+                 JOIN campaigns c ON c.id = b.campaign_id
+                 WHERE b.channel_id = ?1",
+                params![channel_id],
+                campaign,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    /// Bind a channel to a campaign. Returns false if the channel is already
+    /// bound to a campaign, whether this one or another.
+    pub async fn bind_channel(
+        &self,
+        channel_id: &u64,
+        campaign_id: &i64,
+        added_by: &u64,
+    ) -> Result<bool> {
+        let (channel_id, campaign_id, added_by) =
+            (*channel_id as i64, *campaign_id, *added_by as i64);
+        self.with(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO campaign_channels (channel_id, campaign_id, added_by, added_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![channel_id, campaign_id, added_by, chrono::Utc::now().timestamp()],
+            )
+            .map(|inserted| inserted > 0)
+        })
+        .await
+    }
+
+    /// Unbind a channel from a campaign. Returns false if it wasn't bound to
+    /// that campaign.
+    pub async fn unbind_channel(&self, channel_id: &u64, campaign_id: &i64) -> Result<bool> {
+        let (channel_id, campaign_id) = (*channel_id as i64, *campaign_id);
+        self.with(move |conn| {
+            conn.execute(
+                "DELETE FROM campaign_channels WHERE channel_id = ?1 AND campaign_id = ?2",
+                params![channel_id, campaign_id],
+            )
+            .map(|deleted| deleted > 0)
+        })
+        .await
+    }
+
+    /// The channels bound to a campaign, in the order they were bound.
+    pub async fn campaign_channels(&self, campaign_id: &i64) -> Result<Vec<u64>> {
+        let campaign_id = *campaign_id;
+        self.with(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT channel_id FROM campaign_channels WHERE campaign_id = ?1
+                 ORDER BY added_at, channel_id",
+            )?;
+            let channels = statement
+                .query_map(params![campaign_id], |row| row.get::<_, i64>(0))?
+                .map(|id| id.map(|id| id as u64))
+                .collect();
+            channels
+        })
+        .await
+    }
+
+    /// Whether a feature is on in a campaign. Features are on unless a DM has
+    /// turned them off.
+    pub async fn is_feature_enabled(&self, campaign_id: &i64, feature: &str) -> Result<bool> {
+        let (campaign_id, feature) = (*campaign_id, feature.to_owned());
+        self.with(move |conn| {
+            conn.query_row(
+                "SELECT 1 FROM disabled_features WHERE campaign_id = ?1 AND feature = ?2",
+                params![campaign_id, feature],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|row| row.is_none())
+        })
+        .await
+    }
+
+    /// Turn a feature on or off in a campaign. Returns false if it already
+    /// was.
+    pub async fn set_feature_enabled(
+        &self,
+        campaign_id: &i64,
+        feature: &str,
+        enabled: bool,
+        changed_by: &u64,
+    ) -> Result<bool> {
+        let (campaign_id, feature, changed_by) =
+            (*campaign_id, feature.to_owned(), *changed_by as i64);
+        self.with(move |conn| {
+            let changed = match enabled {
+                true => conn.execute(
+                    "DELETE FROM disabled_features WHERE campaign_id = ?1 AND feature = ?2",
+                    params![campaign_id, feature],
+                )?,
+                false => conn.execute(
+                    "INSERT OR IGNORE INTO disabled_features
+                     (campaign_id, feature, disabled_by, disabled_at) VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        campaign_id,
+                        feature,
+                        changed_by,
+                        chrono::Utc::now().timestamp()
+                    ],
+                )?,
+            };
+            Ok(changed > 0)
+        })
+        .await
+    }
+
+    /// The features turned off in a campaign, by name.
+    pub async fn disabled_features(&self, campaign_id: &i64) -> Result<Vec<String>> {
+        let campaign_id = *campaign_id;
+        self.with(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT feature FROM disabled_features WHERE campaign_id = ?1 ORDER BY feature",
+            )?;
+            let features = statement
+                .query_map(params![campaign_id], |row| row.get(0))?
+                .collect();
+            features
+        })
+        .await
+    }
+}
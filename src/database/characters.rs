@@ -0,0 +1,180 @@
+use eyre::Result;
+use rusqlite::{params, OptionalExtension, Row};
+
+use crate::model::character::Character;
+
+use super::xp::sync_levels;
+
+fn character(row: &Row) -> rusqlite::Result<Character> {
+    Ok(Character {
+        id: row.get(0)?,
+        campaign_id: row.get(1)?,
+        name: row.get(2)?,
+    })
+}
+
+impl super::MageDB {
+    /// Find a character by ID, adding them to the campaign if they aren't in
+    /// it yet. Returns the character, and whether they were just added.
+    pub async fn ensure_character(
+        &self,
+        campaign_id: &i64,
+        name: &str,
+    ) -> Result<(Character, bool)> {
+        let campaign_id = *campaign_id;
+        let name = name.to_owned();
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let created = transaction.execute(
+                "INSERT OR IGNORE INTO characters (campaign_id, name, created_at) VALUES (?1, ?2, ?3)",
+                params![campaign_id, name, chrono::Utc::now().timestamp()],
+            )? > 0;
+            let character = transaction.query_row(
+                "SELECT id, campaign_id, name FROM characters WHERE campaign_id = ?1 AND name = ?2",
+                params![campaign_id, name],
+                character,
+            )?;
+            // New characters' posts start out with their level, which isn't
+            // a change anyone made, so it isn't kept in the history.
+            if created {
+                let _ = sync_levels(&transaction, campaign_id, Some(character.id))?;
+            }
+            transaction.commit()?;
+            Ok((character, created))
+        })
+        .await
+    }
+
+    /// A campaign's character, by ID. IDs are matched ignoring case.
+    pub async fn character(&self, campaign_id: &i64, name: &str) -> Result<Option<Character>> {
+        let campaign_id = *campaign_id;
+        let name = name.to_owned();
+        self.with(move |conn| {
+            conn.query_row(
+                "SELECT id, campaign_id, name FROM characters WHERE campaign_id = ?1 AND name = ?2",
+                params![campaign_id, name],
+                character,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    /// A character, by their row ID rather than the ID players use.
+    pub async fn character_by_id(&self, id: &i64) -> Result<Option<Character>> {
+        let id = *id;
+        self.with(move |conn| {
+            conn.query_row(
+                "SELECT id, campaign_id, name FROM characters WHERE id = ?1",
+                params![id],
+                character,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    /// Delete a character and everything belonging to them. Returns false if
+    /// they didn't exist.
+    pub async fn delete_character(&self, id: &i64) -> Result<bool> {
+        let id = *id;
+        self.with(move |conn| {
+            conn.execute("DELETE FROM characters WHERE id = ?1", params![id])
+                .map(|deleted| deleted > 0)
+        })
+        .await
+    }
+
+    /// Let a user play a character. Returns false if they already could.
+    pub async fn add_player(&self, character_id: &i64, user_id: &u64) -> Result<bool> {
+        let (character_id, user_id) = (*character_id, *user_id as i64);
+        self.with(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO character_players (character_id, user_id, added_at)
+                 VALUES (?1, ?2, ?3)",
+                params![character_id, user_id, chrono::Utc::now().timestamp()],
+            )
+            .map(|inserted| inserted > 0)
+        })
+        .await
+    }
+
+    /// Stop a user playing a character. Returns false if they didn't. The
+    /// character is kept even if nobody plays them any more.
+    pub async fn remove_player(&self, character_id: &i64, user_id: &u64) -> Result<bool> {
+        let (character_id, user_id) = (*character_id, *user_id as i64);
+        self.with(move |conn| {
+            conn.execute(
+                "DELETE FROM character_players WHERE character_id = ?1 AND user_id = ?2",
+                params![character_id, user_id],
+            )
+            .map(|deleted| deleted > 0)
+        })
+        .await
+    }
+
+    /// Who plays a character, in the order they were added.
+    pub async fn players(&self, character_id: &i64) -> Result<Vec<u64>> {
+        let character_id = *character_id;
+        self.with(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT user_id FROM character_players WHERE character_id = ?1
+                 ORDER BY added_at, user_id",
+            )?;
+            let players = statement
+                .query_map(params![character_id], |row| row.get::<_, i64>(0))?
+                .map(|id| id.map(|id| id as u64))
+                .collect();
+            players
+        })
+        .await
+    }
+
+    /// Every character in a campaign, by ID.
+    pub async fn campaign_characters(&self, campaign_id: &i64) -> Result<Vec<Character>> {
+        let campaign_id = *campaign_id;
+        self.with(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT id, campaign_id, name FROM characters WHERE campaign_id = ?1 ORDER BY name",
+            )?;
+            let characters = statement
+                .query_map(params![campaign_id], character)?
+                .collect();
+            characters
+        })
+        .await
+    }
+
+    /// The characters a user plays in a campaign, by ID.
+    pub async fn characters_played(&self, campaign_id: &i64, user_id: &u64) -> Result<Vec<Character>> {
+        let (campaign_id, user_id) = (*campaign_id, *user_id as i64);
+        self.with(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT c.id, c.campaign_id, c.name FROM characters c
+                 JOIN character_players p ON p.character_id = c.id
+                 WHERE c.campaign_id = ?1 AND p.user_id = ?2
+                 ORDER BY c.name",
+            )?;
+            let characters = statement
+                .query_map(params![campaign_id, user_id], character)?
+                .collect();
+            characters
+        })
+        .await
+    }
+
+    /// Whether a user plays a character.
+    pub async fn is_player(&self, character_id: &i64, user_id: &u64) -> Result<bool> {
+        let (character_id, user_id) = (*character_id, *user_id as i64);
+        self.with(move |conn| {
+            conn.query_row(
+                "SELECT 1 FROM character_players WHERE character_id = ?1 AND user_id = ?2",
+                params![character_id, user_id],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|row| row.is_some())
+        })
+        .await
+    }
+}
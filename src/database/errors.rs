@@ -0,0 +1,65 @@
+use eyre::Result;
+use rusqlite::{params, OptionalExtension};
+
+/// A terminal error, as reported to the user by its ID, kept so that it can
+/// be looked up long after the logs have been rotated away.
+#[derive(Debug, Clone)]
+pub struct ErrorRecord {
+    /// The error code the user was given.
+    pub id: String,
+    /// What was being done at the time, serialized as JSON.
+    pub context: String,
+    /// The full report, including every cause in its chain.
+    pub report: String,
+    pub guild_id: Option<u64>,
+    pub user_id: Option<u64>,
+    /// When the error happened, in seconds since the Unix epoch.
+    pub created_at: i64,
+}
+
+impl super::MageDB {
+    /// Keep a record of an error.
+    pub async fn log_error(&self, record: &ErrorRecord) -> Result<()> {
+        let record = record.clone();
+        self.with(move |conn| {
+            conn.execute(
+                "INSERT INTO errors (id, context, report, guild_id, user_id, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    record.id,
+                    record.context,
+                    record.report,
+                    record.guild_id.map(|id| id as i64),
+                    record.user_id.map(|id| id as i64),
+                    record.created_at,
+                ],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// Look up an error by the code the user was given.
+    pub async fn get_error(&self, id: &str) -> Result<Option<ErrorRecord>> {
+        let id = id.to_owned();
+        self.with(move |conn| {
+            conn.query_row(
+                "SELECT id, context, report, guild_id, user_id, created_at
+                 FROM errors WHERE id = ?1",
+                params![id],
+                |row| {
+                    Ok(ErrorRecord {
+                        id: row.get(0)?,
+                        context: row.get(1)?,
+                        report: row.get(2)?,
+                        guild_id: row.get::<_, Option<i64>>(3)?.map(|id| id as u64),
+                        user_id: row.get::<_, Option<i64>>(4)?.map(|id| id as u64),
+                        created_at: row.get(5)?,
+                    })
+                },
+            )
+            .optional()
+        })
+        .await
+    }
+}
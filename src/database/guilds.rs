@@ -0,0 +1,124 @@
+use eyre::Result;
+use rusqlite::{params, OptionalExtension};
+
+impl super::MageDB {
+    /// The chat command prefix chosen for a guild, if it has chosen one.
+    pub async fn get_prefix(&self, guild_id: &u64) -> Result<Option<String>> {
+        let guild_id = *guild_id as i64;
+        self.with(move |conn| {
+            conn.query_row(
+                "SELECT prefix FROM guild_settings WHERE guild_id = ?1",
+                params![guild_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map(Option::flatten)
+        })
+        .await
+    }
+
+    /// Set a guild's chat command prefix. None restores the default.
+    pub async fn set_prefix(&self, guild_id: &u64, prefix: Option<&str>) -> Result<()> {
+        let guild_id = *guild_id as i64;
+        let prefix = prefix.map(str::to_owned);
+        self.with(move |conn| {
+            conn.execute(
+                "INSERT INTO guild_settings (guild_id, prefix) VALUES (?1, ?2)
+                 ON CONFLICT (guild_id) DO UPDATE SET prefix = excluded.prefix",
+                params![guild_id, prefix],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// Whether an owner has allowed Archmage into a guild. Guilds allowed by
+    /// the configuration file aren't recorded here.
+    pub async fn is_guild_allowed(&self, guild_id: &u64) -> Result<bool> {
+        let guild_id = *guild_id as i64;
+        self.with(move |conn| {
+            conn.query_row(
+                "SELECT 1 FROM allowed_guilds WHERE guild_id = ?1",
+                params![guild_id],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|row| row.is_some())
+        })
+        .await
+    }
+
+    /// Every guild an owner has allowed Archmage into.
+    pub async fn allowed_guilds(&self) -> Result<Vec<u64>> {
+        self.with(|conn| {
+            let mut statement =
+                conn.prepare("SELECT guild_id FROM allowed_guilds ORDER BY added_at")?;
+            let guilds = statement
+                .query_map([], |row| row.get::<_, i64>(0))?
+                .map(|id| id.map(|id| id as u64))
+                .collect();
+            guilds
+        })
+        .await
+    }
+
+    /// Allow Archmage into a guild, which is then no longer pending. Returns
+    /// false if it was already allowed.
+    pub async fn allow_guild(&self, guild_id: &u64, added_by: &u64) -> Result<bool> {
+        let (guild_id, added_by) = (*guild_id as i64, *added_by as i64);
+        self.with(move |conn| {
+            let _ = conn.execute(
+                "DELETE FROM pending_guilds WHERE guild_id = ?1",
+                params![guild_id],
+            )?;
+            conn.execute(
+                "INSERT OR IGNORE INTO allowed_guilds (guild_id, added_by, added_at)
+                 VALUES (?1, ?2, ?3)",
+                params![guild_id, added_by, chrono::Utc::now().timestamp()],
+            )
+            .map(|inserted| inserted > 0)
+        })
+        .await
+    }
+
+    /// Stop allowing Archmage into a guild. Returns false if it wasn't allowed.
+    pub async fn disallow_guild(&self, guild_id: &u64) -> Result<bool> {
+        let guild_id = *guild_id as i64;
+        self.with(move |conn| {
+            conn.execute(
+                "DELETE FROM allowed_guilds WHERE guild_id = ?1",
+                params![guild_id],
+            )
+            .map(|deleted| deleted > 0)
+        })
+        .await
+    }
+
+    /// Note that Archmage is in a guild it hasn't been allowed into yet.
+    pub async fn mark_guild_pending(&self, guild_id: &u64, name: &str) -> Result<()> {
+        let guild_id = *guild_id as i64;
+        let name = name.to_owned();
+        self.with(move |conn| {
+            conn.execute(
+                "INSERT INTO pending_guilds (guild_id, name, requested_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (guild_id) DO UPDATE SET name = excluded.name",
+                params![guild_id, name, chrono::Utc::now().timestamp()],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// Every guild awaiting approval, with its name as of when it was added.
+    pub async fn pending_guilds(&self) -> Result<Vec<(u64, String)>> {
+        self.with(|conn| {
+            let mut statement = conn
+                .prepare("SELECT guild_id, name FROM pending_guilds ORDER BY requested_at")?;
+            let guilds = statement
+                .query_map([], |row| Ok((row.get::<_, i64>(0)? as u64, row.get(1)?)))?
+                .collect();
+            guilds
+        })
+        .await
+    }
+}
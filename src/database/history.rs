@@ -0,0 +1,620 @@
+use eyre::{eyre, Result};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+
+use crate::model::history::{Edit, Event, Target, DONE, OPEN};
+use crate::model::inventory::{Change, Contributor, Owner};
+use crate::model::quest::Field;
+use crate::model::xp::{check_threshold, Threshold};
+
+use super::info::set_tag;
+use super::inventory::change_item;
+use super::xp::{sync_levels, thresholds};
+
+/// How a target is stored: its kind, the ID of what it belongs to, and the
+/// name and number that tell it apart from others there.
+fn columns(target: &Target) -> (&'static str, i64, Option<&str>, Option<i64>) {
+    match target {
+        Target::Item {
+            owner: Owner::Character(id),
+            item,
+        } => ("inventory", *id, Some(item), None),
+        Target::Item {
+            owner: Owner::Pot(id),
+            item,
+        } => ("pot", *id, Some(item), None),
+        Target::Xp { character_id } => ("xp", *character_id, None, None),
+        Target::Multiplier { character_id } => ("multiplier", *character_id, None, None),
+        Target::Level { campaign_id, level } => ("level", *campaign_id, None, Some(*level)),
+        Target::Info { character_id, tag } => ("info", *character_id, Some(tag), None),
+        Target::Quest { quest_id, number } => ("quest", *quest_id, None, Some(*number)),
+        Target::QuestField { quest_id, field } => {
+            ("quest field", *quest_id, Some(field.as_str()), None)
+        }
+        Target::Status { quest_id } => ("status", *quest_id, None, None),
+        Target::Objective {
+            quest_id,
+            objective_id,
+            text,
+        } => ("objective", *quest_id, Some(text), Some(*objective_id)),
+    }
+}
+
+/// The reverse of [columns]. None if the kind isn't known.
+fn target(kind: &str, id: i64, name: Option<String>, detail: Option<i64>) -> Option<Target> {
+    Some(match (kind, name, detail) {
+        ("inventory", Some(item), _) => Target::Item {
+            owner: Owner::Character(id),
+            item,
+        },
+        ("pot", Some(item), _) => Target::Item {
+            owner: Owner::Pot(id),
+            item,
+        },
+        ("xp", _, _) => Target::Xp { character_id: id },
+        ("multiplier", _, _) => Target::Multiplier { character_id: id },
+        ("level", _, Some(level)) => Target::Level {
+            campaign_id: id,
+            level,
+        },
+        ("info", Some(tag), _) => Target::Info {
+            character_id: id,
+            tag,
+        },
+        ("quest", _, Some(number)) => Target::Quest {
+            quest_id: id,
+            number,
+        },
+        ("quest field", Some(field), _) => Target::QuestField {
+            quest_id: id,
+            field: Field::parse(&field)?,
+        },
+        ("status", _, _) => Target::Status { quest_id: id },
+        ("objective", Some(text), Some(objective_id)) => Target::Objective {
+            quest_id: id,
+            objective_id,
+            text,
+        },
+        _ => return None,
+    })
+}
+
+/// The campaign a character is in, and their name, for edits about them.
+pub(super) fn character_subject(
+    conn: &Connection,
+    character_id: i64,
+) -> rusqlite::Result<(i64, String)> {
+    conn.query_row(
+        "SELECT campaign_id, name FROM characters WHERE id = ?1",
+        params![character_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+}
+
+/// The campaign an inventory is in, and whose it is, for edits to it.
+pub(super) fn owner_subject(conn: &Connection, owner: &Owner) -> rusqlite::Result<(i64, String)> {
+    match *owner {
+        Owner::Character(id) => character_subject(conn, id),
+        Owner::Pot(campaign_id) => Ok((campaign_id, "Party Pot".to_owned())),
+    }
+}
+
+/// The campaign a quest is in, and what it's called, for edits to it.
+pub(super) fn quest_subject(conn: &Connection, quest_id: i64) -> rusqlite::Result<(i64, String)> {
+    conn.query_row(
+        "SELECT campaign_id, number FROM quests WHERE id = ?1",
+        params![quest_id],
+        |row| Ok((row.get(0)?, format!("Quest #{}", row.get::<_, i64>(1)?))),
+    )
+}
+
+/// Record what a command changed as an event in a campaign's history, as
+/// part of the transaction that changed it. Edits that left things as they
+/// were are left out, and if that's all of them, nothing is recorded.
+pub(super) fn record(
+    transaction: &Transaction,
+    campaign_id: i64,
+    user_id: u64,
+    edits: Vec<Edit>,
+) -> rusqlite::Result<()> {
+    let _ = insert(transaction, campaign_id, user_id, None, edits)?;
+    Ok(())
+}
+
+/// Add an event to a campaign's history. Returns its ID, or None if none of
+/// the edits changed anything.
+fn insert(
+    transaction: &Transaction,
+    campaign_id: i64,
+    user_id: u64,
+    undoes: Option<i64>,
+    edits: Vec<Edit>,
+) -> rusqlite::Result<Option<i64>> {
+    let edits: Vec<Edit> = edits.into_iter().filter(|e| e.before != e.after).collect();
+    if edits.is_empty() {
+        return Ok(None);
+    }
+
+    let id = transaction.query_row(
+        "INSERT INTO history (campaign_id, user_id, undoes, created_at) VALUES (?1, ?2, ?3, ?4)
+         RETURNING id",
+        params![
+            campaign_id,
+            user_id as i64,
+            undoes,
+            chrono::Utc::now().timestamp()
+        ],
+        |row| row.get(0),
+    )?;
+    for (position, edit) in edits.into_iter().enumerate() {
+        let (kind, target, name, detail) = columns(&edit.target);
+        let _ = transaction.execute(
+            "INSERT INTO history_edits
+                 (event_id, position, subject, kind, target, name, detail, before, after)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                id,
+                position as i64,
+                edit.subject,
+                kind,
+                target,
+                name,
+                detail,
+                edit.before,
+                edit.after
+            ],
+        )?;
+    }
+    Ok(Some(id))
+}
+
+/// An event in a campaign's history, with its edits.
+fn find(conn: &Connection, campaign_id: i64, id: i64) -> rusqlite::Result<Option<Event>> {
+    let event = conn
+        .query_row(
+            "SELECT id, user_id, created_at, undoes, undone_by FROM history
+             WHERE campaign_id = ?1 AND id = ?2",
+            params![campaign_id, id],
+            |row| {
+                Ok(Event {
+                    id: row.get(0)?,
+                    user_id: row.get::<_, i64>(1)? as u64,
+                    created_at: row.get(2)?,
+                    undoes: row.get(3)?,
+                    undone_by: row.get(4)?,
+                    edits: Vec::new(),
+                })
+            },
+        )
+        .optional()?;
+    let Some(mut event) = event else {
+        return Ok(None);
+    };
+
+    let mut statement = conn.prepare_cached(
+        "SELECT subject, kind, target, name, detail, before, after FROM history_edits
+         WHERE event_id = ?1 ORDER BY position",
+    )?;
+    let rows = statement.query_map(params![event.id], |row| {
+        let (subject, kind, before, after): (String, String, _, _) =
+            (row.get(0)?, row.get(1)?, row.get(5)?, row.get(6)?);
+        let target = target(&kind, row.get(2)?, row.get(3)?, row.get(4)?);
+        Ok(target.map(|target| Edit {
+            subject,
+            target,
+            before,
+            after,
+        }))
+    })?;
+    for edit in rows {
+        event.edits.extend(edit?);
+    }
+    Ok(Some(event))
+}
+
+/// The state of a target now, as edits record it. None if what it belongs
+/// to, such as its character or quest, no longer exists.
+fn state(conn: &Connection, target: &Target) -> rusqlite::Result<Option<Option<String>>> {
+    let character_exists = |id: i64| {
+        conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM characters WHERE id = ?1)",
+            params![id],
+            |row| row.get::<_, bool>(0),
+        )
+    };
+    let quest_exists = |id: i64| {
+        conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM quests WHERE id = ?1)",
+            params![id],
+            |row| row.get::<_, bool>(0),
+        )
+    };
+
+    Ok(match target {
+        Target::Item { owner, item } => {
+            let (table, column, id) = match *owner {
+                Owner::Character(id) if !character_exists(id)? => return Ok(None),
+                Owner::Character(id) => ("inventory_items", "character_id", id),
+                Owner::Pot(id) => ("pot_items", "campaign_id", id),
+            };
+            let quantity = conn
+                .query_row(
+                    &format!("SELECT quantity FROM {table} WHERE {column} = ?1 AND name = ?2"),
+                    params![id, item],
+                    |row| row.get::<_, i64>(0),
+                )
+                .optional()?;
+            Some(Some(quantity.unwrap_or(0).to_string()))
+        }
+        Target::Xp { character_id } => conn
+            .query_row(
+                "SELECT xp FROM characters WHERE id = ?1",
+                params![character_id],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?
+            .map(|xp| Some(xp.to_string())),
+        Target::Multiplier { character_id } => conn
+            .query_row(
+                "SELECT xp_multiplier FROM characters WHERE id = ?1",
+                params![character_id],
+                |row| row.get::<_, f64>(0),
+            )
+            .optional()?
+            .map(|multiplier| Some(multiplier.to_string())),
+        Target::Level { campaign_id, level } => Some(
+            conn.query_row(
+                "SELECT xp FROM campaign_levels WHERE campaign_id = ?1 AND level = ?2",
+                params![campaign_id, level],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?
+            .map(|xp| xp.to_string()),
+        ),
+        Target::Info { character_id, tag } => match character_exists(*character_id)? {
+            false => None,
+            true => Some(
+                conn.query_row(
+                    "SELECT value FROM character_info WHERE character_id = ?1 AND tag = ?2",
+                    params![character_id, tag],
+                    |row| row.get(0),
+                )
+                .optional()?,
+            ),
+        },
+        Target::Quest { quest_id, .. } => Some(
+            conn.query_row(
+                "SELECT title FROM quests WHERE id = ?1",
+                params![quest_id],
+                |row| row.get(0),
+            )
+            .optional()?,
+        ),
+        Target::QuestField { quest_id, field } => conn
+            .query_row(
+                &format!("SELECT {} FROM quests WHERE id = ?1", field.as_str()),
+                params![quest_id],
+                |row| row.get(0),
+            )
+            .optional()?,
+        Target::Status { quest_id } => conn
+            .query_row(
+                "SELECT status FROM quests WHERE id = ?1",
+                params![quest_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .map(Some),
+        Target::Objective {
+            quest_id,
+            objective_id,
+            text,
+        } => match quest_exists(*quest_id)? {
+            false => None,
+            true => Some(
+                conn.query_row(
+                    "SELECT done FROM quest_objectives
+                     WHERE id = ?1 AND quest_id = ?2 AND text = ?3",
+                    params![objective_id, quest_id, text],
+                    |row| row.get::<_, bool>(0),
+                )
+                .optional()?
+                .map(|done| if done { DONE } else { OPEN }.to_owned()),
+            ),
+        },
+    })
+}
+
+/// Why a target can't be put back the way it was, beyond it having changed.
+fn blocked(conn: &Connection, campaign_id: i64, edit: &Edit) -> rusqlite::Result<Option<String>> {
+    if let Target::Level { level, .. } = edit.target {
+        // The levels around it may have changed since, so that it would now
+        // be out of order.
+        let xp = edit.before.as_deref().and_then(|xp| xp.parse().ok());
+        return Ok(match xp {
+            Some(xp) => {
+                check_threshold(&thresholds(conn, campaign_id)?, Threshold { level, xp }).err()
+            }
+            None => None,
+        });
+    }
+    let Target::Quest { quest_id, number } = edit.target else {
+        return Ok(None);
+    };
+    match edit.before {
+        // Taking a quest away would lose whatever's been added to it since.
+        None => {
+            let bare: bool = conn.query_row(
+                "SELECT description IS NULL AND giver IS NULL AND reward IS NULL
+                        AND status = 'active'
+                        AND NOT EXISTS (
+                            SELECT 1 FROM quest_objectives o WHERE o.quest_id = quests.id)
+                 FROM quests WHERE id = ?1",
+                params![quest_id],
+                |row| row.get(0),
+            )?;
+            Ok((!bare).then(|| {
+                format!(
+                    "{} has changed since it was added. Undo those changes first.",
+                    edit.subject
+                )
+            }))
+        }
+        Some(_) => {
+            let taken: bool = conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM quests WHERE campaign_id = ?1 AND number = ?2)",
+                params![campaign_id, number],
+                |row| row.get(0),
+            )?;
+            Ok(taken.then(|| format!("Another quest has since been numbered {number}.")))
+        }
+    }
+}
+
+/// Put a target back to a state it was in.
+fn restore(
+    transaction: &Transaction,
+    campaign_id: i64,
+    user_id: u64,
+    target: &Target,
+    value: Option<&str>,
+) -> rusqlite::Result<()> {
+    match target {
+        Target::Item { owner, item } => {
+            let quantity = value.and_then(|v| v.parse().ok()).unwrap_or(0);
+            let contributor = Contributor {
+                user_id,
+                character: None,
+            };
+            // The quantity was there before, so it can't be refused.
+            let _ = change_item(
+                transaction,
+                owner,
+                item.clone(),
+                Change::Set(quantity),
+                &contributor,
+            )?;
+        }
+        Target::Xp { character_id } => {
+            let xp = value.and_then(|v| v.parse::<i64>().ok()).unwrap_or(0);
+            let _ = transaction.execute(
+                "UPDATE characters SET xp = ?2 WHERE id = ?1",
+                params![character_id, xp],
+            )?;
+        }
+        Target::Multiplier { character_id } => {
+            let multiplier = value.and_then(|v| v.parse::<f64>().ok()).unwrap_or(1.0);
+            let _ = transaction.execute(
+                "UPDATE characters SET xp_multiplier = ?2 WHERE id = ?1",
+                params![character_id, multiplier],
+            )?;
+        }
+        Target::Level { level, .. } => {
+            let _ = match value.and_then(|v| v.parse::<i64>().ok()) {
+                None => transaction.execute(
+                    "DELETE FROM campaign_levels WHERE campaign_id = ?1 AND level = ?2",
+                    params![campaign_id, level],
+                )?,
+                Some(xp) => transaction.execute(
+                    "INSERT INTO campaign_levels (campaign_id, level, xp) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (campaign_id, level) DO UPDATE SET xp = excluded.xp",
+                    params![campaign_id, level, xp],
+                )?,
+            };
+        }
+        Target::Info { character_id, tag } => {
+            let _ = set_tag(transaction, *character_id, tag, value)?;
+        }
+        Target::Quest { quest_id, number } => {
+            let _ = match value {
+                None => {
+                    transaction.execute("DELETE FROM quests WHERE id = ?1", params![quest_id])?
+                }
+                Some(title) => transaction.execute(
+                    "INSERT INTO quests (id, campaign_id, number, title, added_by, created_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        quest_id,
+                        campaign_id,
+                        number,
+                        title,
+                        user_id as i64,
+                        chrono::Utc::now().timestamp()
+                    ],
+                )?,
+            };
+        }
+        Target::QuestField { quest_id, field } => {
+            let _ = transaction.execute(
+                &format!("UPDATE quests SET {} = ?2 WHERE id = ?1", field.as_str()),
+                params![quest_id, value],
+            )?;
+        }
+        Target::Status { quest_id } => {
+            let _ = transaction.execute(
+                "UPDATE quests SET status = ?2 WHERE id = ?1",
+                params![quest_id, value],
+            )?;
+        }
+        Target::Objective {
+            quest_id,
+            objective_id,
+            text,
+        } => {
+            let there = state(transaction, target)?.flatten().is_some();
+            let _ = match (value, there) {
+                (None, _) => transaction.execute(
+                    "DELETE FROM quest_objectives WHERE id = ?1",
+                    params![objective_id],
+                )?,
+                (Some(state), true) => transaction.execute(
+                    "UPDATE quest_objectives SET done = ?2 WHERE id = ?1",
+                    params![objective_id, state == DONE],
+                )?,
+                // Put back in its old place if that's free, or at the end if
+                // not.
+                (Some(state), false) => transaction.execute(
+                    "INSERT INTO quest_objectives (id, quest_id, text, done)
+                     SELECT CASE WHEN EXISTS (SELECT 1 FROM quest_objectives WHERE id = ?1)
+                                 THEN NULL ELSE ?1 END, ?2, ?3, ?4",
+                    params![objective_id, quest_id, text, state == DONE],
+                )?,
+            };
+        }
+    }
+    Ok(())
+}
+
+impl super::MageDB {
+    /// A page of a campaign's history, newest first. `about` limits it to
+    /// events that changed a character, or the party pot.
+    pub async fn history(
+        &self,
+        campaign_id: &i64,
+        about: Option<Owner>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Event>> {
+        let campaign_id = *campaign_id;
+        let (character, pot) = match about {
+            Some(Owner::Character(id)) => (Some(id), false),
+            Some(Owner::Pot(_)) => (None, true),
+            None => (None, false),
+        };
+        self.with(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT id FROM history
+                 WHERE campaign_id = ?1
+                   AND (?2 IS NULL OR EXISTS (
+                       SELECT 1 FROM history_edits
+                       WHERE event_id = history.id
+                         AND kind IN ('inventory', 'xp', 'multiplier', 'info')
+                         AND target = ?2))
+                   AND (NOT ?3 OR EXISTS (
+                       SELECT 1 FROM history_edits WHERE event_id = history.id AND kind = 'pot'))
+                 ORDER BY id DESC LIMIT ?4 OFFSET ?5",
+            )?;
+            let ids = statement
+                .query_map(
+                    params![campaign_id, character, pot, limit as i64, offset as i64],
+                    |row| row.get::<_, i64>(0),
+                )?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            let mut events = Vec::with_capacity(ids.len());
+            for id in ids {
+                events.extend(find(conn, campaign_id, id)?);
+            }
+            Ok(events)
+        })
+        .await
+    }
+
+    /// Undo an event in a campaign's history, putting everything it changed
+    /// back the way it was. That's only done if nothing it changed has
+    /// changed again since; if anything has, or it's already been undone,
+    /// nothing is and the reason is returned. Returns the event that undid
+    /// it, which is itself in the history.
+    ///
+    /// History that can't be undone as recorded, which could only come of
+    /// the database being changed by hand, is an error.
+    pub async fn undo(
+        &self,
+        campaign_id: &i64,
+        id: &i64,
+        user_id: &u64,
+    ) -> Result<Result<Event, String>> {
+        let (campaign_id, id, user_id) = (*campaign_id, *id, *user_id);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let Some(event) = find(&transaction, campaign_id, id)? else {
+                return Ok(Ok(Err(format!(
+                    "There's no change #{id} in this campaign."
+                ))));
+            };
+            if let Some(by) = event.undone_by {
+                return Ok(Ok(Err(format!(
+                    "Change #{id} was already undone by #{by}."
+                ))));
+            }
+
+            // Last first, so that an item changed twice ends up as it was
+            // before the first change.
+            for edit in event.edits.iter().rev() {
+                match state(&transaction, &edit.target)? {
+                    None => return Ok(Ok(Err(format!("{} no longer exists.", edit.subject)))),
+                    Some(now) if now != edit.after => {
+                        return Ok(Ok(Err(format!(
+                            "{} has changed since #{id}. Undo the later changes first.",
+                            edit.subject
+                        ))))
+                    }
+                    Some(_) => {}
+                }
+                if let Some(reason) = blocked(&transaction, campaign_id, edit)? {
+                    return Ok(Ok(Err(reason)));
+                }
+                restore(
+                    &transaction,
+                    campaign_id,
+                    user_id,
+                    &edit.target,
+                    edit.before.as_deref(),
+                )?;
+            }
+
+            let mut reversed: Vec<Edit> = event.edits.iter().rev().map(Edit::reverse).collect();
+            // With the level table put back, the levels in info posts are
+            // brought in line with it once everything else is, as they were
+            // when it changed.
+            if event
+                .edits
+                .iter()
+                .any(|edit| matches!(edit.target, Target::Level { .. }))
+            {
+                reversed.extend(sync_levels(&transaction, campaign_id, None)?);
+            }
+            let Some(undo) = insert(&transaction, campaign_id, user_id, Some(id), reversed)? else {
+                return Ok(Err(eyre!(
+                    "Change #{id} in campaign {campaign_id} changed nothing"
+                )));
+            };
+            let _ = transaction.execute(
+                "UPDATE history SET undone_by = ?2 WHERE id = ?1",
+                params![id, undo],
+            )?;
+            // Undoing an undo redoes what it undid, which can then be undone
+            // again.
+            if let Some(undid) = event.undoes {
+                let _ = transaction.execute(
+                    "UPDATE history SET undone_by = NULL WHERE id = ?1",
+                    params![undid],
+                )?;
+            }
+            let Some(undo) = find(&transaction, campaign_id, undo)? else {
+                return Ok(Err(eyre!("Undo #{undo} of #{id} wasn't recorded")));
+            };
+            transaction.commit()?;
+            Ok(Ok(Ok(undo)))
+        })
+        .await?
+    }
+}
@@ -0,0 +1,120 @@
+use eyre::Result;
+use rusqlite::{params, OptionalExtension, Transaction};
+
+use crate::model::history::{Edit, Target};
+use crate::model::info::Info;
+
+use super::history::{character_subject, record};
+
+/// Set a tag in a character's post, or remove it if the value is None, as
+/// part of a larger transaction. A tag that's already there keeps its place,
+/// and the name it was first given. Returns that name, and the tag's value
+/// before.
+pub(super) fn set_tag(
+    transaction: &Transaction,
+    character_id: i64,
+    tag: &str,
+    value: Option<&str>,
+) -> rusqlite::Result<(String, Option<String>)> {
+    let existing: Option<(String, String)> = transaction
+        .query_row(
+            "SELECT tag, value FROM character_info WHERE character_id = ?1 AND tag = ?2",
+            params![character_id, tag],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let _ = match value {
+        Some(value) => transaction.execute(
+            "INSERT INTO character_info (character_id, tag, value) VALUES (?1, ?2, ?3)
+             ON CONFLICT (character_id, tag) DO UPDATE SET value = excluded.value",
+            params![character_id, tag, value],
+        )?,
+        None => transaction.execute(
+            "DELETE FROM character_info WHERE character_id = ?1 AND tag = ?2",
+            params![character_id, tag],
+        )?,
+    };
+    Ok(match existing {
+        Some((name, before)) => (name, Some(before)),
+        None => (tag.to_owned(), None),
+    })
+}
+
+impl super::MageDB {
+    /// A character's info post, in the order its tags were first added.
+    pub async fn character_info(&self, character_id: &i64) -> Result<Vec<Info>> {
+        let character_id = *character_id;
+        self.with(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT tag, value FROM character_info WHERE character_id = ?1 ORDER BY rowid",
+            )?;
+            let info = statement
+                .query_map(params![character_id], |row| {
+                    Ok(Info {
+                        tag: row.get(0)?,
+                        value: row.get(1)?,
+                    })
+                })?
+                .collect();
+            info
+        })
+        .await
+    }
+
+    /// Add tags to a character's post, or change the values of tags already
+    /// in it. Changed tags keep their place, and the name they were first
+    /// given.
+    pub async fn set_info(&self, character_id: &i64, info: Vec<Info>, user_id: &u64) -> Result<()> {
+        let (character_id, user_id) = (*character_id, *user_id);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let (campaign_id, subject) = character_subject(&transaction, character_id)?;
+            let mut edits = Vec::with_capacity(info.len());
+            for Info { tag, value } in info {
+                let (tag, before) = set_tag(&transaction, character_id, &tag, Some(&value))?;
+                edits.push(Edit {
+                    subject: subject.clone(),
+                    target: Target::Info { character_id, tag },
+                    before,
+                    after: Some(value),
+                });
+            }
+            record(&transaction, campaign_id, user_id, edits)?;
+            transaction.commit()
+        })
+        .await
+    }
+
+    /// Remove tags from a character's post. Returns the tags that were there
+    /// to remove, as they were named.
+    pub async fn remove_info(
+        &self,
+        character_id: &i64,
+        tags: Vec<String>,
+        user_id: &u64,
+    ) -> Result<Vec<String>> {
+        let (character_id, user_id) = (*character_id, *user_id);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let (campaign_id, subject) = character_subject(&transaction, character_id)?;
+            let mut removed = Vec::with_capacity(tags.len());
+            let mut edits = Vec::with_capacity(tags.len());
+            for tag in tags {
+                let (tag, before) = set_tag(&transaction, character_id, &tag, None)?;
+                if before.is_some() {
+                    removed.push(tag.clone());
+                }
+                edits.push(Edit {
+                    subject: subject.clone(),
+                    target: Target::Info { character_id, tag },
+                    before,
+                    after: None,
+                });
+            }
+            record(&transaction, campaign_id, user_id, edits)?;
+            transaction.commit()?;
+            Ok(removed)
+        })
+        .await
+    }
+}
@@ -0,0 +1,218 @@
+use eyre::Result;
+use rusqlite::{params, OptionalExtension, Transaction};
+
+use crate::model::history::{Edit, Target};
+use crate::model::inventory::{
+    Change, ChangeError, Changed, Contribution, Contributor, Owner, Stack,
+};
+
+use super::history::{owner_subject, record};
+
+/// The table an owner's items are kept in, the column naming the owner, and
+/// the owner's ID.
+fn location(owner: &Owner) -> (&'static str, &'static str, i64) {
+    match *owner {
+        Owner::Character(id) => ("inventory_items", "character_id", id),
+        Owner::Pot(campaign_id) => ("pot_items", "campaign_id", campaign_id),
+    }
+}
+
+/// Make a change to one item, as part of a larger transaction. Changes to the
+/// party pot are entered in its ledger.
+pub(super) fn change_item(
+    transaction: &Transaction,
+    owner: &Owner,
+    item: String,
+    change: Change,
+    contributor: &Contributor,
+) -> rusqlite::Result<Result<Changed, ChangeError>> {
+    let (table, column, id) = location(owner);
+    let (item, before) = transaction
+        .query_row(
+            &format!("SELECT name, quantity FROM {table} WHERE {column} = ?1 AND name = ?2"),
+            params![id, item],
+            |row| Ok((row.get::<_, String>(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .unwrap_or((item, 0));
+    let after = match change.apply(&item, before) {
+        Ok(after) => after,
+        Err(e) => return Ok(Err(e)),
+    };
+
+    let _ = match after {
+        0 => transaction.execute(
+            &format!("DELETE FROM {table} WHERE {column} = ?1 AND name = ?2"),
+            params![id, item],
+        )?,
+        _ => transaction.execute(
+            &format!(
+                "INSERT INTO {table} ({column}, name, quantity) VALUES (?1, ?2, ?3)
+                 ON CONFLICT ({column}, name) DO UPDATE SET quantity = excluded.quantity"
+            ),
+            params![id, item, after],
+        )?,
+    };
+    if let Owner::Pot(campaign_id) = owner {
+        if after != before {
+            let _ = transaction.execute(
+                "INSERT INTO pot_ledger (campaign_id, user_id, character, name, amount, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    campaign_id,
+                    contributor.user_id as i64,
+                    contributor.character,
+                    item,
+                    after - before,
+                    chrono::Utc::now().timestamp(),
+                ],
+            )?;
+        }
+    }
+    Ok(Ok(Changed {
+        item,
+        before,
+        after,
+    }))
+}
+
+/// A change to one item, for the history.
+fn edit(subject: &str, owner: &Owner, changed: &Changed) -> Edit {
+    Edit {
+        subject: subject.to_owned(),
+        target: Target::Item {
+            owner: *owner,
+            item: changed.item.clone(),
+        },
+        before: Some(changed.before.to_string()),
+        after: Some(changed.after.to_string()),
+    }
+}
+
+impl super::MageDB {
+    /// Everything a character or party pot has, by item name.
+    pub async fn inventory(&self, owner: &Owner) -> Result<Vec<Stack>> {
+        let (table, column, id) = location(owner);
+        self.with(move |conn| {
+            let mut statement = conn.prepare(&format!(
+                "SELECT name, quantity FROM {table} WHERE {column} = ?1 ORDER BY name"
+            ))?;
+            let stacks = statement
+                .query_map(params![id], |row| {
+                    Ok(Stack {
+                        item: row.get(0)?,
+                        quantity: row.get(1)?,
+                    })
+                })?
+                .collect();
+            stacks
+        })
+        .await
+    }
+
+    /// Make a batch of changes to an inventory, in order. Either every change
+    /// is made, or if any can't be, none are and the reason is returned.
+    pub async fn change_items(
+        &self,
+        owner: &Owner,
+        changes: Vec<(String, Change)>,
+        contributor: &Contributor,
+    ) -> Result<Result<Vec<Changed>, ChangeError>> {
+        let (owner, contributor) = (*owner, contributor.clone());
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let mut results = Vec::with_capacity(changes.len());
+            for (item, change) in changes {
+                match change_item(&transaction, &owner, item, change, &contributor)? {
+                    Ok(changed) => results.push(changed),
+                    // Dropping the transaction rolls back the whole batch.
+                    Err(e) => return Ok(Err(e)),
+                }
+            }
+            let (campaign_id, subject) = owner_subject(&transaction, &owner)?;
+            let edits = results.iter().map(|c| edit(&subject, &owner, c)).collect();
+            record(&transaction, campaign_id, contributor.user_id, edits)?;
+            transaction.commit()?;
+            Ok(Ok(results))
+        })
+        .await
+    }
+
+    /// Move items from one inventory to another, in order. Either every item
+    /// is moved, or if any can't be, none are and the reason is returned.
+    /// Returns how each item changed on both sides.
+    pub async fn transfer_items(
+        &self,
+        from: &Owner,
+        to: &Owner,
+        items: Vec<(String, i64)>,
+        contributor: &Contributor,
+    ) -> Result<Result<Vec<(Changed, Changed)>, ChangeError>> {
+        let (from, to, contributor) = (*from, *to, contributor.clone());
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let mut results = Vec::with_capacity(items.len());
+            for (item, amount) in items {
+                let taken = match change_item(
+                    &transaction,
+                    &from,
+                    item,
+                    Change::Remove(Some(amount)),
+                    &contributor,
+                )? {
+                    Ok(taken) => taken,
+                    Err(e) => return Ok(Err(e)),
+                };
+                let given = match change_item(
+                    &transaction,
+                    &to,
+                    taken.item.clone(),
+                    Change::Add(amount),
+                    &contributor,
+                )? {
+                    Ok(given) => given,
+                    Err(e) => return Ok(Err(e)),
+                };
+                results.push((taken, given));
+            }
+            let (campaign_id, giver) = owner_subject(&transaction, &from)?;
+            let (_, receiver) = owner_subject(&transaction, &to)?;
+            let edits = results
+                .iter()
+                .flat_map(|(taken, given)| {
+                    [edit(&giver, &from, taken), edit(&receiver, &to, given)]
+                })
+                .collect();
+            record(&transaction, campaign_id, contributor.user_id, edits)?;
+            transaction.commit()?;
+            Ok(Ok(results))
+        })
+        .await
+    }
+
+    /// The most recent changes to a campaign's party pot, newest first.
+    pub async fn pot_ledger(&self, campaign_id: &i64, limit: usize) -> Result<Vec<Contribution>> {
+        let (campaign_id, limit) = (*campaign_id, limit as i64);
+        self.with(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT user_id, character, name, amount, created_at FROM pot_ledger
+                 WHERE campaign_id = ?1 ORDER BY id DESC LIMIT ?2",
+            )?;
+            let entries = statement
+                .query_map(params![campaign_id, limit], |row| {
+                    Ok(Contribution {
+                        contributor: Contributor {
+                            user_id: row.get::<_, i64>(0)? as u64,
+                            character: row.get(1)?,
+                        },
+                        item: row.get(2)?,
+                        amount: row.get(3)?,
+                        created_at: row.get(4)?,
+                    })
+                })?
+                .collect();
+            entries
+        })
+        .await
+    }
+}
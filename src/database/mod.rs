@@ -0,0 +1,346 @@
+//! Archmage's persistent storage, kept in a single SQLite database file.
+//! Queries are grouped into submodules by what they're about, each adding
+//! methods to [MageDB].
+//!
+//! The schema is versioned using SQLite's `user_version`. To change it, add a
+//! migration to the end of [MIGRATIONS]; never edit one that has shipped, as
+//! existing databases have already run it.
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use eyre::{bail, eyre, Result, WrapErr};
+use rusqlite::Connection;
+
+mod campaigns;
+mod characters;
+mod errors;
+mod guilds;
+mod history;
+mod info;
+mod inventory;
+mod monitors;
+mod quests;
+mod xp;
+#[cfg(test)]
+mod tests;
+
+pub use errors::ErrorRecord;
+
+/// Every change ever made to the schema, in order. A database at version N
+/// has run the first N of these.
+const MIGRATIONS: &[&str] = &[
+    // 1: Guild settings, the guild allow-list and the error log. These tables
+    // predate versioning, hence IF NOT EXISTS.
+    "
+    CREATE TABLE IF NOT EXISTS guild_settings (
+        guild_id INTEGER PRIMARY KEY,
+        prefix   TEXT
+    );
+
+    CREATE TABLE IF NOT EXISTS allowed_guilds (
+        guild_id   INTEGER PRIMARY KEY,
+        added_by   INTEGER NOT NULL,
+        added_at   INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS pending_guilds (
+        guild_id     INTEGER PRIMARY KEY,
+        name         TEXT NOT NULL,
+        requested_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS errors (
+        id         TEXT PRIMARY KEY,
+        context    TEXT NOT NULL,
+        report     TEXT NOT NULL,
+        guild_id   INTEGER,
+        user_id    INTEGER,
+        created_at INTEGER NOT NULL
+    );
+    ",
+    // 2: Campaigns and their DMs.
+    "
+    CREATE TABLE campaigns (
+        id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        guild_id   INTEGER NOT NULL,
+        name       TEXT NOT NULL COLLATE NOCASE,
+        created_at INTEGER NOT NULL,
+        UNIQUE (guild_id, name)
+    );
+
+    CREATE TABLE campaign_dms (
+        campaign_id INTEGER NOT NULL REFERENCES campaigns (id) ON DELETE CASCADE,
+        user_id     INTEGER NOT NULL,
+        added_at    INTEGER NOT NULL,
+        PRIMARY KEY (campaign_id, user_id)
+    );
+    ",
+    // 3: Channels bound to campaigns. A channel belongs to one campaign at most.
+    "
+    CREATE TABLE campaign_channels (
+        channel_id  INTEGER PRIMARY KEY,
+        campaign_id INTEGER NOT NULL REFERENCES campaigns (id) ON DELETE CASCADE,
+        added_by    INTEGER NOT NULL,
+        added_at    INTEGER NOT NULL
+    );
+    ",
+    // 4: Characters and who plays them.
+    "
+    CREATE TABLE characters (
+        id          INTEGER PRIMARY KEY AUTOINCREMENT,
+        campaign_id INTEGER NOT NULL REFERENCES campaigns (id) ON DELETE CASCADE,
+        name        TEXT NOT NULL COLLATE NOCASE,
+        created_at  INTEGER NOT NULL,
+        UNIQUE (campaign_id, name)
+    );
+
+    CREATE TABLE character_players (
+        character_id INTEGER NOT NULL REFERENCES characters (id) ON DELETE CASCADE,
+        user_id      INTEGER NOT NULL,
+        added_at     INTEGER NOT NULL,
+        PRIMARY KEY (character_id, user_id)
+    );
+    ",
+    // 5: Character inventories. Items run out rather than reaching zero.
+    "
+    CREATE TABLE inventory_items (
+        character_id INTEGER NOT NULL REFERENCES characters (id) ON DELETE CASCADE,
+        name         TEXT NOT NULL COLLATE NOCASE,
+        quantity     INTEGER NOT NULL CHECK (quantity > 0),
+        PRIMARY KEY (character_id, name)
+    );
+    ",
+    // 6: Party pots, and a ledger of who put in or took out what. Entries
+    // outlive the characters that made them.
+    "
+    CREATE TABLE pot_items (
+        campaign_id INTEGER NOT NULL REFERENCES campaigns (id) ON DELETE CASCADE,
+        name        TEXT NOT NULL COLLATE NOCASE,
+        quantity    INTEGER NOT NULL CHECK (quantity > 0),
+        PRIMARY KEY (campaign_id, name)
+    );
+
+    CREATE TABLE pot_ledger (
+        id          INTEGER PRIMARY KEY,
+        campaign_id INTEGER NOT NULL REFERENCES campaigns (id) ON DELETE CASCADE,
+        user_id     INTEGER NOT NULL,
+        character   TEXT,
+        name        TEXT NOT NULL,
+        amount      INTEGER NOT NULL,
+        created_at  INTEGER NOT NULL
+    );
+    CREATE INDEX pot_ledger_by_campaign ON pot_ledger (campaign_id, id);
+    ",
+    // 7: Character info posts. Tags are listed in the order they were first
+    // added, which is their rowid's.
+    "
+    CREATE TABLE character_info (
+        character_id INTEGER NOT NULL REFERENCES characters (id) ON DELETE CASCADE,
+        tag          TEXT NOT NULL COLLATE NOCASE,
+        value        TEXT NOT NULL,
+        UNIQUE (character_id, tag)
+    );
+    ",
+    // 8: XP, and the levels it earns. New campaigns start with the D&D 3.5e
+    // SRD's table, as do campaigns that existed before levels did.
+    "
+    ALTER TABLE characters ADD COLUMN xp INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE characters ADD COLUMN xp_multiplier REAL NOT NULL DEFAULT 1.0;
+
+    CREATE TABLE default_levels (
+        level INTEGER PRIMARY KEY,
+        xp    INTEGER NOT NULL
+    );
+    INSERT INTO default_levels (level, xp) VALUES
+        (1, 0), (2, 1000), (3, 3000), (4, 6000),
+        (5, 10000), (6, 15000), (7, 21000), (8, 28000),
+        (9, 36000), (10, 45000), (11, 55000), (12, 66000),
+        (13, 78000), (14, 91000), (15, 105000), (16, 120000),
+        (17, 136000), (18, 153000), (19, 171000), (20, 190000);
+
+    CREATE TABLE campaign_levels (
+        campaign_id INTEGER NOT NULL REFERENCES campaigns (id) ON DELETE CASCADE,
+        level       INTEGER NOT NULL,
+        xp          INTEGER NOT NULL,
+        PRIMARY KEY (campaign_id, level)
+    );
+    INSERT INTO campaign_levels (campaign_id, level, xp)
+        SELECT campaigns.id, level, xp FROM campaigns, default_levels;
+
+    CREATE TRIGGER campaign_default_levels AFTER INSERT ON campaigns
+    BEGIN
+        INSERT INTO campaign_levels (campaign_id, level, xp)
+            SELECT NEW.id, level, xp FROM default_levels;
+    END;
+    ",
+    // 9: Quest logs. Quests are numbered within their campaign, and keep who
+    // added them rather than which character. Objectives are listed in the
+    // order they were added, which is their ID's.
+    "
+    CREATE TABLE quests (
+        id          INTEGER PRIMARY KEY AUTOINCREMENT,
+        campaign_id INTEGER NOT NULL REFERENCES campaigns (id) ON DELETE CASCADE,
+        number      INTEGER NOT NULL,
+        title       TEXT NOT NULL,
+        description TEXT,
+        giver       TEXT,
+        reward      TEXT,
+        status      TEXT NOT NULL DEFAULT 'active',
+        added_by    INTEGER NOT NULL,
+        created_at  INTEGER NOT NULL,
+        UNIQUE (campaign_id, number)
+    );
+
+    CREATE TABLE quest_objectives (
+        id       INTEGER PRIMARY KEY,
+        quest_id INTEGER NOT NULL REFERENCES quests (id) ON DELETE CASCADE,
+        text     TEXT NOT NULL,
+        done     INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX quest_objectives_by_quest ON quest_objectives (quest_id);
+    ",
+    // 10: Features DMs have turned off in their campaigns. Features are on
+    // unless they're listed here, so new ones start out on everywhere.
+    "
+    CREATE TABLE disabled_features (
+        campaign_id INTEGER NOT NULL REFERENCES campaigns (id) ON DELETE CASCADE,
+        feature     TEXT NOT NULL COLLATE NOCASE,
+        disabled_by INTEGER NOT NULL,
+        disabled_at INTEGER NOT NULL,
+        PRIMARY KEY (campaign_id, feature)
+    );
+    ",
+    // 11: Monitors, the messages Archmage keeps up to date in a channel, one
+    // channel per kind per campaign. A monitor may take several messages.
+    "
+    CREATE TABLE monitors (
+        campaign_id INTEGER NOT NULL REFERENCES campaigns (id) ON DELETE CASCADE,
+        kind        TEXT NOT NULL,
+        channel_id  INTEGER NOT NULL,
+        added_by    INTEGER NOT NULL,
+        added_at    INTEGER NOT NULL,
+        PRIMARY KEY (campaign_id, kind)
+    );
+
+    CREATE TABLE monitor_messages (
+        campaign_id INTEGER NOT NULL,
+        kind        TEXT NOT NULL,
+        position    INTEGER NOT NULL,
+        message_id  INTEGER NOT NULL,
+        PRIMARY KEY (campaign_id, kind, position),
+        FOREIGN KEY (campaign_id, kind) REFERENCES monitors (campaign_id, kind) ON DELETE CASCADE
+    );
+    ",
+    // 12: Campaign history. Each event is everything one command changed, and
+    // each edit one thing it changed, with its state before and after as
+    // text. Edits outlive the characters and quests they were about.
+    "
+    CREATE TABLE history (
+        id          INTEGER PRIMARY KEY AUTOINCREMENT,
+        campaign_id INTEGER NOT NULL REFERENCES campaigns (id) ON DELETE CASCADE,
+        user_id     INTEGER NOT NULL,
+        undoes      INTEGER,
+        undone_by   INTEGER,
+        created_at  INTEGER NOT NULL
+    );
+    CREATE INDEX history_by_campaign ON history (campaign_id, id);
+
+    CREATE TABLE history_edits (
+        event_id INTEGER NOT NULL REFERENCES history (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        subject  TEXT NOT NULL,
+        kind     TEXT NOT NULL,
+        target   INTEGER NOT NULL,
+        name     TEXT,
+        detail   INTEGER,
+        before   TEXT,
+        after    TEXT,
+        PRIMARY KEY (event_id, position)
+    );
+    ",
+];
+
+/// A handle to the database. Queries are short, so a single connection
+/// behind a lock is plenty. Queries run on tokio's blocking thread pool, so
+/// handlers can await them without stalling other events.
+#[derive(Debug, Clone)]
+pub struct MageDB {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl MageDB {
+    /// Open (or create) the database at the given path, bringing its schema
+    /// up to date.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("Unable to create database directory {}", parent.display()))?;
+        }
+
+        let mut conn = Connection::open(path)
+            .wrap_err_with(|| format!("Unable to open database {}", path.display()))?;
+        conn.execute_batch("PRAGMA foreign_keys = ON;")
+            .wrap_err("Unable to enable foreign keys")?;
+        migrate(&mut conn)
+            .wrap_err_with(|| format!("Unable to migrate database {}", path.display()))?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Make sure everything written so far is on disk. Called on shutdown.
+    pub async fn flush(&self) -> Result<()> {
+        self.with(|conn| conn.cache_flush()).await
+    }
+
+    /// Run some queries against the connection. Anything that must happen
+    /// all-or-nothing should open a transaction on it.
+    async fn with<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| eyre!("Database connection poisoned"))?;
+            f(&mut conn).map_err(|e| eyre!(e))
+        })
+        .await
+        .map_err(|e| eyre!(e).wrap_err("Database query panicked"))?
+    }
+}
+
+/// The schema version the database is at.
+fn version(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Run every migration the database hasn't yet, each in its own transaction.
+/// Refuses to touch a database from a newer version of Archmage.
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let current = version(conn)?;
+    if current > MIGRATIONS.len() {
+        bail!(
+            "Database is at schema version {current}, but this version of Archmage only knows up to {}",
+            MIGRATIONS.len()
+        );
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        let target = index + 1;
+        let transaction = conn.transaction()?;
+        transaction
+            .execute_batch(migration)
+            .wrap_err_with(|| format!("Migration to schema version {target} failed"))?;
+        transaction.pragma_update(None, "user_version", target)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
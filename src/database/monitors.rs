@@ -0,0 +1,136 @@
+use eyre::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::model::monitor::{Kind, Monitor};
+
+fn find(conn: &Connection, campaign_id: i64, kind: Kind) -> rusqlite::Result<Option<Monitor>> {
+    let channel_id = conn
+        .query_row(
+            "SELECT channel_id FROM monitors WHERE campaign_id = ?1 AND kind = ?2",
+            params![campaign_id, kind.as_str()],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?;
+    let Some(channel_id) = channel_id else {
+        return Ok(None);
+    };
+
+    let mut statement = conn.prepare_cached(
+        "SELECT message_id FROM monitor_messages WHERE campaign_id = ?1 AND kind = ?2
+         ORDER BY position",
+    )?;
+    let message_ids = statement
+        .query_map(params![campaign_id, kind.as_str()], |row| {
+            row.get::<_, i64>(0)
+        })?
+        .map(|id| id.map(|id| id as u64))
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(Some(Monitor {
+        channel_id: channel_id as u64,
+        message_ids,
+    }))
+}
+
+impl super::MageDB {
+    /// A campaign's monitor of one kind, if it has one.
+    pub async fn monitor(&self, campaign_id: &i64, kind: Kind) -> Result<Option<Monitor>> {
+        let campaign_id = *campaign_id;
+        self.with(move |conn| find(conn, campaign_id, kind)).await
+    }
+
+    /// Monitor a kind of thing in a channel, instead of wherever it was
+    /// monitored before. Returns the monitor this replaces, whose messages
+    /// are no longer kept up to date, or None if there was none or it was
+    /// already in that channel.
+    pub async fn set_monitor(
+        &self,
+        campaign_id: &i64,
+        kind: Kind,
+        channel_id: &u64,
+        added_by: &u64,
+    ) -> Result<Option<Monitor>> {
+        let (campaign_id, channel_id, added_by) = (*campaign_id, *channel_id, *added_by as i64);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let previous = find(&transaction, campaign_id, kind)?;
+            if previous
+                .as_ref()
+                .is_some_and(|p| p.channel_id == channel_id)
+            {
+                return Ok(None);
+            }
+            let _ = transaction.execute(
+                "DELETE FROM monitors WHERE campaign_id = ?1 AND kind = ?2",
+                params![campaign_id, kind.as_str()],
+            )?;
+            let _ = transaction.execute(
+                "INSERT INTO monitors (campaign_id, kind, channel_id, added_by, added_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    campaign_id,
+                    kind.as_str(),
+                    channel_id as i64,
+                    added_by,
+                    chrono::Utc::now().timestamp()
+                ],
+            )?;
+            transaction.commit()?;
+            Ok(previous)
+        })
+        .await
+    }
+
+    /// Stop monitoring a kind of thing. Returns the monitor that was removed,
+    /// if there was one.
+    pub async fn remove_monitor(&self, campaign_id: &i64, kind: Kind) -> Result<Option<Monitor>> {
+        let campaign_id = *campaign_id;
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let previous = find(&transaction, campaign_id, kind)?;
+            let _ = transaction.execute(
+                "DELETE FROM monitors WHERE campaign_id = ?1 AND kind = ?2",
+                params![campaign_id, kind.as_str()],
+            )?;
+            transaction.commit()?;
+            Ok(previous)
+        })
+        .await
+    }
+
+    /// Record the messages a monitor is shown in, in order. Does nothing if
+    /// the monitor has since been removed, or moved to another channel.
+    pub async fn set_monitor_messages(
+        &self,
+        campaign_id: &i64,
+        kind: Kind,
+        channel_id: &u64,
+        message_ids: Vec<u64>,
+    ) -> Result<()> {
+        let (campaign_id, channel_id) = (*campaign_id, *channel_id);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let current = find(&transaction, campaign_id, kind)?;
+            if current.map(|m| m.channel_id) != Some(channel_id) {
+                return Ok(());
+            }
+            let _ = transaction.execute(
+                "DELETE FROM monitor_messages WHERE campaign_id = ?1 AND kind = ?2",
+                params![campaign_id, kind.as_str()],
+            )?;
+            for (position, message_id) in message_ids.into_iter().enumerate() {
+                let _ = transaction.execute(
+                    "INSERT INTO monitor_messages (campaign_id, kind, position, message_id)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        campaign_id,
+                        kind.as_str(),
+                        position as i64,
+                        message_id as i64
+                    ],
+                )?;
+            }
+            transaction.commit()
+        })
+        .await
+    }
+}
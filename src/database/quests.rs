@@ -0,0 +1,334 @@
+use eyre::Result;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::model::history::{Edit, Target, DONE, OPEN};
+use crate::model::quest::{Field, Objective, Quest, QuestFilter, Status, MAX_OBJECTIVES};
+
+use super::history::{quest_subject, record};
+
+const QUEST_COLUMNS: &str =
+    "id, campaign_id, number, title, description, giver, reward, status, added_by";
+
+/// A quest without its objectives, which [objectives] fills in.
+fn quest(row: &Row) -> rusqlite::Result<Quest> {
+    let status: String = row.get(7)?;
+    Ok(Quest {
+        id: row.get(0)?,
+        campaign_id: row.get(1)?,
+        number: row.get(2)?,
+        title: row.get(3)?,
+        description: row.get(4)?,
+        giver: row.get(5)?,
+        reward: row.get(6)?,
+        status: Status::parse(&status).unwrap_or(Status::Active),
+        added_by: row.get::<_, i64>(8)? as u64,
+        objectives: Vec::new(),
+    })
+}
+
+fn objectives(conn: &Connection, quest_id: i64) -> rusqlite::Result<Vec<Objective>> {
+    let mut statement = conn.prepare_cached(
+        "SELECT text, done FROM quest_objectives WHERE quest_id = ?1 ORDER BY id",
+    )?;
+    let objectives = statement
+        .query_map(params![quest_id], |row| {
+            Ok(Objective {
+                text: row.get(0)?,
+                done: row.get(1)?,
+            })
+        })?
+        .collect();
+    objectives
+}
+
+/// The ID of a quest's objective, counting from 1 in the order they were
+/// added.
+fn objective_id(conn: &Connection, quest_id: i64, index: usize) -> rusqlite::Result<Option<i64>> {
+    if index == 0 {
+        return Ok(None);
+    }
+    conn.query_row(
+        "SELECT id FROM quest_objectives WHERE quest_id = ?1 ORDER BY id LIMIT 1 OFFSET ?2",
+        params![quest_id, index as i64 - 1],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// How an objective's state is recorded in the history.
+fn objective_state(done: bool) -> Option<String> {
+    Some(if done { DONE } else { OPEN }.to_owned())
+}
+
+impl super::MageDB {
+    /// Add an active quest to a campaign's log, numbered after the last one.
+    pub async fn add_quest(&self, campaign_id: &i64, title: &str, added_by: &u64) -> Result<Quest> {
+        let (campaign_id, title, added_by) = (*campaign_id, title.to_owned(), *added_by);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let added = transaction.query_row(
+                &format!(
+                    "INSERT INTO quests (campaign_id, number, title, added_by, created_at)
+                     SELECT ?1, COALESCE(MAX(number), 0) + 1, ?2, ?3, ?4
+                     FROM quests WHERE campaign_id = ?1
+                     RETURNING {QUEST_COLUMNS}"
+                ),
+                params![
+                    campaign_id,
+                    title,
+                    added_by as i64,
+                    chrono::Utc::now().timestamp()
+                ],
+                quest,
+            )?;
+            let edit = Edit {
+                subject: format!("Quest #{}", added.number),
+                target: Target::Quest {
+                    quest_id: added.id,
+                    number: added.number,
+                },
+                before: None,
+                after: Some(added.title.clone()),
+            };
+            record(&transaction, campaign_id, added_by, vec![edit])?;
+            transaction.commit()?;
+            Ok(added)
+        })
+        .await
+    }
+
+    /// A quest in a campaign's log, by its number.
+    pub async fn quest(&self, campaign_id: &i64, number: &i64) -> Result<Option<Quest>> {
+        let (campaign_id, number) = (*campaign_id, *number);
+        self.with(move |conn| {
+            let found = conn
+                .query_row(
+                    &format!(
+                        "SELECT {QUEST_COLUMNS} FROM quests WHERE campaign_id = ?1 AND number = ?2"
+                    ),
+                    params![campaign_id, number],
+                    quest,
+                )
+                .optional()?;
+            let Some(mut found) = found else {
+                return Ok(None);
+            };
+            found.objectives = objectives(conn, found.id)?;
+            Ok(Some(found))
+        })
+        .await
+    }
+
+    /// The quests in a campaign's log that pass the filter, by number.
+    pub async fn quests(&self, campaign_id: &i64, filter: &QuestFilter) -> Result<Vec<Quest>> {
+        let (campaign_id, filter) = (*campaign_id, filter.clone());
+        self.with(move |conn| {
+            let mut statement = conn.prepare(&format!(
+                "SELECT {QUEST_COLUMNS} FROM quests
+                 WHERE campaign_id = ?1
+                   AND (?2 IS NULL OR status = ?2)
+                   AND (?3 IS NULL OR instr(lower(giver), lower(?3)) > 0)
+                 ORDER BY number"
+            ))?;
+            let quests = statement
+                .query_map(
+                    params![campaign_id, filter.status.map(Status::as_str), filter.giver],
+                    quest,
+                )?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            quests
+                .into_iter()
+                .map(|mut quest| {
+                    quest.objectives = objectives(conn, quest.id)?;
+                    Ok(quest)
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// Change one part of a quest. None clears it.
+    pub async fn edit_quest(
+        &self,
+        id: &i64,
+        field: Field,
+        value: Option<String>,
+        user_id: &u64,
+    ) -> Result<()> {
+        let (id, user_id) = (*id, *user_id);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let before = transaction.query_row(
+                &format!("SELECT {} FROM quests WHERE id = ?1", field.as_str()),
+                params![id],
+                |row| row.get(0),
+            )?;
+            let _ = transaction.execute(
+                &format!("UPDATE quests SET {} = ?2 WHERE id = ?1", field.as_str()),
+                params![id, value],
+            )?;
+            let (campaign_id, subject) = quest_subject(&transaction, id)?;
+            let edit = Edit {
+                subject,
+                target: Target::QuestField {
+                    quest_id: id,
+                    field,
+                },
+                before,
+                after: value,
+            };
+            record(&transaction, campaign_id, user_id, vec![edit])?;
+            transaction.commit()
+        })
+        .await
+    }
+
+    /// Mark a quest active, completed, failed or abandoned.
+    pub async fn set_quest_status(&self, id: &i64, status: Status, user_id: &u64) -> Result<()> {
+        let (id, user_id) = (*id, *user_id);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let before = transaction.query_row(
+                "SELECT status FROM quests WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )?;
+            let _ = transaction.execute(
+                "UPDATE quests SET status = ?2 WHERE id = ?1",
+                params![id, status.as_str()],
+            )?;
+            let (campaign_id, subject) = quest_subject(&transaction, id)?;
+            let edit = Edit {
+                subject,
+                target: Target::Status { quest_id: id },
+                before: Some(before),
+                after: Some(status.as_str().to_owned()),
+            };
+            record(&transaction, campaign_id, user_id, vec![edit])?;
+            transaction.commit()
+        })
+        .await
+    }
+
+    /// Add objectives to the end of a quest's checklist. If that would make
+    /// it too long, none are added and the reason is returned.
+    pub async fn add_objectives(
+        &self,
+        quest_id: &i64,
+        added: Vec<String>,
+        user_id: &u64,
+    ) -> Result<Result<(), String>> {
+        let (quest_id, user_id) = (*quest_id, *user_id);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let count = objectives(&transaction, quest_id)?.len();
+            if count + added.len() > MAX_OBJECTIVES {
+                return Ok(Err(format!(
+                    "Quests may have at most {MAX_OBJECTIVES} objectives."
+                )));
+            }
+            let (campaign_id, subject) = quest_subject(&transaction, quest_id)?;
+            let mut edits = Vec::with_capacity(added.len());
+            for text in added {
+                let objective_id = transaction.query_row(
+                    "INSERT INTO quest_objectives (quest_id, text) VALUES (?1, ?2) RETURNING id",
+                    params![quest_id, text],
+                    |row| row.get(0),
+                )?;
+                edits.push(Edit {
+                    subject: subject.clone(),
+                    target: Target::Objective {
+                        quest_id,
+                        objective_id,
+                        text,
+                    },
+                    before: None,
+                    after: objective_state(false),
+                });
+            }
+            record(&transaction, campaign_id, user_id, edits)?;
+            transaction.commit()?;
+            Ok(Ok(()))
+        })
+        .await
+    }
+
+    /// Tick off an objective, counting from 1, or untick it. Returns the
+    /// objective, or None if the quest has no such objective.
+    pub async fn set_objective_done(
+        &self,
+        quest_id: &i64,
+        index: usize,
+        done: bool,
+        user_id: &u64,
+    ) -> Result<Option<Objective>> {
+        let (quest_id, user_id) = (*quest_id, *user_id);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let Some(objective_id) = objective_id(&transaction, quest_id, index)? else {
+                return Ok(None);
+            };
+            let (text, before): (String, bool) = transaction.query_row(
+                "SELECT text, done FROM quest_objectives WHERE id = ?1",
+                params![objective_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            let _ = transaction.execute(
+                "UPDATE quest_objectives SET done = ?2 WHERE id = ?1",
+                params![objective_id, done],
+            )?;
+            let (campaign_id, subject) = quest_subject(&transaction, quest_id)?;
+            let edit = Edit {
+                subject,
+                target: Target::Objective {
+                    quest_id,
+                    objective_id,
+                    text: text.clone(),
+                },
+                before: objective_state(before),
+                after: objective_state(done),
+            };
+            record(&transaction, campaign_id, user_id, vec![edit])?;
+            transaction.commit()?;
+            Ok(Some(Objective { text, done }))
+        })
+        .await
+    }
+
+    /// Remove an objective, counting from 1. Returns what it was, or None if
+    /// the quest has no such objective.
+    pub async fn remove_objective(
+        &self,
+        quest_id: &i64,
+        index: usize,
+        user_id: &u64,
+    ) -> Result<Option<String>> {
+        let (quest_id, user_id) = (*quest_id, *user_id);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let Some(objective_id) = objective_id(&transaction, quest_id, index)? else {
+                return Ok(None);
+            };
+            let (text, done): (String, bool) = transaction.query_row(
+                "DELETE FROM quest_objectives WHERE id = ?1 RETURNING text, done",
+                params![objective_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            let (campaign_id, subject) = quest_subject(&transaction, quest_id)?;
+            let edit = Edit {
+                subject,
+                target: Target::Objective {
+                    quest_id,
+                    objective_id,
+                    text: text.clone(),
+                },
+                before: objective_state(done),
+                after: None,
+            };
+            record(&transaction, campaign_id, user_id, vec![edit])?;
+            transaction.commit()?;
+            Ok(Some(text))
+        })
+        .await
+    }
+}
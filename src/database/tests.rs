@@ -0,0 +1,1274 @@
+//! Every test gets its own database file in a temporary directory, which is
+//! deleted when the test ends.
+use rusqlite::Connection;
+use tempfile::TempDir;
+
+use super::{version, ErrorRecord, MageDB, MIGRATIONS};
+use crate::model::history::{Edit, Target};
+use crate::model::info::Info;
+use crate::model::inventory::{Change, ChangeError, Changed, Contributor, Owner, Stack};
+use crate::model::monitor::{Kind, Monitor};
+use crate::model::quest::{Field, Objective, Quest, QuestFilter, Status, MAX_OBJECTIVES};
+use crate::model::xp::{Threshold, XpChange};
+
+fn temp_db() -> (TempDir, MageDB) {
+    let dir = tempfile::tempdir().expect("Unable to create temporary directory");
+    let db = MageDB::open(dir.path().join("archmage.db")).expect("Unable to open database");
+    (dir, db)
+}
+
+#[tokio::test]
+async fn new_database_is_fully_migrated() {
+    let (_dir, db) = temp_db();
+    let conn = db.conn.lock().unwrap();
+    assert_eq!(version(&conn).unwrap(), MIGRATIONS.len());
+}
+
+#[tokio::test]
+async fn open_creates_missing_directories() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("nested").join("data").join("archmage.db");
+    let _db = MageDB::open(&path).unwrap();
+    assert!(path.exists());
+}
+
+#[tokio::test]
+async fn reopening_keeps_data() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("archmage.db");
+    {
+        let db = MageDB::open(&path).unwrap();
+        db.set_prefix(&1, Some("?")).await.unwrap();
+    }
+    let db = MageDB::open(&path).unwrap();
+    assert_eq!(db.get_prefix(&1).await.unwrap().as_deref(), Some("?"));
+}
+
+#[tokio::test]
+async fn unversioned_database_is_migrated_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("archmage.db");
+    {
+        // As created before the schema was versioned.
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE guild_settings (guild_id INTEGER PRIMARY KEY, prefix TEXT);
+             INSERT INTO guild_settings VALUES (7, '$');",
+        )
+        .unwrap();
+    }
+
+    let db = MageDB::open(&path).unwrap();
+    assert_eq!(db.get_prefix(&7).await.unwrap().as_deref(), Some("$"));
+    assert_eq!(version(&db.conn.lock().unwrap()).unwrap(), MIGRATIONS.len());
+}
+
+#[tokio::test]
+async fn newer_database_is_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("archmage.db");
+    {
+        let conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+    }
+
+    assert!(MageDB::open(&path).is_err());
+}
+
+#[tokio::test]
+async fn prefix_round_trip() {
+    let (_dir, db) = temp_db();
+    assert_eq!(db.get_prefix(&1).await.unwrap(), None);
+
+    db.set_prefix(&1, Some("?")).await.unwrap();
+    db.set_prefix(&2, Some("%")).await.unwrap();
+    assert_eq!(db.get_prefix(&1).await.unwrap().as_deref(), Some("?"));
+    assert_eq!(db.get_prefix(&2).await.unwrap().as_deref(), Some("%"));
+
+    db.set_prefix(&1, None).await.unwrap();
+    assert_eq!(db.get_prefix(&1).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn guild_allow_list() {
+    let (_dir, db) = temp_db();
+    let guild = u64::MAX >> 1;
+
+    db.mark_guild_pending(&guild, "The Tower").await.unwrap();
+    assert_eq!(
+        db.pending_guilds().await.unwrap(),
+        vec![(guild, "The Tower".to_owned())]
+    );
+    assert!(!db.is_guild_allowed(&guild).await.unwrap());
+
+    assert!(db.allow_guild(&guild, &3).await.unwrap());
+    assert!(!db.allow_guild(&guild, &3).await.unwrap());
+    assert!(db.is_guild_allowed(&guild).await.unwrap());
+    assert_eq!(db.allowed_guilds().await.unwrap(), vec![guild]);
+    assert!(db.pending_guilds().await.unwrap().is_empty());
+
+    assert!(db.disallow_guild(&guild).await.unwrap());
+    assert!(!db.disallow_guild(&guild).await.unwrap());
+    assert!(!db.is_guild_allowed(&guild).await.unwrap());
+}
+
+#[tokio::test]
+async fn error_log_round_trip() {
+    let (_dir, db) = temp_db();
+    let record = ErrorRecord {
+        id: "abc123".to_owned(),
+        context: "{}".to_owned(),
+        report: "Something broke".to_owned(),
+        guild_id: Some(1),
+        user_id: None,
+        created_at: 1_700_000_000,
+    };
+    db.log_error(&record).await.unwrap();
+
+    let found = db.get_error("abc123").await.unwrap().unwrap();
+    assert_eq!(found.report, record.report);
+    assert_eq!(found.guild_id, Some(1));
+    assert_eq!(found.user_id, None);
+    assert_eq!(found.created_at, record.created_at);
+    assert!(db.get_error("nope").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn campaign_lifecycle() {
+    let (_dir, db) = temp_db();
+
+    let campaign = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    assert_eq!(campaign.name, "Lost Mine");
+    assert_eq!(campaign.guild_id, 1);
+    // Names are unique per guild, ignoring case.
+    assert!(db.create_campaign(&1, "lost mine").await.unwrap().is_none());
+    assert!(db.create_campaign(&2, "Lost Mine").await.unwrap().is_some());
+    assert_eq!(
+        db.campaign(&1, "LOST MINE").await.unwrap(),
+        Some(campaign.clone())
+    );
+
+    let (found, created) = db.ensure_campaign(&1, "lost mine").await.unwrap();
+    assert_eq!((found, created), (campaign.clone(), false));
+    let (other, created) = db.ensure_campaign(&1, "Curse of Strahd").await.unwrap();
+    assert!(created);
+    assert_eq!(
+        db.campaigns(&1).await.unwrap(),
+        vec![other.clone(), campaign.clone()]
+    );
+
+    assert!(!db
+        .rename_campaign(&campaign.id, "curse of strahd")
+        .await
+        .unwrap());
+    assert!(db.rename_campaign(&campaign.id, "LOST MINE").await.unwrap());
+    assert_eq!(
+        db.campaign_by_id(&campaign.id).await.unwrap().unwrap().name,
+        "LOST MINE"
+    );
+
+    assert!(db.delete_campaign(&campaign.id).await.unwrap());
+    assert!(!db.delete_campaign(&campaign.id).await.unwrap());
+    assert_eq!(db.campaigns(&1).await.unwrap(), vec![other]);
+}
+
+#[tokio::test]
+async fn campaign_dms() {
+    let (_dir, db) = temp_db();
+    let campaign = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+
+    assert!(db.add_dm(&campaign.id, &10).await.unwrap());
+    assert!(!db.add_dm(&campaign.id, &10).await.unwrap());
+    assert!(db.add_dm(&campaign.id, &11).await.unwrap());
+    assert_eq!(db.dms(&campaign.id).await.unwrap(), vec![10, 11]);
+
+    // Removing the last DM keeps the campaign.
+    assert!(db.remove_dm(&campaign.id, &10).await.unwrap());
+    assert!(!db.remove_dm(&campaign.id, &10).await.unwrap());
+    assert!(db.remove_dm(&campaign.id, &11).await.unwrap());
+    assert!(db.campaign_by_id(&campaign.id).await.unwrap().is_some());
+
+    // Deleting the campaign takes its DMs with it.
+    assert!(db.add_dm(&campaign.id, &10).await.unwrap());
+    assert!(db.delete_campaign(&campaign.id).await.unwrap());
+    let conn = db.conn.lock().unwrap();
+    let left: i64 = conn
+        .query_row("SELECT COUNT(*) FROM campaign_dms", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(left, 0);
+}
+
+#[tokio::test]
+async fn channel_bindings() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let strahd = db
+        .create_campaign(&1, "Curse of Strahd")
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert!(db.channel_campaign(&100).await.unwrap().is_none());
+    assert!(db.bind_channel(&100, &lost_mine.id, &10).await.unwrap());
+    assert!(db.bind_channel(&101, &lost_mine.id, &10).await.unwrap());
+    // A channel belongs to one campaign at most.
+    assert!(!db.bind_channel(&100, &strahd.id, &10).await.unwrap());
+    assert_eq!(
+        db.channel_campaign(&100).await.unwrap(),
+        Some(lost_mine.clone())
+    );
+    assert_eq!(
+        db.campaign_channels(&lost_mine.id).await.unwrap(),
+        vec![100, 101]
+    );
+
+    assert!(!db.unbind_channel(&100, &strahd.id).await.unwrap());
+    assert!(db.unbind_channel(&100, &lost_mine.id).await.unwrap());
+    assert!(db.channel_campaign(&100).await.unwrap().is_none());
+
+    // Deleting a campaign frees its channels.
+    assert!(db.delete_campaign(&lost_mine.id).await.unwrap());
+    assert!(db.channel_campaign(&101).await.unwrap().is_none());
+    assert!(db.bind_channel(&101, &strahd.id, &10).await.unwrap());
+}
+
+#[tokio::test]
+async fn characters_and_players() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let strahd = db
+        .create_campaign(&1, "Curse of Strahd")
+        .await
+        .unwrap()
+        .unwrap();
+
+    let (cal, created) = db.ensure_character(&lost_mine.id, "Cal").await.unwrap();
+    assert!(created);
+    // IDs are unique per campaign, ignoring case.
+    assert_eq!(
+        db.ensure_character(&lost_mine.id, "cal").await.unwrap(),
+        (cal.clone(), false)
+    );
+    let (other_cal, created) = db.ensure_character(&strahd.id, "Cal").await.unwrap();
+    assert!(created);
+    assert_ne!(other_cal.id, cal.id);
+    assert_eq!(
+        db.character(&lost_mine.id, "CAL").await.unwrap(),
+        Some(cal.clone())
+    );
+
+    // Several players may share a character.
+    assert!(db.add_player(&cal.id, &10).await.unwrap());
+    assert!(!db.add_player(&cal.id, &10).await.unwrap());
+    assert!(db.add_player(&cal.id, &11).await.unwrap());
+    assert_eq!(db.players(&cal.id).await.unwrap(), vec![10, 11]);
+
+    // Removing every player keeps the character.
+    assert!(db.remove_player(&cal.id, &10).await.unwrap());
+    assert!(!db.remove_player(&cal.id, &10).await.unwrap());
+    assert!(db.remove_player(&cal.id, &11).await.unwrap());
+    assert_eq!(
+        db.character_by_id(&cal.id).await.unwrap(),
+        Some(cal.clone())
+    );
+
+    assert!(db.delete_character(&cal.id).await.unwrap());
+    assert!(!db.delete_character(&cal.id).await.unwrap());
+    assert!(db.character(&lost_mine.id, "Cal").await.unwrap().is_none());
+
+    // Deleting a campaign deletes its characters.
+    assert!(db.delete_campaign(&strahd.id).await.unwrap());
+    assert!(db.character_by_id(&other_cal.id).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn inventory_changes() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let (cal, _) = db.ensure_character(&lost_mine.id, "Cal").await.unwrap();
+    let owner = Owner::Character(cal.id);
+    let by = Contributor {
+        user_id: 10,
+        character: Some("Cal".to_owned()),
+    };
+    assert!(db.inventory(&owner).await.unwrap().is_empty());
+
+    let changed = db
+        .change_items(
+            &owner,
+            vec![
+                ("Gold".to_owned(), Change::Add(3000)),
+                ("Torch".to_owned(), Change::Add(2)),
+            ],
+            &by,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        changed[0],
+        Changed {
+            item: "Gold".to_owned(),
+            before: 0,
+            after: 3000
+        }
+    );
+
+    // Items are matched ignoring case, and keep the name they were first given.
+    let changed = db
+        .change_items(&owner, vec![("gold".to_owned(), Change::Add(5))], &by)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(changed[0].item, "Gold");
+    assert_eq!(changed[0].after, 3005);
+
+    // If any change in a batch fails, none are made.
+    let error = db
+        .change_items(
+            &owner,
+            vec![
+                ("Gold".to_owned(), Change::Remove(Some(5))),
+                ("Torch".to_owned(), Change::Remove(Some(3))),
+            ],
+            &by,
+        )
+        .await
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(
+        error,
+        ChangeError::NotEnough {
+            item: "Torch".to_owned(),
+            have: 2,
+            wanted: 3
+        }
+    );
+    assert_eq!(
+        db.inventory(&owner).await.unwrap(),
+        vec![
+            Stack {
+                item: "Gold".to_owned(),
+                quantity: 3005
+            },
+            Stack {
+                item: "Torch".to_owned(),
+                quantity: 2
+            },
+        ]
+    );
+
+    // Items that run out aren't listed.
+    let _ = db
+        .change_items(
+            &owner,
+            vec![
+                ("Torch".to_owned(), Change::Remove(None)),
+                ("Gold".to_owned(), Change::Set(0)),
+            ],
+            &by,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(db.inventory(&owner).await.unwrap().is_empty());
+
+    // Deleting a character deletes its inventory.
+    let _ = db
+        .change_items(&owner, vec![("Rope".to_owned(), Change::Add(1))], &by)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(db.delete_character(&cal.id).await.unwrap());
+    assert!(db.inventory(&owner).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn party_pot_ledger() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let (cal, _) = db.ensure_character(&lost_mine.id, "Cal").await.unwrap();
+    assert!(db.add_player(&cal.id, &10).await.unwrap());
+    assert_eq!(
+        db.characters_played(&lost_mine.id, &10).await.unwrap(),
+        vec![cal.clone()]
+    );
+    assert!(db
+        .characters_played(&lost_mine.id, &11)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let pot = Owner::Pot(lost_mine.id);
+    let cal_gives = Contributor {
+        user_id: 10,
+        character: Some("Cal".to_owned()),
+    };
+    let dm_takes = Contributor {
+        user_id: 20,
+        character: None,
+    };
+    let _ = db
+        .change_items(&pot, vec![("Gold".to_owned(), Change::Add(50))], &cal_gives)
+        .await
+        .unwrap()
+        .unwrap();
+    let _ = db
+        .change_items(&pot, vec![("Gold".to_owned(), Change::Set(30))], &dm_takes)
+        .await
+        .unwrap()
+        .unwrap();
+    // Failed batches leave no trace in the ledger.
+    let _ = db
+        .change_items(
+            &pot,
+            vec![("Gold".to_owned(), Change::Remove(Some(31)))],
+            &dm_takes,
+        )
+        .await
+        .unwrap()
+        .unwrap_err();
+    // The pot and a character's inventory are kept apart.
+    assert!(db
+        .inventory(&Owner::Character(cal.id))
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(db.inventory(&pot).await.unwrap()[0].quantity, 30);
+
+    let ledger = db.pot_ledger(&lost_mine.id, 10).await.unwrap();
+    assert_eq!(ledger.len(), 2);
+    assert_eq!(
+        (ledger[0].contributor.clone(), ledger[0].amount),
+        (dm_takes, -20)
+    );
+    assert_eq!(
+        (ledger[1].contributor.clone(), ledger[1].amount),
+        (cal_gives, 50)
+    );
+
+    // Entries outlive the characters that made them.
+    assert!(db.delete_character(&cal.id).await.unwrap());
+    assert_eq!(db.pot_ledger(&lost_mine.id, 10).await.unwrap().len(), 2);
+    assert_eq!(db.pot_ledger(&lost_mine.id, 1).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn transfers_are_all_or_nothing() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let (cal, _) = db.ensure_character(&lost_mine.id, "Cal").await.unwrap();
+    let (cal, pot) = (Owner::Character(cal.id), Owner::Pot(lost_mine.id));
+    let by = Contributor {
+        user_id: 10,
+        character: Some("Cal".to_owned()),
+    };
+    let _ = db
+        .change_items(
+            &cal,
+            vec![
+                ("Gold".to_owned(), Change::Add(80)),
+                ("Torch".to_owned(), Change::Add(1)),
+            ],
+            &by,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+    let moved = db
+        .transfer_items(&cal, &pot, vec![("gold".to_owned(), 50)], &by)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        moved,
+        vec![(
+            Changed {
+                item: "Gold".to_owned(),
+                before: 80,
+                after: 30
+            },
+            Changed {
+                item: "Gold".to_owned(),
+                before: 0,
+                after: 50
+            }
+        )]
+    );
+    let ledger = db.pot_ledger(&lost_mine.id, 10).await.unwrap();
+    assert_eq!(
+        (ledger[0].contributor.clone(), ledger[0].amount),
+        (by.clone(), 50)
+    );
+
+    // Running short of one item stops the others moving too.
+    let error = db
+        .transfer_items(
+            &cal,
+            &pot,
+            vec![("Gold".to_owned(), 30), ("Torch".to_owned(), 2)],
+            &by,
+        )
+        .await
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(
+        error,
+        ChangeError::NotEnough {
+            item: "Torch".to_owned(),
+            have: 1,
+            wanted: 2
+        }
+    );
+    assert_eq!(db.inventory(&cal).await.unwrap()[0].quantity, 30);
+    assert_eq!(db.inventory(&pot).await.unwrap()[0].quantity, 50);
+    assert_eq!(db.pot_ledger(&lost_mine.id, 10).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn character_info_posts() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let (cal, _) = db.ensure_character(&lost_mine.id, "Cal").await.unwrap();
+    let info = |tag: &str, value: &str| Info {
+        tag: tag.to_owned(),
+        value: value.to_owned(),
+    };
+
+    db.set_info(
+        &cal.id,
+        vec![
+            info("Name", "Calmasis"),
+            info("Alignment", "LN"),
+            info("Notes", ""),
+        ],
+        &7,
+    )
+    .await
+    .unwrap();
+    // Changed tags keep their place and their name. Posts start out with the
+    // character's level.
+    db.set_info(
+        &cal.id,
+        vec![
+            info("name", "Calmasis M. Bluthersworth"),
+            info("Race", "Kenku"),
+        ],
+        &7,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        db.character_info(&cal.id).await.unwrap(),
+        vec![
+            info("Level", "1"),
+            info("Name", "Calmasis M. Bluthersworth"),
+            info("Alignment", "LN"),
+            info("Notes", ""),
+            info("Race", "Kenku"),
+        ]
+    );
+
+    let removed = db
+        .remove_info(
+            &cal.id,
+            vec!["ALIGNMENT".to_owned(), "Height".to_owned()],
+            &7,
+        )
+        .await
+        .unwrap();
+    assert_eq!(removed, vec!["Alignment".to_owned()]);
+    assert_eq!(db.character_info(&cal.id).await.unwrap().len(), 4);
+
+    // Deleting a character deletes their post.
+    assert!(db.delete_character(&cal.id).await.unwrap());
+    assert!(db.character_info(&cal.id).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn level_tables() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let (strahd, _) = db.ensure_campaign(&1, "Curse of Strahd").await.unwrap();
+
+    // Campaigns start with the D&D 3.5e table, however they were started.
+    let levels = db.levels(&lost_mine.id).await.unwrap();
+    assert_eq!(levels.len(), 20);
+    assert_eq!(levels[0], Threshold { level: 1, xp: 0 });
+    assert_eq!(
+        levels[19],
+        Threshold {
+            level: 20,
+            xp: 190_000
+        }
+    );
+    assert_eq!(db.levels(&strahd.id).await.unwrap(), levels);
+
+    // Each level must take more XP than the one before it, and less than the
+    // one after.
+    let set = |level, xp| db.set_level(&lost_mine.id, Threshold { level, xp }, &7);
+    assert!(set(2, 1500).await.unwrap().is_ok());
+    assert!(set(2, 3000).await.unwrap().is_err());
+    assert!(set(3, 1500).await.unwrap().is_err());
+    assert!(set(21, 190_000).await.unwrap().is_err());
+    assert!(set(21, 210_000).await.unwrap().is_ok());
+    assert_eq!(db.levels(&lost_mine.id).await.unwrap()[1].xp, 1500);
+    assert_eq!(db.levels(&strahd.id).await.unwrap()[1].xp, 1000);
+
+    assert!(db.remove_level(&lost_mine.id, &21, &7).await.unwrap());
+    assert!(!db.remove_level(&lost_mine.id, &21, &7).await.unwrap());
+    assert_eq!(db.levels(&lost_mine.id).await.unwrap().len(), 20);
+}
+
+/// The level shown in a character's info post.
+async fn level_tag(db: &MageDB, character_id: i64) -> String {
+    let info = db.character_info(&character_id).await.unwrap();
+    info.into_iter().find(|i| i.tag == "Level").unwrap().value
+}
+
+#[tokio::test]
+async fn new_characters_show_their_level() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let (cal, created) = db.ensure_character(&lost_mine.id, "Cal").await.unwrap();
+    assert!(created);
+    assert_eq!(level_tag(&db, cal.id).await, "1");
+
+    // That isn't a change anyone made.
+    assert!(db
+        .history(&lost_mine.id, None, 0, 10)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn setting_levels_updates_posts() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let (cal, _) = db.ensure_character(&lost_mine.id, "Cal").await.unwrap();
+    let (tam, _) = db.ensure_character(&lost_mine.id, "Tam").await.unwrap();
+    let _ = db
+        .change_xp(&cal.id, XpChange::Set(3500), &7)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(level_tag(&db, cal.id).await, "3");
+
+    // Everyone whose level changes is updated, as one change.
+    db.set_level(&lost_mine.id, Threshold { level: 3, xp: 4000 }, &8)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(level_tag(&db, cal.id).await, "2");
+    assert_eq!(level_tag(&db, tam.id).await, "1");
+    let event = db
+        .history(&lost_mine.id, None, 0, 1)
+        .await
+        .unwrap()
+        .remove(0);
+    assert_eq!(event.user_id, 8);
+    assert_eq!(
+        event.edits,
+        [
+            Edit {
+                subject: "Level 3".to_owned(),
+                target: Target::Level {
+                    campaign_id: lost_mine.id,
+                    level: 3
+                },
+                before: Some("3000".to_owned()),
+                after: Some("4000".to_owned()),
+            },
+            Edit {
+                subject: "Cal".to_owned(),
+                target: Target::Info {
+                    character_id: cal.id,
+                    tag: "Level".to_owned()
+                },
+                before: Some("3".to_owned()),
+                after: Some("2".to_owned()),
+            }
+        ]
+    );
+
+    // Levels that can't be set don't change anyone's.
+    assert!(db
+        .set_level(&lost_mine.id, Threshold { level: 2, xp: 5000 }, &8)
+        .await
+        .unwrap()
+        .is_err());
+    assert_eq!(level_tag(&db, cal.id).await, "2");
+}
+
+#[tokio::test]
+async fn removing_levels_updates_posts() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let (cal, _) = db.ensure_character(&lost_mine.id, "Cal").await.unwrap();
+    let (tam, _) = db.ensure_character(&lost_mine.id, "Tam").await.unwrap();
+    let _ = db
+        .change_xp(&cal.id, XpChange::Set(1500), &7)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(level_tag(&db, cal.id).await, "2");
+
+    assert!(db.remove_level(&lost_mine.id, &2, &8).await.unwrap());
+    assert_eq!(level_tag(&db, cal.id).await, "1");
+    assert_eq!(level_tag(&db, tam.id).await, "1");
+    let event = db
+        .history(&lost_mine.id, None, 0, 1)
+        .await
+        .unwrap()
+        .remove(0);
+    assert_eq!(event.edits.len(), 2);
+    assert_eq!(event.edits[0].before.as_deref(), Some("1000"));
+    assert_eq!(event.edits[0].after, None);
+    assert_eq!(event.edits[1].after.as_deref(), Some("1"));
+}
+
+#[tokio::test]
+async fn undoing_level_changes() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let (cal, _) = db.ensure_character(&lost_mine.id, "Cal").await.unwrap();
+    let _ = db
+        .change_xp(&cal.id, XpChange::Set(3500), &7)
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The table and the posts go back together, and come back together.
+    db.set_level(&lost_mine.id, Threshold { level: 3, xp: 4000 }, &8)
+        .await
+        .unwrap()
+        .unwrap();
+    let set = db.history(&lost_mine.id, None, 0, 1).await.unwrap()[0].id;
+    let undo = db.undo(&lost_mine.id, &set, &8).await.unwrap().unwrap();
+    assert_eq!(db.levels(&lost_mine.id).await.unwrap()[2].xp, 3000);
+    assert_eq!(level_tag(&db, cal.id).await, "3");
+    let _ = db.undo(&lost_mine.id, &undo.id, &8).await.unwrap().unwrap();
+    assert_eq!(db.levels(&lost_mine.id).await.unwrap()[2].xp, 4000);
+    assert_eq!(level_tag(&db, cal.id).await, "2");
+
+    // Removed levels are put back, unless they'd now be out of order.
+    assert!(db.remove_level(&lost_mine.id, &2, &8).await.unwrap());
+    let removed = db.history(&lost_mine.id, None, 0, 1).await.unwrap()[0].id;
+    let _ = db.undo(&lost_mine.id, &removed, &8).await.unwrap().unwrap();
+    assert_eq!(db.levels(&lost_mine.id).await.unwrap()[1].xp, 1000);
+    assert!(db.remove_level(&lost_mine.id, &2, &8).await.unwrap());
+    let removed = db.history(&lost_mine.id, None, 0, 1).await.unwrap()[0].id;
+    db.set_level(&lost_mine.id, Threshold { level: 3, xp: 900 }, &8)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(db.undo(&lost_mine.id, &removed, &8).await.unwrap().is_err());
+    assert_eq!(db.levels(&lost_mine.id).await.unwrap()[1].level, 3);
+}
+
+#[tokio::test]
+async fn experience_changes() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let (cal, _) = db.ensure_character(&lost_mine.id, "Cal").await.unwrap();
+    let experience = db.experience(&cal.id).await.unwrap().unwrap();
+    assert_eq!((experience.xp, experience.multiplier), (0, 1.0));
+
+    // Multipliers only apply to XP that's added.
+    db.set_xp_multiplier(&cal.id, 1.02, &7).await.unwrap();
+    assert_eq!(
+        db.change_xp(&cal.id, XpChange::Add(100), &7).await.unwrap(),
+        Ok((0, 102))
+    );
+    assert_eq!(
+        db.change_xp(&cal.id, XpChange::Remove(2), &7)
+            .await
+            .unwrap(),
+        Ok((102, 100))
+    );
+    assert!(db
+        .change_xp(&cal.id, XpChange::Remove(101), &7)
+        .await
+        .unwrap()
+        .is_err());
+    assert_eq!(
+        db.change_xp(&cal.id, XpChange::Set(5000), &7)
+            .await
+            .unwrap(),
+        Ok((100, 5000))
+    );
+    let experience = db.experience(&cal.id).await.unwrap().unwrap();
+    assert_eq!((experience.xp, experience.multiplier), (5000, 1.02));
+}
+
+#[tokio::test]
+async fn quest_logs() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let other = db.create_campaign(&1, "Other").await.unwrap().unwrap();
+
+    // Quests are numbered per campaign.
+    let rescue = db
+        .add_quest(&lost_mine.id, "Rescue Gundren", &7)
+        .await
+        .unwrap();
+    let deliver = db
+        .add_quest(&lost_mine.id, "Deliver the wagon", &7)
+        .await
+        .unwrap();
+    let elsewhere = db.add_quest(&other.id, "Elsewhere", &8).await.unwrap();
+    assert_eq!((rescue.number, deliver.number, elsewhere.number), (1, 2, 1));
+    assert_eq!((rescue.status, rescue.added_by), (Status::Active, 7));
+
+    db.edit_quest(
+        &rescue.id,
+        Field::Giver,
+        Some("Sildar Hallwinter".to_owned()),
+        &7,
+    )
+    .await
+    .unwrap();
+    db.set_quest_status(&deliver.id, Status::Completed, &7)
+        .await
+        .unwrap();
+    let active = QuestFilter {
+        status: Some(Status::Active),
+        giver: None,
+    };
+    let titles = |quests: Vec<Quest>| quests.into_iter().map(|q| q.title).collect::<Vec<_>>();
+    assert_eq!(
+        titles(db.quests(&lost_mine.id, &active).await.unwrap()),
+        ["Rescue Gundren"]
+    );
+    let from_sildar = QuestFilter {
+        status: None,
+        giver: Some("sildar".to_owned()),
+    };
+    assert_eq!(
+        titles(db.quests(&lost_mine.id, &from_sildar).await.unwrap()),
+        ["Rescue Gundren"]
+    );
+    assert_eq!(
+        titles(
+            db.quests(&lost_mine.id, &QuestFilter::default())
+                .await
+                .unwrap()
+        ),
+        ["Rescue Gundren", "Deliver the wagon"]
+    );
+
+    // Objectives keep their order, and a batch that's too long adds nothing.
+    let objectives = vec!["Find the cave".to_owned(), "Free Gundren".to_owned()];
+    db.add_objectives(&rescue.id, objectives, &7)
+        .await
+        .unwrap()
+        .unwrap();
+    let too_many = vec!["Again".to_owned(); MAX_OBJECTIVES - 1];
+    assert!(db
+        .add_objectives(&rescue.id, too_many, &7)
+        .await
+        .unwrap()
+        .is_err());
+    let checked = db
+        .set_objective_done(&rescue.id, 2, true, &7)
+        .await
+        .unwrap();
+    assert_eq!(checked.map(|o| o.text).as_deref(), Some("Free Gundren"));
+    assert_eq!(
+        db.set_objective_done(&rescue.id, 3, true, &7)
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        db.remove_objective(&rescue.id, 1, &7)
+            .await
+            .unwrap()
+            .as_deref(),
+        Some("Find the cave")
+    );
+    let quest = db.quest(&lost_mine.id, &1).await.unwrap().unwrap();
+    assert_eq!(quest.giver.as_deref(), Some("Sildar Hallwinter"));
+    assert_eq!(
+        quest.objectives,
+        [Objective {
+            text: "Free Gundren".to_owned(),
+            done: true
+        }]
+    );
+    assert_eq!(db.quest(&lost_mine.id, &3).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn feature_toggles() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let other = db.create_campaign(&1, "Other").await.unwrap().unwrap();
+
+    // Features are on until turned off, and only in the campaign that did.
+    assert!(db
+        .is_feature_enabled(&lost_mine.id, "quests")
+        .await
+        .unwrap());
+    assert!(db
+        .set_feature_enabled(&lost_mine.id, "quests", false, &7)
+        .await
+        .unwrap());
+    assert!(!db
+        .set_feature_enabled(&lost_mine.id, "Quests", false, &7)
+        .await
+        .unwrap());
+    assert!(!db
+        .is_feature_enabled(&lost_mine.id, "QUESTS")
+        .await
+        .unwrap());
+    assert!(db.is_feature_enabled(&other.id, "quests").await.unwrap());
+    assert_eq!(
+        db.disabled_features(&lost_mine.id).await.unwrap(),
+        ["quests"]
+    );
+
+    assert!(db
+        .set_feature_enabled(&lost_mine.id, "quests", true, &7)
+        .await
+        .unwrap());
+    assert!(!db
+        .set_feature_enabled(&lost_mine.id, "quests", true, &7)
+        .await
+        .unwrap());
+    assert!(db
+        .is_feature_enabled(&lost_mine.id, "quests")
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn monitors() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let other = db.create_campaign(&1, "Other").await.unwrap().unwrap();
+
+    assert_eq!(db.monitor(&lost_mine.id, Kind::Quests).await.unwrap(), None);
+    assert_eq!(
+        db.set_monitor(&lost_mine.id, Kind::Quests, &10, &7)
+            .await
+            .unwrap(),
+        None
+    );
+    db.set_monitor_messages(&lost_mine.id, Kind::Quests, &10, vec![101, 100])
+        .await
+        .unwrap();
+    let quests = Monitor {
+        channel_id: 10,
+        message_ids: vec![101, 100],
+    };
+    assert_eq!(
+        db.monitor(&lost_mine.id, Kind::Quests).await.unwrap(),
+        Some(quests.clone())
+    );
+    assert_eq!(
+        db.monitor(&lost_mine.id, Kind::Inventory).await.unwrap(),
+        None
+    );
+    assert_eq!(db.monitor(&other.id, Kind::Quests).await.unwrap(), None);
+
+    // Monitoring in the same channel again keeps the messages.
+    assert_eq!(
+        db.set_monitor(&lost_mine.id, Kind::Quests, &10, &7)
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        db.monitor(&lost_mine.id, Kind::Quests).await.unwrap(),
+        Some(quests.clone())
+    );
+
+    // Moving it hands back the old messages, and drawing it in the old
+    // channel afterwards is ignored.
+    assert_eq!(
+        db.set_monitor(&lost_mine.id, Kind::Quests, &11, &7)
+            .await
+            .unwrap(),
+        Some(quests)
+    );
+    db.set_monitor_messages(&lost_mine.id, Kind::Quests, &10, vec![102])
+        .await
+        .unwrap();
+    let moved = Monitor {
+        channel_id: 11,
+        message_ids: vec![],
+    };
+    assert_eq!(
+        db.monitor(&lost_mine.id, Kind::Quests).await.unwrap(),
+        Some(moved.clone())
+    );
+
+    assert_eq!(
+        db.remove_monitor(&lost_mine.id, Kind::Quests)
+            .await
+            .unwrap(),
+        Some(moved)
+    );
+    assert_eq!(
+        db.remove_monitor(&lost_mine.id, Kind::Quests)
+            .await
+            .unwrap(),
+        None
+    );
+    db.set_monitor_messages(&lost_mine.id, Kind::Quests, &11, vec![103])
+        .await
+        .unwrap();
+    assert_eq!(db.monitor(&lost_mine.id, Kind::Quests).await.unwrap(), None);
+
+    // Deleting the campaign deletes its monitors.
+    assert_eq!(
+        db.set_monitor(&other.id, Kind::Characters, &12, &7)
+            .await
+            .unwrap(),
+        None
+    );
+    db.set_monitor_messages(&other.id, Kind::Characters, &12, vec![104])
+        .await
+        .unwrap();
+    assert!(db.delete_campaign(&other.id).await.unwrap());
+    assert_eq!(db.monitor(&other.id, Kind::Characters).await.unwrap(), None);
+    let conn = db.conn.lock().unwrap();
+    let messages: i64 = conn
+        .query_row("SELECT COUNT(*) FROM monitor_messages", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(messages, 0);
+}
+
+#[tokio::test]
+async fn history_and_undo() {
+    let (_dir, db) = temp_db();
+    let lost_mine = db.create_campaign(&1, "Lost Mine").await.unwrap().unwrap();
+    let other = db.create_campaign(&1, "Other").await.unwrap().unwrap();
+    let (cal, _) = db.ensure_character(&lost_mine.id, "Cal").await.unwrap();
+    let owner = Owner::Character(cal.id);
+    let by = Contributor {
+        user_id: 7,
+        character: Some("Cal".to_owned()),
+    };
+    let gold = |change| vec![("Gold".to_owned(), change)];
+    let latest = || async {
+        db.history(&lost_mine.id, None, 0, 1)
+            .await
+            .unwrap()
+            .remove(0)
+    };
+
+    // Every change is recorded, newest first, unless it changed nothing.
+    let _ = db
+        .change_items(&owner, gold(Change::Add(3000)), &by)
+        .await
+        .unwrap()
+        .unwrap();
+    let _ = db
+        .change_items(&owner, gold(Change::Set(0)), &by)
+        .await
+        .unwrap()
+        .unwrap();
+    let _ = db
+        .change_items(&owner, gold(Change::Set(0)), &by)
+        .await
+        .unwrap()
+        .unwrap();
+    let events = db.history(&lost_mine.id, None, 0, 10).await.unwrap();
+    assert_eq!(events.len(), 2);
+    let wipe = &events[0];
+    assert_eq!((wipe.user_id, wipe.undoes, wipe.undone_by), (7, None, None));
+    assert_eq!(
+        wipe.edits,
+        [Edit {
+            subject: "Cal".to_owned(),
+            target: Target::Item {
+                owner,
+                item: "Gold".to_owned()
+            },
+            before: Some("3000".to_owned()),
+            after: Some("0".to_owned()),
+        }]
+    );
+
+    // Undoing puts things back, once, and only in the event's campaign.
+    assert!(db.undo(&other.id, &wipe.id, &8).await.unwrap().is_err());
+    let undo = db.undo(&lost_mine.id, &wipe.id, &8).await.unwrap().unwrap();
+    assert_eq!((undo.user_id, undo.undoes), (8, Some(wipe.id)));
+    assert_eq!(undo.edits, [wipe.edits[0].reverse()]);
+    let stacks = db.inventory(&owner).await.unwrap();
+    assert_eq!(
+        stacks.iter().map(|s| s.quantity).collect::<Vec<_>>(),
+        [3000]
+    );
+    assert!(db.undo(&lost_mine.id, &wipe.id, &8).await.unwrap().is_err());
+    let wipe = db
+        .history(&lost_mine.id, None, 1, 1)
+        .await
+        .unwrap()
+        .remove(0);
+    assert_eq!(wipe.undone_by, Some(undo.id));
+
+    // Changes made since get in the way, until they're undone too.
+    let added = events[1].id;
+    let _ = db
+        .change_items(&owner, gold(Change::Add(5)), &by)
+        .await
+        .unwrap()
+        .unwrap();
+    let since = latest().await.id;
+    assert!(db.undo(&lost_mine.id, &added, &8).await.unwrap().is_err());
+    let _ = db.undo(&lost_mine.id, &since, &8).await.unwrap().unwrap();
+    let _ = db.undo(&lost_mine.id, &added, &8).await.unwrap().unwrap();
+    assert!(db.inventory(&owner).await.unwrap().is_empty());
+
+    // Undoing an undo redoes what it undid.
+    let _ = db
+        .undo(&lost_mine.id, &undo.id, &8)
+        .await
+        .unwrap()
+        .unwrap_err();
+    let _ = db
+        .change_items(&owner, gold(Change::Add(3000)), &by)
+        .await
+        .unwrap()
+        .unwrap();
+    let add = latest().await.id;
+    let undo = db.undo(&lost_mine.id, &add, &8).await.unwrap().unwrap();
+    let _ = db.undo(&lost_mine.id, &undo.id, &8).await.unwrap().unwrap();
+    assert_eq!(db.inventory(&owner).await.unwrap().len(), 1);
+    let add = db
+        .history(&lost_mine.id, None, 2, 1)
+        .await
+        .unwrap()
+        .remove(0);
+    assert_eq!(add.undone_by, None);
+
+    // Moving items is one event, and can be filtered by whose it touched.
+    let pot = Owner::Pot(lost_mine.id);
+    let _ = db
+        .transfer_items(&owner, &pot, vec![("gold".to_owned(), 50)], &by)
+        .await
+        .unwrap()
+        .unwrap();
+    let to_pot = db.history(&lost_mine.id, Some(pot), 0, 10).await.unwrap();
+    assert_eq!(to_pot.len(), 1);
+    assert_eq!(to_pot[0].edits.len(), 2);
+    let _ = db
+        .undo(&lost_mine.id, &to_pot[0].id, &8)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(db.inventory(&pot).await.unwrap().is_empty());
+
+    // XP takes the level in the info post along with it.
+    let _ = db
+        .change_xp(&cal.id, XpChange::Set(1000), &7)
+        .await
+        .unwrap()
+        .unwrap();
+    let level = |value: &str| Info {
+        tag: "Level".to_owned(),
+        value: value.to_owned(),
+    };
+    assert_eq!(db.character_info(&cal.id).await.unwrap(), [level("2")]);
+    let gained = latest().await;
+    assert_eq!(gained.edits.len(), 2);
+    let _ = db
+        .undo(&lost_mine.id, &gained.id, &8)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(db.experience(&cal.id).await.unwrap().unwrap().xp, 0);
+    assert_eq!(db.character_info(&cal.id).await.unwrap(), [level("1")]);
+
+    // So are multipliers.
+    db.set_xp_multiplier(&cal.id, 1.5, &8).await.unwrap();
+    let multiplied = latest().await;
+    assert_eq!(
+        multiplied.edits,
+        [Edit {
+            subject: "Cal".to_owned(),
+            target: Target::Multiplier {
+                character_id: cal.id
+            },
+            before: Some("1".to_owned()),
+            after: Some("1.5".to_owned()),
+        }]
+    );
+    let _ = db
+        .undo(&lost_mine.id, &multiplied.id, &8)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        db.experience(&cal.id).await.unwrap().unwrap().multiplier,
+        1.0
+    );
+
+    // Quests can be taken away while nothing's been added to them, and put
+    // back under the same number.
+    let rescue = db
+        .add_quest(&lost_mine.id, "Rescue Gundren", &7)
+        .await
+        .unwrap();
+    let added = latest().await.id;
+    let objectives = vec!["Find the cave".to_owned(), "Free Gundren".to_owned()];
+    db.add_objectives(&rescue.id, objectives, &7)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(db.undo(&lost_mine.id, &added, &8).await.unwrap().is_err());
+    let _ = db
+        .remove_objective(&rescue.id, 1, &7)
+        .await
+        .unwrap()
+        .unwrap();
+    let removed = latest().await.id;
+    let _ = db.undo(&lost_mine.id, &removed, &8).await.unwrap().unwrap();
+    let quest = db.quest(&lost_mine.id, &1).await.unwrap().unwrap();
+    assert_eq!(
+        quest
+            .objectives
+            .iter()
+            .map(|o| o.text.as_str())
+            .collect::<Vec<_>>(),
+        ["Find the cave", "Free Gundren"]
+    );
+
+    let deliver = db
+        .add_quest(&lost_mine.id, "Deliver the wagon", &7)
+        .await
+        .unwrap();
+    let added = latest().await.id;
+    let undo = db.undo(&lost_mine.id, &added, &8).await.unwrap().unwrap();
+    assert_eq!(
+        db.quest(&lost_mine.id, &deliver.number).await.unwrap(),
+        None
+    );
+    let _ = db.undo(&lost_mine.id, &undo.id, &8).await.unwrap().unwrap();
+    let back = db
+        .quest(&lost_mine.id, &deliver.number)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!((back.id, back.title), (deliver.id, deliver.title));
+
+    // Edits outlive the characters they were about, but can't be undone.
+    let wiped = db
+        .history(&lost_mine.id, Some(owner), 0, 100)
+        .await
+        .unwrap();
+    assert!(db.delete_character(&cal.id).await.unwrap());
+    assert!(db
+        .undo(&lost_mine.id, &wiped[0].id, &8)
+        .await
+        .unwrap()
+        .is_err());
+}
@@ -1,5 +0,0 @@
-use tracing::{event, Level};
-
-impl super::MageDB {
-    pub async fn get_user(&self, guild_id: &u64, user_id: &u64) -> 
-}
\ No newline at end of file
@@ -0,0 +1,239 @@
+use eyre::Result;
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction};
+
+use crate::model::history::{Edit, Target};
+use crate::model::xp::{self, check_threshold, Experience, Threshold, XpChange, LEVEL_TAG};
+
+use super::history::{character_subject, record};
+use super::info::set_tag;
+
+fn experience(row: &Row) -> rusqlite::Result<Experience> {
+    Ok(Experience {
+        xp: row.get(0)?,
+        multiplier: row.get(1)?,
+    })
+}
+
+pub(super) fn thresholds(conn: &Connection, campaign_id: i64) -> rusqlite::Result<Vec<Threshold>> {
+    let mut statement = conn
+        .prepare("SELECT level, xp FROM campaign_levels WHERE campaign_id = ?1 ORDER BY level")?;
+    let thresholds = statement
+        .query_map(params![campaign_id], |row| {
+            Ok(Threshold {
+                level: row.get(0)?,
+                xp: row.get(1)?,
+            })
+        })?
+        .collect();
+    thresholds
+}
+
+/// Bring the level in characters' info posts up to date with their XP and
+/// their campaign's table: every character's in the campaign, or only one's.
+/// Returns the edits this made, to be recorded with whatever called for them.
+pub(super) fn sync_levels(
+    transaction: &Transaction,
+    campaign_id: i64,
+    only: Option<i64>,
+) -> rusqlite::Result<Vec<Edit>> {
+    let levels = thresholds(transaction, campaign_id)?;
+    let characters: Vec<(i64, String, i64, Option<String>)> = transaction
+        .prepare(
+            "SELECT c.id, c.name, c.xp, i.value FROM characters c
+             LEFT JOIN character_info i ON i.character_id = c.id AND i.tag = ?2
+             WHERE c.campaign_id = ?1 AND (?3 IS NULL OR c.id = ?3)
+             ORDER BY c.id",
+        )?
+        .query_map(params![campaign_id, LEVEL_TAG, only], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut edits = Vec::new();
+    for (character_id, subject, xp, shown) in characters {
+        let level = xp::level(&levels, xp).to_string();
+        if shown.as_deref() == Some(level.as_str()) {
+            continue;
+        }
+        let (tag, before) = set_tag(transaction, character_id, LEVEL_TAG, Some(&level))?;
+        edits.push(Edit {
+            subject,
+            target: Target::Info { character_id, tag },
+            before,
+            after: Some(level),
+        });
+    }
+    Ok(edits)
+}
+
+impl super::MageDB {
+    /// How much XP each level of a campaign takes, in order of level.
+    pub async fn levels(&self, campaign_id: &i64) -> Result<Vec<Threshold>> {
+        let campaign_id = *campaign_id;
+        self.with(move |conn| thresholds(conn, campaign_id)).await
+    }
+
+    /// Set how much XP a level takes, updating the level in the info posts of
+    /// the campaign's characters to match. If that would make it take no more
+    /// than a lower level, or no less than a higher one, nothing changes and
+    /// the reason is returned.
+    pub async fn set_level(
+        &self,
+        campaign_id: &i64,
+        threshold: Threshold,
+        user_id: &u64,
+    ) -> Result<Result<(), String>> {
+        let (campaign_id, user_id) = (*campaign_id, *user_id);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let levels = thresholds(&transaction, campaign_id)?;
+            if let Err(e) = check_threshold(&levels, threshold) {
+                return Ok(Err(e));
+            }
+            let before = levels.iter().find(|t| t.level == threshold.level);
+            let mut edits = vec![Edit {
+                subject: format!("Level {}", threshold.level),
+                target: Target::Level {
+                    campaign_id,
+                    level: threshold.level,
+                },
+                before: before.map(|t| t.xp.to_string()),
+                after: Some(threshold.xp.to_string()),
+            }];
+            let _ = transaction.execute(
+                "INSERT INTO campaign_levels (campaign_id, level, xp) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (campaign_id, level) DO UPDATE SET xp = excluded.xp",
+                params![campaign_id, threshold.level, threshold.xp],
+            )?;
+            edits.extend(sync_levels(&transaction, campaign_id, None)?);
+            record(&transaction, campaign_id, user_id, edits)?;
+            transaction.commit()?;
+            Ok(Ok(()))
+        })
+        .await
+    }
+
+    /// Remove a level from a campaign's table, updating the level in the info
+    /// posts of the campaign's characters to match. Returns false if it
+    /// wasn't there.
+    pub async fn remove_level(
+        &self,
+        campaign_id: &i64,
+        level: &i64,
+        user_id: &u64,
+    ) -> Result<bool> {
+        let (campaign_id, level, user_id) = (*campaign_id, *level, *user_id);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let Some(xp) = transaction
+                .query_row(
+                    "DELETE FROM campaign_levels WHERE campaign_id = ?1 AND level = ?2
+                     RETURNING xp",
+                    params![campaign_id, level],
+                    |row| row.get::<_, i64>(0),
+                )
+                .optional()?
+            else {
+                return Ok(false);
+            };
+            let mut edits = vec![Edit {
+                subject: format!("Level {level}"),
+                target: Target::Level { campaign_id, level },
+                before: Some(xp.to_string()),
+                after: None,
+            }];
+            edits.extend(sync_levels(&transaction, campaign_id, None)?);
+            record(&transaction, campaign_id, user_id, edits)?;
+            transaction.commit()?;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// A character's XP and multiplier.
+    pub async fn experience(&self, character_id: &i64) -> Result<Option<Experience>> {
+        let character_id = *character_id;
+        self.with(move |conn| {
+            conn.query_row(
+                "SELECT xp, xp_multiplier FROM characters WHERE id = ?1",
+                params![character_id],
+                experience,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    /// Change a character's XP, and the level in their info post if it
+    /// changes too. Returns their XP before and after, or if the change can't
+    /// be made, why not.
+    pub async fn change_xp(
+        &self,
+        character_id: &i64,
+        change: XpChange,
+        user_id: &u64,
+    ) -> Result<Result<(i64, i64), String>> {
+        let (character_id, user_id) = (*character_id, *user_id);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let before = transaction.query_row(
+                "SELECT xp, xp_multiplier FROM characters WHERE id = ?1",
+                params![character_id],
+                experience,
+            )?;
+            let after = match change.apply(before) {
+                Ok(after) => after,
+                Err(e) => return Ok(Err(e)),
+            };
+            let _ = transaction.execute(
+                "UPDATE characters SET xp = ?2 WHERE id = ?1",
+                params![character_id, after],
+            )?;
+
+            let (campaign_id, subject) = character_subject(&transaction, character_id)?;
+            let mut edits = vec![Edit {
+                subject,
+                target: Target::Xp { character_id },
+                before: Some(before.xp.to_string()),
+                after: Some(after.to_string()),
+            }];
+            edits.extend(sync_levels(&transaction, campaign_id, Some(character_id))?);
+            record(&transaction, campaign_id, user_id, edits)?;
+            transaction.commit()?;
+            Ok(Ok((before.xp, after)))
+        })
+        .await
+    }
+
+    /// Set what XP given to a character is multiplied by.
+    pub async fn set_xp_multiplier(
+        &self,
+        character_id: &i64,
+        multiplier: f64,
+        user_id: &u64,
+    ) -> Result<()> {
+        let (character_id, user_id) = (*character_id, *user_id);
+        self.with(move |conn| {
+            let transaction = conn.transaction()?;
+            let before: f64 = transaction.query_row(
+                "SELECT xp_multiplier FROM characters WHERE id = ?1",
+                params![character_id],
+                |row| row.get(0),
+            )?;
+            let _ = transaction.execute(
+                "UPDATE characters SET xp_multiplier = ?2 WHERE id = ?1",
+                params![character_id, multiplier],
+            )?;
+            let (campaign_id, subject) = character_subject(&transaction, character_id)?;
+            let edit = Edit {
+                subject,
+                target: Target::Multiplier { character_id },
+                before: Some(before.to_string()),
+                after: Some(multiplier.to_string()),
+            };
+            record(&transaction, campaign_id, user_id, vec![edit])?;
+            transaction.commit()
+        })
+        .await
+    }
+}
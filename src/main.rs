@@ -35,39 +35,55 @@
     unused_variables
 )]
 
-use serde::Deserialize;
-use std::fs;
+use config::{Cli, Config};
+use database::MageDB;
+use std::env;
 use tracing::{event, Level};
 
 mod archmage;
 use archmage::Archmage;
 mod command;
-
-#[derive(Deserialize, Debug, Clone)]
-struct Config {
-    #[serde(alias = "allowed-guilds")]
-    pub allowed_guilds: Vec<u64>,
-
-    #[serde(alias = "app-id")]
-    pub appid: u64,
-
-    #[serde(alias = "secret")]
-    pub token: String,
-}
+mod config;
+mod database;
+mod model;
+mod shutdown;
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     tracing_subscriber::fmt::init();
 
-    let config = toml::from_str(
-        &fs::read_to_string("secret/config.toml").expect("secret/config.toml not present"),
-    )
-    .expect("Invalid Configuration");
+    let cli = Cli::parse(env::args().skip(1))?;
+    if cli.help {
+        println!("{}", Cli::usage());
+        return Ok(());
+    }
+
+    let config = Config::load(cli.config.as_deref())?;
+    if cli.check_config {
+        println!("Configuration OK");
+        return Ok(());
+    }
+    let db = MageDB::open(&config.database)?;
 
     event!(Level::INFO, "Strike the Earth!");
 
-    Archmage::new(config)
-        .with_commands::<(command::ping::PingCommand, command::roll::RollCommand)>()
+    Archmage::new(config, db)
+        .with_config_path(cli.config)
+        .with_commands::<(
+            command::admin::AdminCommand,
+            command::campaign::CampaignCommand,
+            command::character::CharCommand,
+            command::config::ConfigCommand,
+            command::help::HelpCommand,
+            command::history::HistoryCommand,
+            command::inv::give::GiveCommand,
+            command::inv::InvCommand,
+            command::inv::pot::PotCommand,
+            command::ping::PingCommand,
+            command::quest::QuestCommand,
+            command::roll::RollCommand,
+            command::xp::XpCommand,
+        )>()
         .start()
         .await
 }
@@ -0,0 +1,31 @@
+//! Campaigns, the games a server runs. Everything Archmage keeps about a game
+//! (its DMs, characters, inventories and so on) belongs to a campaign.
+
+/// Campaign names longer than this are more likely mistakes than names.
+pub const MAX_NAME_LENGTH: usize = 64;
+
+/// A campaign run in a server. Names are unique within a server, ignoring
+/// case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Campaign {
+    pub id: i64,
+    pub guild_id: u64,
+    pub name: String,
+}
+
+impl Campaign {
+    /// Tidy up a proposed campaign name, collapsing runs of whitespace, or
+    /// explain why it can't be used.
+    pub fn clean_name(name: &str) -> Result<String, String> {
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        if name.is_empty() {
+            return Err("Campaigns need a name.".to_owned());
+        }
+        if name.chars().count() > MAX_NAME_LENGTH {
+            return Err(format!(
+                "Campaign names may be at most {MAX_NAME_LENGTH} characters long."
+            ));
+        }
+        Ok(name)
+    }
+}
@@ -0,0 +1,48 @@
+//! Player characters. Each belongs to a campaign, and may be played by any
+//! number of users.
+
+/// Character IDs are meant to be quick to type, so are kept short.
+pub const MAX_NAME_LENGTH: usize = 32;
+/// The ID that stands for the party pot wherever a character is expected, as
+/// in `!inv pot`.
+pub const POT: &str = "pot";
+
+/// A character in a campaign, known by a short ID such as `Cal` rather than
+/// their full name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Character {
+    pub id: i64,
+    pub campaign_id: i64,
+    /// The short ID players use. Unique within the campaign, ignoring case.
+    pub name: String,
+}
+
+impl Character {
+    /// Check a proposed character ID, explaining why it can't be used if so.
+    /// IDs are single words so that they can be typed in chat commands
+    /// without quoting, and can't contain the characters used to separate
+    /// and quote items in batches.
+    pub fn check_name(name: &str) -> Result<&str, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Characters need an ID.".to_owned());
+        }
+        if name.chars().count() > MAX_NAME_LENGTH {
+            return Err(format!(
+                "Character IDs may be at most {MAX_NAME_LENGTH} characters long."
+            ));
+        }
+        if name.contains(|c: char| c.is_whitespace() || c == ';' || c == '"') {
+            return Err(
+                "Character IDs must be a single word, without semicolons or quotes.".to_owned(),
+            );
+        }
+        if name.starts_with('<') {
+            return Err("Character IDs can't be mentions.".to_owned());
+        }
+        if name.eq_ignore_ascii_case(POT) {
+            return Err(format!("`{POT}` is reserved for the party pot."));
+        }
+        Ok(name)
+    }
+}
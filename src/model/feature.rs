@@ -0,0 +1,12 @@
+//! Features: parts of Archmage that DMs may turn off in their campaigns, such
+//! as inventories. Commands name the feature they belong to when they're
+//! registered, and are refused in campaigns that have turned it off. Any
+//! feature a registered command names can be turned on and off, so new ones
+//! only need a name here.
+
+/// Character inventories, the party pot, and giving items.
+pub const INVENTORY: &str = "inventory";
+/// Quest logs.
+pub const QUESTS: &str = "quests";
+/// Character info posts and XP.
+pub const CHARACTERS: &str = "characters";
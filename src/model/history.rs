@@ -0,0 +1,79 @@
+//! Campaign history: every change made to inventories, party pots, XP, level
+//! tables, quests and character info, kept so that it can be looked back on
+//! and, by a DM, undone. Undoing a change is itself a change, so it too can be
+//! undone.
+use crate::model::inventory::Owner;
+use crate::model::quest::Field;
+
+/// The state of an objective that's been ticked off.
+pub const DONE: &str = "done";
+/// The state of an objective that hasn't.
+pub const OPEN: &str = "open";
+
+/// Something an edit changed. Each has a state, written as text, which edits
+/// record before and after; None means it wasn't there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// How many of an item an inventory has, e.g. `3`, or `0` for none.
+    Item { owner: Owner, item: String },
+    /// How much XP a character has.
+    Xp { character_id: i64 },
+    /// What XP added to a character is multiplied by.
+    Multiplier { character_id: i64 },
+    /// How much XP a level of a campaign's table takes.
+    Level { campaign_id: i64, level: i64 },
+    /// The value of a tag in a character's info post.
+    Info { character_id: i64, tag: String },
+    /// A quest, as its title.
+    Quest { quest_id: i64, number: i64 },
+    /// A part of a quest other than its title, which may be cleared.
+    QuestField { quest_id: i64, field: Field },
+    /// A quest's status, as stored.
+    Status { quest_id: i64 },
+    /// An objective, as [DONE] or [OPEN]. Objectives are told apart by their
+    /// ID, which keeps their place in the checklist if they're put back.
+    Objective {
+        quest_id: i64,
+        objective_id: i64,
+        text: String,
+    },
+}
+
+/// A change to one thing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    /// What was changed, as it was called at the time, e.g. `Cal` or
+    /// `Quest #3`. It may since have been deleted.
+    pub subject: String,
+    pub target: Target,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl Edit {
+    /// The edit that takes this one back.
+    pub fn reverse(&self) -> Edit {
+        Edit {
+            subject: self.subject.clone(),
+            target: self.target.clone(),
+            before: self.after.clone(),
+            after: self.before.clone(),
+        }
+    }
+}
+
+/// Everything one command changed, all at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// What DMs call the event, e.g. 42 for `/undo 42`.
+    pub id: i64,
+    pub user_id: u64,
+    /// When, in seconds since the Unix epoch.
+    pub created_at: i64,
+    /// The event this one undid, if it's an undo.
+    pub undoes: Option<i64>,
+    /// The event that undid this one, if it's been undone.
+    pub undone_by: Option<i64>,
+    /// In the order they were made.
+    pub edits: Vec<Edit>,
+}
@@ -0,0 +1,62 @@
+//! Character info posts: tagged facts about a character, such as their full
+//! name or alignment, shown together as a post.
+
+/// Tags are headings, so are kept short.
+pub const MAX_TAG_LENGTH: usize = 64;
+/// Values must fit in a single embed field.
+pub const MAX_VALUE_LENGTH: usize = 1024;
+/// Links ending in these are shown as images.
+const IMAGE_EXTENSIONS: [&str; 5] = [".png", ".jpg", ".jpeg", ".gif", ".webp"];
+
+/// One entry in a character's post, such as `Alignment: LN`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Info {
+    /// What the value is. Unique per character, ignoring case.
+    pub tag: String,
+    /// May be empty; empty tags are kept until they are removed.
+    pub value: String,
+}
+
+impl Info {
+    /// The value, if it is nothing but a link to an image.
+    pub fn image(&self) -> Option<&str> {
+        let value = self.value.trim();
+        if value.contains(char::is_whitespace) {
+            return None;
+        }
+        let rest = value
+            .strip_prefix("https://")
+            .or_else(|| value.strip_prefix("http://"))?;
+        let path = rest.split(['?', '#']).next()?.to_ascii_lowercase();
+        IMAGE_EXTENSIONS
+            .iter()
+            .any(|extension| path.ends_with(extension))
+            .then_some(value)
+    }
+}
+
+/// Tidy up a tag, collapsing runs of whitespace, or explain why it can't be
+/// used.
+pub fn clean_tag(tag: &str) -> Result<String, String> {
+    let tag = tag.split_whitespace().collect::<Vec<_>>().join(" ");
+    if tag.is_empty() {
+        return Err("Which tag?".to_owned());
+    }
+    if tag.chars().count() > MAX_TAG_LENGTH {
+        return Err(format!(
+            "Tags may be at most {MAX_TAG_LENGTH} characters long."
+        ));
+    }
+    Ok(tag)
+}
+
+/// Check a value, explaining why it can't be used if so.
+pub fn check_value<'a>(tag: &str, value: &'a str) -> Result<&'a str, String> {
+    let value = value.trim();
+    if value.chars().count() > MAX_VALUE_LENGTH {
+        return Err(format!(
+            "**{tag}** is too long: values may be at most {MAX_VALUE_LENGTH} characters."
+        ));
+    }
+    Ok(value)
+}
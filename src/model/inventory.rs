@@ -0,0 +1,175 @@
+//! Inventories: how many of each item a character, or a campaign's party pot,
+//! has. Items are matched by name, ignoring case, and an item nobody has any
+//! of isn't listed at all.
+use std::fmt;
+
+/// Nobody needs more than this many of anything, and it keeps sums well
+/// clear of overflowing.
+pub const MAX_QUANTITY: i64 = 1_000_000_000_000;
+/// Item names longer than this are more likely mistakes than names.
+pub const MAX_ITEM_LENGTH: usize = 100;
+
+/// Whose inventory it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Owner {
+    /// A character's own inventory, by the character's row ID.
+    Character(i64),
+    /// The party pot shared by everyone in a campaign, by the campaign's ID.
+    Pot(i64),
+}
+
+/// Who changed the party pot, for its ledger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contributor {
+    pub user_id: u64,
+    /// The character's ID, or None if a DM changed the pot themselves.
+    pub character: Option<String>,
+}
+
+/// A change to the party pot, as recorded in its ledger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contribution {
+    pub contributor: Contributor,
+    pub item: String,
+    /// How many were put in, or taken out if negative.
+    pub amount: i64,
+    /// When, in seconds since the Unix epoch.
+    pub created_at: i64,
+}
+
+/// Some number of a single item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stack {
+    pub item: String,
+    pub quantity: i64,
+}
+
+/// A change to one item in an inventory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// Add some of the item.
+    Add(i64),
+    /// Remove some of the item, or the whole stack if no amount is given.
+    Remove(Option<i64>),
+    /// Have exactly this many of the item.
+    Set(i64),
+}
+
+/// The result of a change to one item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Changed {
+    /// The item's name, as it was first written.
+    pub item: String,
+    pub before: i64,
+    pub after: i64,
+}
+
+/// Why a change couldn't be made. Changes are made all-or-nothing, so
+/// nothing else in the same batch was changed either.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeError {
+    /// Removing more of an item than there is.
+    NotEnough {
+        item: String,
+        have: i64,
+        wanted: i64,
+    },
+    /// Ending up with more of an item than [MAX_QUANTITY].
+    TooMany { item: String },
+}
+
+impl fmt::Display for ChangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotEnough { item, have: 0, .. } => write!(f, "There isn't any {item}."),
+            Self::NotEnough { item, have, wanted } => {
+                write!(f, "There's only {have} {item}, not {wanted}.")
+            }
+            Self::TooMany { item } => {
+                write!(f, "That would be more than {MAX_QUANTITY} {item}.")
+            }
+        }
+    }
+}
+
+impl Change {
+    /// The quantity after the change, given the quantity before it.
+    pub fn apply(self, item: &str, have: i64) -> Result<i64, ChangeError> {
+        let after = match self {
+            Self::Add(amount) => have + amount,
+            Self::Remove(None) if have > 0 => 0,
+            Self::Remove(amount) => {
+                let wanted = amount.unwrap_or(1);
+                if wanted > have {
+                    return Err(ChangeError::NotEnough {
+                        item: item.to_owned(),
+                        have,
+                        wanted,
+                    });
+                }
+                have - wanted
+            }
+            Self::Set(amount) => amount,
+        };
+        match after > MAX_QUANTITY {
+            true => Err(ChangeError::TooMany {
+                item: item.to_owned(),
+            }),
+            false => Ok(after),
+        }
+    }
+}
+
+/// Tidy up an item name, collapsing runs of whitespace, or explain why it
+/// can't be used.
+pub fn clean_item(name: &str) -> Result<String, String> {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        return Err("Which item?".to_owned());
+    }
+    if name.chars().count() > MAX_ITEM_LENGTH {
+        return Err(format!(
+            "Item names may be at most {MAX_ITEM_LENGTH} characters long."
+        ));
+    }
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_and_sets() {
+        assert_eq!(Change::Add(3).apply("Gold", 2), Ok(5));
+        assert_eq!(Change::Set(0).apply("Gold", 2), Ok(0));
+        assert_eq!(Change::Set(7).apply("Gold", 0), Ok(7));
+    }
+
+    #[test]
+    fn removes() {
+        assert_eq!(Change::Remove(Some(2)).apply("Gold", 5), Ok(3));
+        assert_eq!(Change::Remove(None).apply("Gold", 5), Ok(0));
+        assert_eq!(
+            Change::Remove(Some(6)).apply("Gold", 5),
+            Err(ChangeError::NotEnough {
+                item: "Gold".to_owned(),
+                have: 5,
+                wanted: 6,
+            })
+        );
+        let error = Change::Remove(None).apply("Gold", 0).unwrap_err();
+        assert_eq!(error.to_string(), "There isn't any Gold.");
+    }
+
+    #[test]
+    fn caps_quantities() {
+        assert_eq!(
+            Change::Add(1).apply("Gold", MAX_QUANTITY),
+            Err(ChangeError::TooMany {
+                item: "Gold".to_owned()
+            })
+        );
+        assert_eq!(Change::Set(MAX_QUANTITY).apply("Gold", 0), Ok(MAX_QUANTITY));
+    }
+}
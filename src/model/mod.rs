@@ -0,0 +1,10 @@
+//! The things Archmage keeps track of for each server's games.
+pub mod campaign;
+pub mod character;
+pub mod feature;
+pub mod history;
+pub mod info;
+pub mod inventory;
+pub mod monitor;
+pub mod quest;
+pub mod xp;
@@ -0,0 +1,52 @@
+//! Monitors: messages Archmage keeps up to date in a channel, so that a
+//! campaign's inventories, quests or characters can be seen at a glance
+//! without asking for them.
+use super::feature;
+
+/// What a monitor shows. Each campaign may monitor each kind in one channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    /// The party pot and every character's inventory.
+    Inventory,
+    /// Active quests.
+    Quests,
+    /// Every character's level, XP and players.
+    Characters,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 3] = [Kind::Inventory, Kind::Quests, Kind::Characters];
+
+    /// How the kind is stored, and named in commands.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Inventory => "inventory",
+            Kind::Quests => "quests",
+            Kind::Characters => "characters",
+        }
+    }
+
+    /// The feature whose commands change what the kind shows. Monitors of a
+    /// feature that's turned off are left as they were.
+    pub fn feature(self) -> &'static str {
+        match self {
+            Kind::Inventory => feature::INVENTORY,
+            Kind::Quests => feature::QUESTS,
+            Kind::Characters => feature::CHARACTERS,
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Kind> {
+        Kind::ALL
+            .into_iter()
+            .find(|k| k.as_str().eq_ignore_ascii_case(kind))
+    }
+}
+
+/// A campaign's monitor of one kind, and the messages it has sent so far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Monitor {
+    pub channel_id: u64,
+    /// In the order they're shown. Long monitors take several messages.
+    pub message_ids: Vec<u64>,
+}
@@ -0,0 +1,181 @@
+//! Quest logs. Each campaign keeps its own, with quests numbered in the order
+//! they were added so that they're quick to name in commands.
+
+/// Titles are shown as embed titles, alongside the quest's number.
+pub const MAX_TITLE_LENGTH: usize = 200;
+/// Descriptions share an embed's description with the objectives.
+pub const MAX_DESCRIPTION_LENGTH: usize = 2000;
+/// Givers and rewards must fit in a single embed field.
+pub const MAX_FIELD_LENGTH: usize = 1024;
+/// Objectives are listed a line each under the description.
+pub const MAX_OBJECTIVE_LENGTH: usize = 100;
+/// Enough for any reasonable checklist, while still fitting in one embed.
+pub const MAX_OBJECTIVES: usize = 20;
+
+/// Where a quest stands. Quests start out active.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Active,
+    Completed,
+    Failed,
+    Abandoned,
+}
+
+impl Status {
+    pub const ALL: [Status; 4] = [
+        Status::Active,
+        Status::Completed,
+        Status::Failed,
+        Status::Abandoned,
+    ];
+
+    /// How the status is stored, and named in commands.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Active => "active",
+            Status::Completed => "completed",
+            Status::Failed => "failed",
+            Status::Abandoned => "abandoned",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<Status> {
+        Status::ALL
+            .into_iter()
+            .find(|s| s.as_str().eq_ignore_ascii_case(status))
+    }
+
+    /// How the status is shown to users.
+    pub fn label(self) -> &'static str {
+        match self {
+            Status::Active => "Active",
+            Status::Completed => "Completed",
+            Status::Failed => "Failed",
+            Status::Abandoned => "Abandoned",
+        }
+    }
+}
+
+/// A step towards finishing a quest, ticked off once done.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Objective {
+    pub text: String,
+    pub done: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quest {
+    pub id: i64,
+    pub campaign_id: i64,
+    /// What players call the quest, e.g. 3 for `!quest show 3`. Unique within
+    /// the campaign, and never reused.
+    pub number: i64,
+    pub title: String,
+    pub description: Option<String>,
+    /// Who gave the party the quest, as free text.
+    pub giver: Option<String>,
+    pub reward: Option<String>,
+    pub status: Status,
+    /// The user who added the quest. Quests outlive their characters.
+    pub added_by: u64,
+    /// In the order they were added.
+    pub objectives: Vec<Objective>,
+}
+
+impl Quest {
+    /// How many objectives are done, out of how many.
+    pub fn progress(&self) -> (usize, usize) {
+        let done = self.objectives.iter().filter(|o| o.done).count();
+        (done, self.objectives.len())
+    }
+}
+
+/// The parts of a quest that can be edited after it's added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Title,
+    Description,
+    Giver,
+    Reward,
+}
+
+impl Field {
+    pub const ALL: [Field; 4] = [
+        Field::Title,
+        Field::Description,
+        Field::Giver,
+        Field::Reward,
+    ];
+
+    /// The column the field is stored in, and its name in commands.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Field::Title => "title",
+            Field::Description => "description",
+            Field::Giver => "giver",
+            Field::Reward => "reward",
+        }
+    }
+
+    pub fn parse(field: &str) -> Option<Field> {
+        Field::ALL
+            .into_iter()
+            .find(|f| f.as_str().eq_ignore_ascii_case(field))
+    }
+
+    /// Check a new value for the field, explaining why it can't be used if
+    /// so. Fields other than the title may be cleared by leaving the value
+    /// empty, which gives None.
+    pub fn check(self, value: &str) -> Result<Option<String>, String> {
+        let value = value.trim();
+        let limit = match self {
+            Field::Title => return check_title(value).map(Some),
+            Field::Description => MAX_DESCRIPTION_LENGTH,
+            Field::Giver | Field::Reward => MAX_FIELD_LENGTH,
+        };
+        if value.chars().count() > limit {
+            return Err(format!(
+                "Quest {}s may be at most {limit} characters long.",
+                self.as_str()
+            ));
+        }
+        Ok((!value.is_empty()).then(|| value.to_owned()))
+    }
+}
+
+/// Check a proposed title, explaining why it can't be used if so. Titles are
+/// a single line.
+pub fn check_title(title: &str) -> Result<String, String> {
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    if title.is_empty() {
+        return Err("Quests need a title.".to_owned());
+    }
+    if title.chars().count() > MAX_TITLE_LENGTH {
+        return Err(format!(
+            "Quest titles may be at most {MAX_TITLE_LENGTH} characters long."
+        ));
+    }
+    Ok(title)
+}
+
+/// Check a proposed objective, explaining why it can't be used if so.
+pub fn check_objective(objective: &str) -> Result<String, String> {
+    let objective = objective.split_whitespace().collect::<Vec<_>>().join(" ");
+    if objective.is_empty() {
+        return Err("Which objective?".to_owned());
+    }
+    if objective.chars().count() > MAX_OBJECTIVE_LENGTH {
+        return Err(format!(
+            "Objectives may be at most {MAX_OBJECTIVE_LENGTH} characters long."
+        ));
+    }
+    Ok(objective)
+}
+
+/// Which quests to list. Each filter left as None lets everything through.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuestFilter {
+    pub status: Option<Status>,
+    /// Part of the giver's name, ignoring case.
+    pub giver: Option<String>,
+}
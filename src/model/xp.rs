@@ -0,0 +1,213 @@
+//! Experience points, and the levels they earn. Each campaign has its own
+//! table of how much XP each level takes, starting from the D&D 3.5e SRD's.
+
+/// Nobody needs more XP than this, and it keeps sums well clear of
+/// overflowing.
+pub const MAX_XP: i64 = 1_000_000_000_000;
+/// Multipliers above this are more likely mistakes than house rules.
+pub const MAX_MULTIPLIER: f64 = 100.0;
+/// The tag in a character's info post that shows their level, kept up to
+/// date as their XP changes.
+pub const LEVEL_TAG: &str = "Level";
+
+/// The XP needed to reach a level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Threshold {
+    pub level: i64,
+    pub xp: i64,
+}
+
+/// A character's XP, and what XP they're given is multiplied by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Experience {
+    pub xp: i64,
+    pub multiplier: f64,
+}
+
+/// A change to a character's XP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XpChange {
+    /// Give the character XP, multiplied by their multiplier.
+    Add(i64),
+    /// Take XP away from the character.
+    Remove(i64),
+    /// Give the character exactly this much XP.
+    Set(i64),
+}
+
+impl XpChange {
+    /// The XP after the change, given the XP before it, or why it can't be
+    /// made.
+    pub fn apply(self, experience: Experience) -> Result<i64, String> {
+        let (Self::Add(amount) | Self::Remove(amount) | Self::Set(amount)) = self;
+        if amount > MAX_XP {
+            return Err(format!("{amount} XP is more than anyone could need."));
+        }
+        let after = match self {
+            Self::Add(amount) => {
+                experience.xp + (amount as f64 * experience.multiplier).round() as i64
+            }
+            Self::Remove(amount) if amount > experience.xp => {
+                return Err(format!(
+                    "There's only {} XP to remove, not {amount}.",
+                    experience.xp
+                ))
+            }
+            Self::Remove(amount) => experience.xp - amount,
+            Self::Set(amount) => amount,
+        };
+        match after > MAX_XP {
+            true => Err(format!("That would be more than {MAX_XP} XP.")),
+            false => Ok(after),
+        }
+    }
+}
+
+/// Check a proposed multiplier, explaining why it can't be used if so.
+pub fn check_multiplier(multiplier: f64) -> Result<f64, String> {
+    match multiplier.is_finite() && multiplier > 0.0 && multiplier <= MAX_MULTIPLIER {
+        true => Ok(multiplier),
+        false => Err(format!(
+            "XP multipliers must be more than 0 and at most {MAX_MULTIPLIER}. For no \
+             multiplier, use 1."
+        )),
+    }
+}
+
+/// The level a character with some XP has reached, given a campaign's
+/// thresholds in order of level. Characters who haven't reached any are level
+/// 0.
+pub fn level(thresholds: &[Threshold], xp: i64) -> i64 {
+    thresholds
+        .iter()
+        .filter(|t| t.xp <= xp)
+        .map(|t| t.level)
+        .max()
+        .unwrap_or(0)
+}
+
+/// The next threshold a character with some XP has yet to reach, if any.
+pub fn next(thresholds: &[Threshold], xp: i64) -> Option<Threshold> {
+    thresholds.iter().find(|t| t.xp > xp).copied()
+}
+
+/// Check that a level can take a certain amount of XP without needing less
+/// than a lower level, or more than a higher one.
+pub fn check_threshold(thresholds: &[Threshold], proposed: Threshold) -> Result<(), String> {
+    if proposed.level < 1 {
+        return Err("Levels start at 1.".to_owned());
+    }
+    if !(0..=MAX_XP).contains(&proposed.xp) {
+        return Err(format!("Levels must take between 0 and {MAX_XP} XP."));
+    }
+    for threshold in thresholds {
+        let out_of_order = match threshold.level.cmp(&proposed.level) {
+            std::cmp::Ordering::Less => threshold.xp >= proposed.xp,
+            std::cmp::Ordering::Greater => threshold.xp <= proposed.xp,
+            std::cmp::Ordering::Equal => false,
+        };
+        if out_of_order {
+            return Err(format!(
+                "Level {} takes {} XP, so level {} can't take {}. Each level must take more XP \
+                 than the one before.",
+                threshold.level, threshold.xp, proposed.level, proposed.xp
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn experience(xp: i64, multiplier: f64) -> Experience {
+        Experience { xp, multiplier }
+    }
+
+    fn table() -> Vec<Threshold> {
+        [(1, 0), (2, 1000), (3, 3000)]
+            .into_iter()
+            .map(|(level, xp)| Threshold { level, xp })
+            .collect()
+    }
+
+    #[test]
+    fn multiplies_added_xp() {
+        assert_eq!(XpChange::Add(100).apply(experience(0, 1.02)), Ok(102));
+        // Rounded to the nearest point.
+        assert_eq!(XpChange::Add(25).apply(experience(0, 1.5)), Ok(38));
+        assert_eq!(XpChange::Add(33).apply(experience(10, 1.01)), Ok(43));
+        assert_eq!(XpChange::Add(1).apply(experience(0, 0.4)), Ok(0));
+        // Only added XP is multiplied.
+        assert_eq!(XpChange::Remove(10).apply(experience(100, 2.0)), Ok(90));
+        assert_eq!(XpChange::Set(10).apply(experience(100, 2.0)), Ok(10));
+    }
+
+    #[test]
+    fn cant_remove_past_zero() {
+        assert_eq!(XpChange::Remove(100).apply(experience(100, 1.0)), Ok(0));
+        assert_eq!(
+            XpChange::Remove(101).apply(experience(100, 1.0)),
+            Err("There's only 100 XP to remove, not 101.".to_owned())
+        );
+    }
+
+    #[test]
+    fn bounded_by_max_xp() {
+        assert_eq!(XpChange::Set(MAX_XP).apply(experience(0, 1.0)), Ok(MAX_XP));
+        assert!(XpChange::Set(MAX_XP + 1).apply(experience(0, 1.0)).is_err());
+        assert!(XpChange::Add(1).apply(experience(MAX_XP, 1.0)).is_err());
+        // The multiplier can't take it over either.
+        assert!(XpChange::Add(MAX_XP).apply(experience(0, 2.0)).is_err());
+    }
+
+    #[test]
+    fn checks_multipliers() {
+        assert_eq!(check_multiplier(1.0), Ok(1.0));
+        assert_eq!(check_multiplier(MAX_MULTIPLIER), Ok(MAX_MULTIPLIER));
+        for bad in [0.0, -1.0, MAX_MULTIPLIER + 0.01, f64::NAN, f64::INFINITY] {
+            assert!(check_multiplier(bad).is_err(), "{bad} was allowed");
+        }
+    }
+
+    #[test]
+    fn levels_and_next() {
+        let table = table();
+        assert_eq!(level(&table, 0), 1);
+        assert_eq!(level(&table, 999), 1);
+        assert_eq!(level(&table, 1000), 2);
+        assert_eq!(level(&table, MAX_XP), 3);
+        assert_eq!(level(&[], 5000), 0);
+        assert_eq!(level(&table[1..], 0), 0);
+
+        assert_eq!(next(&table, 0), Some(table[1]));
+        assert_eq!(next(&table, 1000), Some(table[2]));
+        assert_eq!(next(&table, 3000), None);
+    }
+
+    #[test]
+    fn checks_thresholds() {
+        let table = table();
+        let threshold = |level, xp| Threshold { level, xp };
+        assert_eq!(check_threshold(&table, threshold(2, 1500)), Ok(()));
+        assert_eq!(check_threshold(&table, threshold(4, 6000)), Ok(()));
+        // Changing a level to what it already takes is fine.
+        assert_eq!(check_threshold(&table, threshold(2, 1000)), Ok(()));
+
+        assert!(check_threshold(&table, threshold(0, 0)).is_err());
+        assert!(check_threshold(&table, threshold(5, -1)).is_err());
+        assert!(check_threshold(&table, threshold(5, MAX_XP + 1)).is_err());
+        // Out of order, or tied with a neighbour.
+        assert_eq!(
+            check_threshold(&table, threshold(2, 3000)),
+            Err(
+                "Level 3 takes 3000 XP, so level 2 can't take 3000. Each level must take more \
+                 XP than the one before."
+                    .to_owned()
+            )
+        );
+        assert!(check_threshold(&table, threshold(2, 0)).is_err());
+        assert!(check_threshold(&table, threshold(4, 2000)).is_err());
+    }
+}
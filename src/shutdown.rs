@@ -0,0 +1,90 @@
+//! Stopping Archmage without cutting anyone off mid-command.
+use std::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use tokio::sync::Notify;
+use tracing::{event, Level};
+
+/// Keeps track of the events being handled, so that shutdown can wait for
+/// them to finish, and turns new ones away once shutdown has begun.
+#[derive(Debug, Default)]
+pub struct InFlight {
+    count: AtomicUsize,
+    stopping: AtomicBool,
+    idle: Notify,
+}
+
+impl InFlight {
+    /// Start handling an event. Returns None if Archmage is shutting down,
+    /// in which case the event should be turned away. Otherwise, shutdown
+    /// waits until the returned guard is dropped.
+    pub fn enter(&self) -> Option<InFlightGuard<'_>> {
+        let _ = self.count.fetch_add(1, Ordering::SeqCst);
+        let guard = InFlightGuard(self);
+        // Checked after counting ourselves, so shutdown can't miss us.
+        match self.stopping.load(Ordering::SeqCst) {
+            true => None,
+            false => Some(guard),
+        }
+    }
+
+    /// Turn away new events, then wait up to `grace` for those already being
+    /// handled to finish. Returns how many were still running at the end.
+    pub async fn drain(&self, grace: Duration) -> usize {
+        self.stopping.store(true, Ordering::SeqCst);
+        let idle = async {
+            loop {
+                // Created before checking, so a wakeup in between isn't lost.
+                let notified = self.idle.notified();
+                if self.count.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+        let _ = tokio::time::timeout(grace, idle).await;
+        self.count.load(Ordering::SeqCst)
+    }
+}
+
+/// Marks an event as being handled until dropped.
+#[derive(Debug)]
+pub struct InFlightGuard<'a>(&'a InFlight);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+/// Wait for Ctrl-C or, on Unix, `SIGTERM`.
+pub async fn signalled() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => event!(
+                Level::WARN,
+                "Unable to listen for SIGTERM, only Ctrl-C will stop Archmage cleanly: {}",
+                e
+            ),
+        }
+    }
+
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        event!(Level::WARN, "Unable to listen for Ctrl-C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}